@@ -77,7 +77,7 @@ fn main() {
     let psurvival = value_t!(matches.value_of("psurvival"), f64).unwrap_or(0.0);
     let seed = value_t_or_exit!(matches.value_of("seed"), usize);
     let outfile = value_t_or_exit!(matches.value_of("outfile"), String);
-    let validate_tables =  matches.is_present("validate_tables");
+    let validate_tables = matches.is_present("validate_tables");
 
     // TODO: parameter validation..
 
@@ -101,7 +101,7 @@ fn main() {
         simplification_flags |= SimplificationFlags::VALIDATE_ALL;
     }
 
-    let (mut tables, is_sample) = neutral_wf(
+    let tables = neutral_wf(
         PopulationParams {
             size: popsize,
             genome_length: 10000000,
@@ -118,12 +118,11 @@ fn main() {
     )
     .unwrap();
 
-    let mut tskit_tables = forrustts::tskit::convert_to_tskit_and_drain_minimal(
-        &is_sample,
-        forrustts::tskit::simple_time_reverser(g),
-        simplify.is_some(),
-        &mut tables,
-    );
+    let options = forrustts::tskit::TskitExportOptions::new()
+        .convert_time(forrustts::tskit::simple_time_reverser(g))
+        .build_indexes(simplify.is_some())
+        .include_provenance(true);
+    let mut tskit_tables = forrustts::tskit::export_to_tskit_from(tables, &options).unwrap();
 
     if simplify.is_some() {
         tskit_tables.dump(&outfile, 0).unwrap();
@@ -0,0 +1,564 @@
+//! Local ancestry and identity-by-descent (IBD) segments.
+//!
+//! Both calculations trace the genomes of sample nodes back
+//! through the edge table, in the same way as
+//! [``simplify_tables``](crate::simplify_tables).
+//! Each [``Segment``] records a genomic interval of a sample
+//! that is inherited by a node.
+//! The edge table does not have to be sorted.
+//!
+//! # Local ancestry
+//!
+//! [``local_ancestry``] reports, for each sample, the intervals
+//! of its genome that are inherited from each of a list of
+//! ancestral nodes.
+//! If a sample inherits an interval from more than one of
+//! the ancestors, the most recent one is reported.
+//!
+//! # IBD
+//!
+//! Two samples are IBD over an interval that they inherit
+//! from the same most recent common ancestor (MRCA).
+//! [``ibd_segments``] reports these intervals for all pairs
+//! of samples.
+
+use crate::error::ForrusttsError;
+use crate::segment::Segment;
+use crate::simplification_logic::AncestryList;
+use crate::tables::{TableCollection, TablesError};
+use crate::tsdef::{IdType, Position};
+
+/// An interval of a sample's genome inherited from an ancestor.
+///
+/// See [``local_ancestry``].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LocalAncestrySegment {
+    /// The sample node
+    pub sample: IdType,
+    /// Left edge of the interval
+    pub left: Position,
+    /// Right edge (exclusive) of the interval
+    pub right: Position,
+    /// The ancestral node
+    pub ancestor: IdType,
+    /// The deme of the ancestral node
+    pub deme: IdType,
+}
+
+/// An interval that two samples inherit from the same MRCA.
+///
+/// See [``ibd_segments``].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IbdSegment {
+    /// The samples, with the smaller ``ID`` first
+    pub samples: (IdType, IdType),
+    /// Left edge of the interval
+    pub left: Position,
+    /// Right edge (exclusive) of the interval
+    pub right: Position,
+    /// The most recent common ancestor
+    pub mrca: IdType,
+}
+
+fn validate_nodes(tables: &TableCollection, nodes: &[IdType]) -> Result<(), ForrusttsError> {
+    let mut seen = vec![false; tables.num_nodes()];
+    for &u in nodes {
+        if u < 0 || u as usize >= tables.num_nodes() {
+            return Err(TablesError::NodeOutOfBounds.into());
+        }
+        if seen[u as usize] {
+            return Err(TablesError::InvalidNodeValue { found: u }.into());
+        }
+        seen[u as usize] = true;
+    }
+    Ok(())
+}
+
+/// Edge indexes grouped by parent, with the
+/// most recent parents first.
+fn edges_by_parent(tables: &TableCollection) -> Result<Vec<usize>, ForrusttsError> {
+    let edges = tables.edges();
+    let num_nodes = tables.num_nodes() as IdType;
+    if edges
+        .iter()
+        .any(|e| e.parent < 0 || e.parent >= num_nodes || e.child < 0 || e.child >= num_nodes)
+    {
+        return Err(TablesError::NodeOutOfBounds.into());
+    }
+    let mut order: Vec<usize> = (0..edges.len()).collect();
+    order.sort_by(|&i, &j| {
+        let (a, b) = (&edges[i], &edges[j]);
+        tables
+            .node(b.parent)
+            .time
+            .cmp(&tables.node(a.parent).time)
+            .then(a.parent.cmp(&b.parent))
+            .then(a.child.cmp(&b.child))
+            .then(a.left.cmp(&b.left))
+    });
+    Ok(order)
+}
+
+/// Trace the samples' genomes back through the edges.
+///
+/// The samples must have been validated.
+///
+/// For each parent, `visit` is called with the parent
+/// and with the segments that it inherits from its children.
+/// Each segment is paired with the child it came from.
+/// If `visit` returns `false`, the segments are not
+/// passed on to the parent's ancestors.
+/// Otherwise, adjacent segments of the same sample are
+/// merged before they are passed on.
+fn trace_ancestry<F>(
+    tables: &TableCollection,
+    samples: &[IdType],
+    mut visit: F,
+) -> Result<(), ForrusttsError>
+where
+    F: FnMut(IdType, &[(IdType, Segment)]) -> bool,
+{
+    let order = edges_by_parent(tables)?;
+    let edges = tables.edges();
+
+    let mut ancestry = AncestryList::new();
+    ancestry.reset(tables.num_nodes());
+    for &s in samples {
+        ancestry.extend(s, Segment::new(0, tables.genome_length(), s))?;
+    }
+
+    let mut incoming = vec![];
+    let mut i = 0;
+    while i < order.len() {
+        let parent = edges[order[i]].parent;
+        incoming.clear();
+        while i < order.len() && edges[order[i]].parent == parent {
+            let edge = &edges[order[i]];
+            ancestry.for_each(edge.child, |seg: &Segment| {
+                if seg.right > edge.left && edge.right > seg.left {
+                    incoming.push((
+                        edge.child,
+                        Segment::new(
+                            std::cmp::max(seg.left, edge.left),
+                            std::cmp::min(seg.right, edge.right),
+                            seg.node,
+                        ),
+                    ));
+                }
+                true
+            })?;
+            i += 1;
+        }
+        if visit(parent, &incoming) {
+            incoming.sort_by_key(|(_, seg)| (seg.node, seg.left));
+            let mut last: Option<Segment> = None;
+            for &(_, seg) in incoming.iter() {
+                match last.as_mut() {
+                    Some(l) if l.node == seg.node && l.right == seg.left => l.right = seg.right,
+                    _ => {
+                        if let Some(l) = last {
+                            ancestry.extend(parent, l)?;
+                        }
+                        last = Some(seg);
+                    }
+                }
+            }
+            if let Some(l) = last {
+                ancestry.extend(parent, l)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Find the intervals of each sample's genome inherited
+/// from a list of ancestral nodes.
+///
+/// # Parameters
+///
+/// * `tables`: the tables
+/// * `samples`: the sample nodes
+/// * `ancestors`: the ancestral nodes
+///
+/// # Returns
+///
+/// The segments, sorted by sample and then by position.
+/// Adjacent intervals inherited from the same ancestor are
+/// merged.
+/// Intervals inherited from none of `ancestors` are not
+/// reported.
+/// A sample that is also an ancestor inherits its whole
+/// genome from itself.
+///
+/// # Errors
+///
+/// [``TablesError::NodeOutOfBounds``] if a sample, ancestor,
+/// or edge refers to an invalid node, and
+/// [``TablesError::InvalidNodeValue``] if `samples`
+/// or `ancestors` contains the same node twice.
+///
+/// # Example
+///
+/// ```
+/// use forrustts::ancestry::local_ancestry;
+/// use forrustts::TableCollection;
+///
+/// let mut tables = TableCollection::new(100).unwrap();
+/// // Two founders, from demes 0 and 1
+/// tables.add_node(0, 0).unwrap();
+/// tables.add_node(0, 1).unwrap();
+/// // An admixed sample
+/// tables.add_node(1, 0).unwrap();
+/// tables.add_edge(0, 40, 0, 2).unwrap();
+/// tables.add_edge(40, 100, 1, 2).unwrap();
+///
+/// let segments = local_ancestry(&tables, &[2], &[0, 1]).unwrap();
+/// assert_eq!(segments.len(), 2);
+/// assert_eq!((segments[0].left, segments[0].right), (0, 40));
+/// assert_eq!(segments[0].deme, 0);
+/// assert_eq!((segments[1].left, segments[1].right), (40, 100));
+/// assert_eq!(segments[1].deme, 1);
+/// ```
+pub fn local_ancestry(
+    tables: &TableCollection,
+    samples: &[IdType],
+    ancestors: &[IdType],
+) -> Result<Vec<LocalAncestrySegment>, ForrusttsError> {
+    validate_nodes(tables, samples)?;
+    validate_nodes(tables, ancestors)?;
+    let mut is_ancestor = vec![false; tables.num_nodes()];
+    for &a in ancestors {
+        is_ancestor[a as usize] = true;
+    }
+    let make_segment = |sample: IdType, left, right, ancestor: IdType| LocalAncestrySegment {
+        sample,
+        left,
+        right,
+        ancestor,
+        deme: tables.node(ancestor).deme,
+    };
+
+    let mut segments = vec![];
+    let mut traced = vec![];
+    for &s in samples {
+        if is_ancestor[s as usize] {
+            segments.push(make_segment(s, 0, tables.genome_length(), s));
+        } else {
+            traced.push(s);
+        }
+    }
+    trace_ancestry(tables, &traced, |parent, incoming| {
+        if !is_ancestor[parent as usize] {
+            return true;
+        }
+        for (_, seg) in incoming {
+            segments.push(make_segment(seg.node, seg.left, seg.right, parent));
+        }
+        false
+    })?;
+
+    segments.sort_by(|a, b| a.sample.cmp(&b.sample).then(a.left.cmp(&b.left)));
+    let mut rv: Vec<LocalAncestrySegment> = vec![];
+    for seg in segments {
+        match rv.last_mut() {
+            Some(last)
+                if last.sample == seg.sample
+                    && last.ancestor == seg.ancestor
+                    && last.right == seg.left =>
+            {
+                last.right = seg.right
+            }
+            _ => rv.push(seg),
+        }
+    }
+    Ok(rv)
+}
+
+/// Find the intervals that pairs of samples inherit from
+/// the same most recent common ancestor.
+///
+/// # Parameters
+///
+/// * `tables`: the tables
+/// * `samples`: the sample nodes
+/// * `min_length`: only segments longer than this are returned
+///
+/// # Returns
+///
+/// The segments, sorted by pair of samples and then by position.
+/// Adjacent intervals with the same MRCA are merged.
+/// Pairs with no common ancestor in an interval are not IBD
+/// in that interval.
+/// A sample that is an ancestor of another sample is the MRCA
+/// of the pair.
+///
+/// # Errors
+///
+/// [``TablesError::NodeOutOfBounds``] if a sample or edge refers
+/// to an invalid node, and [``TablesError::InvalidNodeValue``]
+/// if `samples` contains the same node twice.
+///
+/// # Example
+///
+/// ```
+/// use forrustts::ancestry::ibd_segments;
+/// use forrustts::TableCollection;
+///
+/// let mut tables = TableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap();
+/// tables.add_node(0, 0).unwrap();
+/// tables.add_node(1, 0).unwrap();
+/// tables.add_node(1, 0).unwrap();
+/// tables.add_edge(0, 100, 0, 2).unwrap();
+/// tables.add_edge(0, 30, 0, 3).unwrap();
+/// tables.add_edge(30, 100, 1, 3).unwrap();
+///
+/// let segments = ibd_segments(&tables, &[2, 3], 0).unwrap();
+/// assert_eq!(segments.len(), 1);
+/// assert_eq!(segments[0].samples, (2, 3));
+/// assert_eq!((segments[0].left, segments[0].right), (0, 30));
+/// assert_eq!(segments[0].mrca, 0);
+///
+/// assert!(ibd_segments(&tables, &[2, 3], 30).unwrap().is_empty());
+/// ```
+pub fn ibd_segments(
+    tables: &TableCollection,
+    samples: &[IdType],
+    min_length: Position,
+) -> Result<Vec<IbdSegment>, ForrusttsError> {
+    validate_nodes(tables, samples)?;
+    let mut is_sample = vec![false; tables.num_nodes()];
+    for &s in samples {
+        is_sample[s as usize] = true;
+    }
+    let mut segments = vec![];
+    let mut current = vec![];
+    // Segments overlapping the current position,
+    // grouped by the child they come from.
+    let mut active: Vec<(IdType, Vec<Segment>)> = vec![];
+    trace_ancestry(tables, samples, |parent, incoming| {
+        current.clear();
+        current.extend_from_slice(incoming);
+        if is_sample[parent as usize] {
+            current.push((parent, Segment::new(0, tables.genome_length(), parent)));
+        }
+        current.sort_by_key(|(_, s)| s.left);
+        active.clear();
+        // Sweep from left to right, pairing each segment with
+        // the active segments from the other children.
+        // Segments that no longer overlap are removed as they
+        // are visited, so segments from the same child are never
+        // compared.
+        for &(child, seg) in current.iter() {
+            for (c, group) in active.iter_mut() {
+                if *c == child {
+                    continue;
+                }
+                group.retain(|other| {
+                    if other.right <= seg.left {
+                        return false;
+                    }
+                    segments.push(IbdSegment {
+                        samples: (
+                            std::cmp::min(seg.node, other.node),
+                            std::cmp::max(seg.node, other.node),
+                        ),
+                        left: seg.left,
+                        right: std::cmp::min(seg.right, other.right),
+                        mrca: parent,
+                    });
+                    true
+                });
+            }
+            match active.iter_mut().find(|(c, _)| *c == child) {
+                Some((_, group)) => group.push(seg),
+                None => active.push((child, vec![seg])),
+            }
+        }
+        true
+    })?;
+
+    segments.sort_by(|a, b| a.samples.cmp(&b.samples).then(a.left.cmp(&b.left)));
+    let mut rv: Vec<IbdSegment> = vec![];
+    for seg in segments {
+        match rv.last_mut() {
+            Some(last)
+                if last.samples == seg.samples
+                    && last.mrca == seg.mrca
+                    && last.right == seg.left =>
+            {
+                last.right = seg.right
+            }
+            _ => rv.push(seg),
+        }
+    }
+    rv.retain(|s| s.right - s.left > min_length);
+    Ok(rv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::mrca;
+    use crate::wright_fisher::*;
+
+    // Founders 0 (deme 0) and 1 (deme 1).
+    // Node 2 is a child of 0 on [0, 50) and of 1 on [50, 100).
+    // Samples 3 and 4 are children of 2, except that
+    // 4 inherits [80, 100) directly from 1.
+    // Sample 5 is a child of 4.
+    fn make_tables() -> TableCollection {
+        let mut tables = TableCollection::new(100).unwrap();
+        tables.add_node(0, 0).unwrap();
+        tables.add_node(0, 1).unwrap();
+        tables.add_node(1, 0).unwrap();
+        tables.add_node(2, 0).unwrap();
+        tables.add_node(2, 0).unwrap();
+        tables.add_node(3, 0).unwrap();
+        tables.add_edge(0, 50, 0, 2).unwrap();
+        tables.add_edge(50, 100, 1, 2).unwrap();
+        tables.add_edge(0, 100, 2, 3).unwrap();
+        tables.add_edge(0, 80, 2, 4).unwrap();
+        tables.add_edge(80, 100, 1, 4).unwrap();
+        tables.add_edge(0, 100, 4, 5).unwrap();
+        tables
+    }
+
+    #[test]
+    fn test_local_ancestry() {
+        let tables = make_tables();
+        let segments = local_ancestry(&tables, &[3, 5], &[0, 1]).unwrap();
+        let found: Vec<_> = segments
+            .iter()
+            .map(|s| (s.sample, s.left, s.right, s.ancestor, s.deme))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (3, 0, 50, 0, 0),
+                (3, 50, 100, 1, 1),
+                (5, 0, 50, 0, 0),
+                (5, 50, 100, 1, 1),
+            ]
+        );
+
+        // The more recent ancestor is reported.
+        let segments = local_ancestry(&tables, &[4, 3], &[2, 1]).unwrap();
+        let found: Vec<_> = segments
+            .iter()
+            .map(|s| (s.sample, s.left, s.right, s.ancestor))
+            .collect();
+        assert_eq!(found, vec![(3, 0, 100, 2), (4, 0, 80, 2), (4, 80, 100, 1)]);
+
+        let segments = local_ancestry(&tables, &[4], &[4]).unwrap();
+        assert_eq!((segments[0].left, segments[0].right), (0, 100));
+        assert_eq!(segments[0].ancestor, 4);
+    }
+
+    #[test]
+    fn test_ibd_segments() {
+        let tables = make_tables();
+        let segments = ibd_segments(&tables, &[3, 4, 5], 0).unwrap();
+        let found: Vec<_> = segments
+            .iter()
+            .map(|s| (s.samples, s.left, s.right, s.mrca))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ((3, 4), 0, 80, 2),
+                ((3, 4), 80, 100, 1),
+                ((3, 5), 0, 80, 2),
+                ((3, 5), 80, 100, 1),
+                ((4, 5), 0, 100, 4),
+            ]
+        );
+        let segments = ibd_segments(&tables, &[3, 4, 5], 20).unwrap();
+        assert_eq!(segments.len(), 3);
+        assert!(segments.iter().all(|s| s.right - s.left > 20));
+    }
+
+    #[test]
+    fn test_ibd_caterpillar() {
+        // Samples 0-4.  Node 5 is the parent of 0 and 1,
+        // and node 4 + i is the parent of node 3 + i and sample i.
+        // Each edge is split in two, so that the segments
+        // passed to each ancestor are fragmented.
+        let mut tables = TableCollection::new(100).unwrap();
+        for _ in 0..5 {
+            tables.add_node(10, 0).unwrap();
+        }
+        for time in [9, 8, 7, 6] {
+            tables.add_node(time, 0).unwrap();
+        }
+        let mut add_edges = |parent, child| {
+            tables.add_edge(0, 50, parent, child).unwrap();
+            tables.add_edge(50, 100, parent, child).unwrap();
+        };
+        add_edges(5, 0);
+        add_edges(5, 1);
+        for i in 2..5 {
+            add_edges(4 + i, 3 + i);
+            add_edges(4 + i, i);
+        }
+        let segments = ibd_segments(&tables, &[0, 1, 2, 3, 4], 0).unwrap();
+        let found: Vec<_> = segments
+            .iter()
+            .map(|s| (s.samples, s.left, s.right, s.mrca))
+            .collect();
+        let mut expected = vec![];
+        for i in 0..5 {
+            for j in (i + 1)..5 {
+                expected.push(((i, j), 0, 100, 4 + j));
+            }
+        }
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn test_simulated_ibd() {
+        let tables = neutral_wf(
+            PopulationParams::new(50, 10000, 1e-3, 0.0),
+            SimulationParams::new(Some(20), 6011, 200, SimulationFlags::empty()),
+        )
+        .unwrap();
+        let samples: Vec<IdType> = tables.sample_nodes()[..8].to_vec();
+        let segments = ibd_segments(&tables, &samples, 0).unwrap();
+        for i in 0..samples.len() {
+            for j in (i + 1)..samples.len() {
+                let pair = (samples[i], samples[j]);
+                let pair_segments: Vec<_> = segments.iter().filter(|s| s.samples == pair).collect();
+                for w in pair_segments.windows(2) {
+                    assert!(w[0].right <= w[1].left);
+                }
+                for s in pair_segments {
+                    for p in [s.left, (s.left + s.right) / 2, s.right - 1] {
+                        let a = mrca(&tables, &[pair.0, pair.1], p).unwrap();
+                        assert_eq!(a, Some(s.mrca));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let tables = make_tables();
+        assert_eq!(
+            local_ancestry(&tables, &[3, 3], &[0]),
+            Err(TablesError::InvalidNodeValue { found: 3 }.into())
+        );
+        assert_eq!(
+            local_ancestry(&tables, &[3], &[0, 0]),
+            Err(TablesError::InvalidNodeValue { found: 0 }.into())
+        );
+        assert_eq!(
+            local_ancestry(&tables, &[3], &[10]),
+            Err(TablesError::NodeOutOfBounds.into())
+        );
+        assert_eq!(
+            ibd_segments(&tables, &[-1], 0),
+            Err(TablesError::NodeOutOfBounds.into())
+        );
+    }
+}
@@ -0,0 +1,864 @@
+//! A "structure of arrays" layout of the tables.
+
+use crate::simplification_common::{simplify_sorted_tables, SimplificationTables};
+use crate::tables::*;
+use crate::tsdef::{IdType, Position, Time};
+use crate::ForrusttsError;
+use crate::SamplesInfo;
+use crate::SimplificationBuffers;
+use crate::SimplificationFlags;
+use crate::SimplificationOutput;
+
+/// The columns of a node table.
+///
+/// Row `i` of the table is element `i` of
+/// each column.
+#[derive(Default)]
+pub struct NodeColumns {
+    pub(crate) time: Vec<Time>,
+    pub(crate) deme: Vec<IdType>,
+    pub(crate) flags: Vec<NodeFlags>,
+}
+
+impl NodeColumns {
+    /// Birth times
+    pub fn time(&self) -> &[Time] {
+        &self.time
+    }
+
+    /// Populations (demes)
+    pub fn deme(&self) -> &[IdType] {
+        &self.deme
+    }
+
+    /// Node flags
+    pub fn flags(&self) -> &[NodeFlags] {
+        &self.flags
+    }
+
+    /// Number of rows
+    pub fn len(&self) -> usize {
+        self.time.len()
+    }
+
+    /// [``true``] if there are no rows.
+    pub fn is_empty(&self) -> bool {
+        self.time.is_empty()
+    }
+
+    /// Return the i-th row as a [``Node``].
+    pub fn row(&self, i: usize) -> Node {
+        Node {
+            time: self.time[i],
+            deme: self.deme[i],
+            flags: self.flags[i],
+        }
+    }
+}
+
+/// The columns of an edge table.
+///
+/// Row `i` of the table is element `i` of
+/// each column.
+#[derive(Default)]
+pub struct EdgeColumns {
+    pub(crate) left: Vec<Position>,
+    pub(crate) right: Vec<Position>,
+    pub(crate) parent: Vec<IdType>,
+    pub(crate) child: Vec<IdType>,
+}
+
+impl EdgeColumns {
+    /// Left ends
+    pub fn left(&self) -> &[Position] {
+        &self.left
+    }
+
+    /// Right ends
+    pub fn right(&self) -> &[Position] {
+        &self.right
+    }
+
+    /// Parents
+    pub fn parent(&self) -> &[IdType] {
+        &self.parent
+    }
+
+    /// Children
+    pub fn child(&self) -> &[IdType] {
+        &self.child
+    }
+
+    /// Number of rows
+    pub fn len(&self) -> usize {
+        self.left.len()
+    }
+
+    /// [``true``] if there are no rows.
+    pub fn is_empty(&self) -> bool {
+        self.left.is_empty()
+    }
+
+    /// Return the i-th row as an [``Edge``].
+    pub fn row(&self, i: usize) -> Edge {
+        Edge {
+            left: self.left[i],
+            right: self.right[i],
+            parent: self.parent[i],
+            child: self.child[i],
+        }
+    }
+
+    fn push(&mut self, edge: Edge) {
+        self.left.push(edge.left);
+        self.right.push(edge.right);
+        self.parent.push(edge.parent);
+        self.child.push(edge.child);
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.left.truncate(len);
+        self.right.truncate(len);
+        self.parent.truncate(len);
+        self.child.truncate(len);
+    }
+
+    fn permute(&mut self, order: &[usize]) {
+        self.left = order.iter().map(|&i| self.left[i]).collect();
+        self.right = order.iter().map(|&i| self.right[i]).collect();
+        self.parent = order.iter().map(|&i| self.parent[i]).collect();
+        self.child = order.iter().map(|&i| self.child[i]).collect();
+    }
+}
+
+impl NodeRows for NodeColumns {
+    fn num_rows(&self) -> usize {
+        self.len()
+    }
+
+    fn node_time(&self, i: usize) -> Time {
+        self.time[i]
+    }
+
+    fn node_row(&self, i: usize) -> Node {
+        self.row(i)
+    }
+}
+
+impl EdgeRows for EdgeColumns {
+    fn num_rows(&self) -> usize {
+        self.len()
+    }
+
+    fn edge_row(&self, i: usize) -> Edge {
+        self.row(i)
+    }
+}
+
+/// The columns of a site table.
+///
+/// Row `i` of the table is element `i` of
+/// each column.
+#[derive(Default)]
+pub struct SiteColumns {
+    pub(crate) position: Vec<Position>,
+    pub(crate) ancestral_state: Vec<Option<Vec<u8>>>,
+}
+
+impl SiteColumns {
+    /// Positions
+    pub fn position(&self) -> &[Position] {
+        &self.position
+    }
+
+    /// Ancestral states
+    pub fn ancestral_state(&self) -> &[Option<Vec<u8>>] {
+        &self.ancestral_state
+    }
+
+    /// Number of rows
+    pub fn len(&self) -> usize {
+        self.position.len()
+    }
+
+    /// [``true``] if there are no rows.
+    pub fn is_empty(&self) -> bool {
+        self.position.is_empty()
+    }
+}
+
+/// The columns of a mutation table.
+///
+/// Row `i` of the table is element `i` of
+/// each column.
+#[derive(Default)]
+pub struct MutationColumns {
+    pub(crate) node: Vec<IdType>,
+    pub(crate) key: Vec<usize>,
+    pub(crate) site: Vec<usize>,
+    pub(crate) derived_state: Vec<Option<Vec<u8>>>,
+    pub(crate) neutral: Vec<bool>,
+}
+
+impl MutationColumns {
+    /// The nodes where the mutations map
+    pub fn node(&self) -> &[IdType] {
+        &self.node
+    }
+
+    /// References to mutation metadata
+    pub fn key(&self) -> &[usize] {
+        &self.key
+    }
+
+    /// The indexes of the corresponding [``Site``]s
+    pub fn site(&self) -> &[usize] {
+        &self.site
+    }
+
+    /// Derived states
+    pub fn derived_state(&self) -> &[Option<Vec<u8>>] {
+        &self.derived_state
+    }
+
+    /// [``true``] if a mutation is neutral
+    pub fn neutral(&self) -> &[bool] {
+        &self.neutral
+    }
+
+    /// Number of rows
+    pub fn len(&self) -> usize {
+        self.node.len()
+    }
+
+    /// [``true``] if there are no rows.
+    pub fn is_empty(&self) -> bool {
+        self.node.is_empty()
+    }
+
+    fn permute(&mut self, order: &[usize]) {
+        self.node = order.iter().map(|&i| self.node[i]).collect();
+        self.key = order.iter().map(|&i| self.key[i]).collect();
+        self.site = order.iter().map(|&i| self.site[i]).collect();
+        let mut derived_state = Vec::with_capacity(order.len());
+        for &i in order {
+            derived_state.push(self.derived_state[i].take());
+        }
+        self.derived_state = derived_state;
+        self.neutral = order.iter().map(|&i| self.neutral[i]).collect();
+    }
+}
+
+/// A collection of node, edge, site, and mutation tables
+/// stored as columns.
+///
+/// This type holds the same data as a [``TableCollection``],
+/// but each table is a "structure of arrays", as in ``tskit``.
+/// Scanning a single column, such as node times or edge left
+/// positions, is therefore cache-friendly.
+///
+/// Conversion to and from [``TableCollection``] is done via [``From``].
+/// Converting an owned value moves the data, one table at a time.
+///
+/// # Notes
+///
+/// Although the columns have the same layout as those of
+/// ``tskit``, the ``tskit`` exporters take a [``TableCollection``],
+/// so exporting requires a conversion.
+/// ``tskit`` tables own their memory, so the columns
+/// cannot be handed over without a copy.
+///
+/// # Example
+///
+/// ```
+/// use forrustts::{ColumnarTableCollection, TableCollection};
+///
+/// let mut tables = TableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap();
+/// tables.add_node(1, 0).unwrap();
+/// tables.add_edge(0, 100, 0, 1).unwrap();
+///
+/// let columns = ColumnarTableCollection::from(tables);
+/// assert_eq!(columns.nodes().time(), &[0, 1]);
+/// assert_eq!(columns.edges().left(), &[0]);
+///
+/// let tables = TableCollection::from(columns);
+/// assert_eq!(tables.num_edges(), 1);
+/// ```
+pub struct ColumnarTableCollection {
+    length_: Position,
+    pub(crate) nodes_: NodeColumns,
+    pub(crate) edges_: EdgeColumns,
+    pub(crate) sites_: SiteColumns,
+    pub(crate) mutations_: MutationColumns,
+}
+
+impl ColumnarTableCollection {
+    /// Create a new instance.
+    ///
+    /// # Parameters
+    ///
+    /// * `genome_length`: the total genome length for the tables.
+    ///
+    /// # Errors
+    ///
+    /// Will return [``TablesError``] if `genome_length < 1`.
+    pub fn new(genome_length: Position) -> TablesResult<ColumnarTableCollection> {
+        if genome_length < 1 {
+            return Err(TablesError::InvalidGenomeLength);
+        }
+        Ok(ColumnarTableCollection {
+            length_: genome_length,
+            nodes_: NodeColumns::default(),
+            edges_: EdgeColumns::default(),
+            sites_: SiteColumns::default(),
+            mutations_: MutationColumns::default(),
+        })
+    }
+
+    /// Add a node.
+    ///
+    /// See [``TableCollection::add_node``].
+    pub fn add_node(&mut self, time: Time, deme: IdType) -> TablesResult<IdType> {
+        self.add_node_with_flags(time, deme, NodeFlags::empty())
+    }
+
+    /// Add a node with [``NodeFlags``].
+    ///
+    /// See [``TableCollection::add_node_with_flags``].
+    pub fn add_node_with_flags(
+        &mut self,
+        time: Time,
+        deme: IdType,
+        flags: NodeFlags,
+    ) -> TablesResult<IdType> {
+        deme_non_negative(deme)?;
+        self.nodes_.time.push(time);
+        self.nodes_.deme.push(deme);
+        self.nodes_.flags.push(flags);
+        Ok((self.nodes_.len() - 1) as IdType)
+    }
+
+    /// Add an edge.
+    ///
+    /// See [``TableCollection::add_edge``].
+    pub fn add_edge(
+        &mut self,
+        left: Position,
+        right: Position,
+        parent: IdType,
+        child: IdType,
+    ) -> TablesResult<IdType> {
+        validate_edge_row(left, right, parent, child)?;
+        self.edges_.push(Edge {
+            left,
+            right,
+            parent,
+            child,
+        });
+        Ok((self.edges_.len() - 1) as IdType)
+    }
+
+    /// Add a site.
+    ///
+    /// See [``TableCollection::add_site``].
+    pub fn add_site(
+        &mut self,
+        position: Position,
+        ancestral_state: Option<Vec<u8>>,
+    ) -> TablesResult<IdType> {
+        validate_site_position(position, self.length_)?;
+        self.sites_.position.push(position);
+        self.sites_.ancestral_state.push(ancestral_state);
+        Ok((self.sites_.len() - 1) as IdType)
+    }
+
+    /// Add a mutation.
+    ///
+    /// See [``TableCollection::add_mutation``].
+    pub fn add_mutation(
+        &mut self,
+        node: IdType,
+        key: usize,
+        site: usize,
+        derived_state: Option<Vec<u8>>,
+        neutral: bool,
+    ) -> TablesResult<IdType> {
+        node_non_negative(node)?;
+        self.mutations_.node.push(node);
+        self.mutations_.key.push(key);
+        self.mutations_.site.push(site);
+        self.mutations_.derived_state.push(derived_state);
+        self.mutations_.neutral.push(neutral);
+        Ok((self.mutations_.len() - 1) as IdType)
+    }
+
+    /// Get genome length
+    pub fn genome_length(&self) -> Position {
+        self.length_
+    }
+
+    /// Return the node columns
+    pub fn nodes(&self) -> &NodeColumns {
+        &self.nodes_
+    }
+
+    /// Return the edge columns
+    pub fn edges(&self) -> &EdgeColumns {
+        &self.edges_
+    }
+
+    /// Return the site columns
+    pub fn sites(&self) -> &SiteColumns {
+        &self.sites_
+    }
+
+    /// Return the mutation columns
+    pub fn mutations(&self) -> &MutationColumns {
+        &self.mutations_
+    }
+
+    /// Return number of nodes
+    pub fn num_nodes(&self) -> usize {
+        self.nodes_.len()
+    }
+
+    /// Return number of edges
+    pub fn num_edges(&self) -> usize {
+        self.edges_.len()
+    }
+
+    /// Sort all tables for simplification.
+    ///
+    /// The sort order is the same as for
+    /// [``TableCollection::sort_tables``].
+    pub fn sort_tables(&mut self, flags: TableSortingFlags) {
+        if !flags.contains(TableSortingFlags::SKIP_EDGE_TABLE) {
+            let edges = &self.edges_;
+            let mut order: Vec<usize> = (0..edges.len()).collect();
+            order.sort_by(|&a, &b| edge_sort_order(&self.nodes_, &edges.row(a), &edges.row(b)));
+            self.edges_.permute(&order);
+        }
+        let position = &self.sites_.position;
+        let site = &self.mutations_.site;
+        let mut order: Vec<usize> = (0..site.len()).collect();
+        order.sort_by(|&a, &b| mutation_sort_order(position[site[a]], position[site[b]]));
+        self.mutations_.permute(&order);
+    }
+}
+
+impl SimplificationTables for ColumnarTableCollection {
+    type Nodes = NodeColumns;
+    type Edges = EdgeColumns;
+
+    fn genome_length(&self) -> Position {
+        self.length_
+    }
+
+    fn node_rows(&self) -> &Self::Nodes {
+        &self.nodes_
+    }
+
+    fn edge_rows(&self) -> &Self::Edges {
+        &self.edges_
+    }
+
+    fn has_sites_or_mutations(&self) -> bool {
+        !self.sites_.is_empty() || !self.mutations_.is_empty()
+    }
+
+    fn set_edge(&mut self, i: usize, edge: Edge) {
+        let e = &mut self.edges_;
+        e.left[i] = edge.left;
+        e.right[i] = edge.right;
+        e.parent[i] = edge.parent;
+        e.child[i] = edge.child;
+    }
+
+    fn finish_edges(&mut self, len: usize, new_edges: &mut EdgeTable) {
+        self.edges_.truncate(len);
+        for edge in new_edges.drain(..) {
+            self.edges_.push(edge);
+        }
+    }
+
+    fn replace_nodes(&mut self, new_nodes: &mut NodeTable) {
+        let n = &mut self.nodes_;
+        n.time.clear();
+        n.deme.clear();
+        n.flags.clear();
+        for node in new_nodes.drain(..) {
+            n.time.push(node.time);
+            n.deme.push(node.deme);
+            n.flags.push(node.flags);
+        }
+    }
+}
+
+impl From<TableCollection> for ColumnarTableCollection {
+    fn from(tables: TableCollection) -> Self {
+        let mut rv = ColumnarTableCollection::new(tables.genome_length()).unwrap();
+        let TableCollection {
+            nodes_,
+            edges_,
+            sites_,
+            mutations_,
+            ..
+        } = tables;
+
+        let n = &mut rv.nodes_;
+        n.time.reserve(nodes_.len());
+        n.deme.reserve(nodes_.len());
+        n.flags.reserve(nodes_.len());
+        for node in nodes_ {
+            n.time.push(node.time);
+            n.deme.push(node.deme);
+            n.flags.push(node.flags);
+        }
+
+        let e = &mut rv.edges_;
+        e.left.reserve(edges_.len());
+        e.right.reserve(edges_.len());
+        e.parent.reserve(edges_.len());
+        e.child.reserve(edges_.len());
+        for edge in edges_ {
+            e.left.push(edge.left);
+            e.right.push(edge.right);
+            e.parent.push(edge.parent);
+            e.child.push(edge.child);
+        }
+
+        let s = &mut rv.sites_;
+        s.position.reserve(sites_.len());
+        s.ancestral_state.reserve(sites_.len());
+        for site in sites_ {
+            s.position.push(site.position);
+            s.ancestral_state.push(site.ancestral_state);
+        }
+
+        let m = &mut rv.mutations_;
+        m.node.reserve(mutations_.len());
+        m.key.reserve(mutations_.len());
+        m.site.reserve(mutations_.len());
+        m.derived_state.reserve(mutations_.len());
+        m.neutral.reserve(mutations_.len());
+        for mutation in mutations_ {
+            m.node.push(mutation.node);
+            m.key.push(mutation.key);
+            m.site.push(mutation.site);
+            m.derived_state.push(mutation.derived_state);
+            m.neutral.push(mutation.neutral);
+        }
+
+        rv
+    }
+}
+
+impl From<ColumnarTableCollection> for TableCollection {
+    fn from(columns: ColumnarTableCollection) -> Self {
+        let mut rv = TableCollection::new(columns.length_).unwrap();
+        let ColumnarTableCollection {
+            nodes_,
+            edges_,
+            sites_,
+            mutations_,
+            ..
+        } = columns;
+
+        rv.nodes_ = nodes_
+            .time
+            .into_iter()
+            .zip(nodes_.deme)
+            .zip(nodes_.flags)
+            .map(|((time, deme), flags)| Node { time, deme, flags })
+            .collect();
+
+        rv.edges_ = edges_
+            .left
+            .into_iter()
+            .zip(edges_.right)
+            .zip(edges_.parent)
+            .zip(edges_.child)
+            .map(|(((left, right), parent), child)| Edge {
+                left,
+                right,
+                parent,
+                child,
+            })
+            .collect();
+
+        rv.sites_ = sites_
+            .position
+            .into_iter()
+            .zip(sites_.ancestral_state)
+            .map(|(position, ancestral_state)| Site {
+                position,
+                ancestral_state,
+            })
+            .collect();
+
+        rv.mutations_ = mutations_
+            .node
+            .into_iter()
+            .zip(mutations_.key)
+            .zip(mutations_.site)
+            .zip(mutations_.derived_state)
+            .zip(mutations_.neutral)
+            .map(
+                |((((node, key), site), derived_state), neutral)| MutationRecord {
+                    node,
+                    key,
+                    site,
+                    derived_state,
+                    neutral,
+                },
+            )
+            .collect();
+
+        rv
+    }
+}
+
+/// Simplify a [``ColumnarTableCollection``].
+///
+/// # Parameters
+///
+/// See [``simplify_tables``](crate::simplify_tables).
+///
+/// # Notes
+///
+/// The input tables must be sorted.
+/// See [``ColumnarTableCollection::sort_tables``].
+///
+/// The input columns are read in place, and the output
+/// edges overwrite them, as for [``TableCollection``].
+///
+/// # Errors
+///
+/// See [``simplify_tables``](crate::simplify_tables).
+///
+/// # Example
+///
+/// ```
+/// use forrustts::*;
+///
+/// let mut tables = ColumnarTableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap(); // parent
+/// tables.add_node(1, 0).unwrap(); // child
+/// tables.add_node(1, 0).unwrap(); // child with no ancestry
+/// tables.add_edge(0, 100, 0, 1).unwrap();
+///
+/// let mut samples = SamplesInfo::new();
+/// samples.samples.push(1);
+/// let mut output = SimplificationOutput::new();
+/// simplify_columnar_tables(
+///     &samples,
+///     SimplificationFlags::empty(),
+///     &mut SimplificationBuffers::new(),
+///     &mut tables,
+///     &mut output,
+/// )
+/// .unwrap();
+/// assert_eq!(tables.num_nodes(), 1);
+/// assert_eq!(tables.num_edges(), 0);
+/// ```
+pub fn simplify_columnar_tables(
+    samples: &SamplesInfo,
+    flags: SimplificationFlags,
+    state: &mut SimplificationBuffers,
+    tables: &mut ColumnarTableCollection,
+    output: &mut SimplificationOutput,
+) -> Result<(), ForrusttsError> {
+    simplify_sorted_tables(samples, flags, state, tables, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_tables() -> TableCollection {
+        let mut tables = TableCollection::new(100).unwrap();
+        tables.add_node(0, 0).unwrap();
+        tables.add_node(1, 1).unwrap();
+        tables
+            .add_node_with_flags(2, 0, NodeFlags::IS_SAMPLE)
+            .unwrap();
+        tables
+            .add_node_with_flags(2, 1, NodeFlags::IS_SAMPLE)
+            .unwrap();
+        tables.add_edge(0, 100, 1, 2).unwrap();
+        tables.add_edge(0, 100, 0, 1).unwrap();
+        tables.add_edge(50, 100, 1, 3).unwrap();
+        tables.add_edge(0, 50, 0, 3).unwrap();
+        tables.add_site(10, Some(b"A".to_vec())).unwrap();
+        tables.add_site(90, None).unwrap();
+        tables.add_mutation(2, 0, 0, None, true).unwrap();
+        tables
+            .add_mutation(3, 1, 1, Some(b"T".to_vec()), false)
+            .unwrap();
+        tables
+    }
+
+    fn assert_same_data(tables: &TableCollection, columns: &ColumnarTableCollection) {
+        assert_eq!(tables.genome_length(), columns.genome_length());
+        assert_eq!(tables.num_nodes(), columns.num_nodes());
+        for (i, n) in tables.enumerate_nodes() {
+            assert_eq!(n.time, columns.nodes().time()[i]);
+            assert_eq!(n.deme, columns.nodes().deme()[i]);
+            assert_eq!(n.flags, columns.nodes().flags()[i]);
+        }
+        assert_eq!(tables.num_edges(), columns.num_edges());
+        for (i, e) in tables.enumerate_edges() {
+            let c = columns.edges().row(i);
+            assert_eq!(
+                (e.left, e.right, e.parent, e.child),
+                (c.left, c.right, c.parent, c.child)
+            );
+        }
+        assert_eq!(tables.sites().len(), columns.sites().len());
+        for (i, s) in tables.enumerate_sites() {
+            assert_eq!(s.position, columns.sites().position()[i]);
+            assert_eq!(s.ancestral_state, columns.sites().ancestral_state()[i]);
+        }
+        assert_eq!(tables.mutations().len(), columns.mutations().len());
+        for (i, m) in tables.enumerate_mutations() {
+            assert_eq!(m.node, columns.mutations().node()[i]);
+            assert_eq!(m.key, columns.mutations().key()[i]);
+            assert_eq!(m.site, columns.mutations().site()[i]);
+            assert_eq!(m.derived_state, columns.mutations().derived_state()[i]);
+            assert_eq!(m.neutral, columns.mutations().neutral()[i]);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let columns = ColumnarTableCollection::from(make_tables());
+        assert_same_data(&make_tables(), &columns);
+        let tables = TableCollection::from(columns);
+        assert_same_data(&tables, &ColumnarTableCollection::from(make_tables()));
+    }
+
+    #[test]
+    fn test_add_rows() {
+        let mut columns = ColumnarTableCollection::new(100).unwrap();
+        assert!(ColumnarTableCollection::new(0).is_err());
+        assert_eq!(columns.add_node(0, 0).unwrap(), 0);
+        assert!(columns.add_node(0, -1).is_err());
+        assert_eq!(columns.add_edge(0, 10, 0, 1).unwrap(), 0);
+        assert!(columns.add_edge(10, 10, 0, 1).is_err());
+        assert!(columns.add_edge(0, 10, -1, 1).is_err());
+        assert_eq!(columns.add_site(5, None).unwrap(), 0);
+        assert!(columns.add_site(100, None).is_err());
+        assert_eq!(columns.add_mutation(1, 0, 0, None, true).unwrap(), 0);
+        assert!(columns.add_mutation(-1, 0, 0, None, true).is_err());
+        assert_eq!(columns.num_nodes(), 1);
+        assert_eq!(columns.num_edges(), 1);
+        assert_eq!(columns.sites().len(), 1);
+        assert_eq!(columns.mutations().len(), 1);
+    }
+
+    #[test]
+    fn test_sort_tables() {
+        let mut tables = make_tables();
+        let mut columns = ColumnarTableCollection::from(make_tables());
+        tables.sort_tables(TableSortingFlags::empty());
+        columns.sort_tables(TableSortingFlags::empty());
+        assert_same_data(&tables, &columns);
+    }
+
+    #[test]
+    fn test_simplify() {
+        // Simplification does not yet handle mutations.
+        let make_tables = || {
+            let mut tables = make_tables();
+            tables.sites_.clear();
+            tables.mutations_.clear();
+            tables
+        };
+        let mut tables = make_tables();
+        tables.sort_tables(TableSortingFlags::empty());
+        let mut columns = ColumnarTableCollection::from(make_tables());
+        columns.sort_tables(TableSortingFlags::empty());
+
+        let mut samples = SamplesInfo::new();
+        samples.samples = vec![2, 3];
+        let mut output = SimplificationOutput::new();
+        crate::simplify_tables_without_state(
+            &samples,
+            SimplificationFlags::VALIDATE_ALL,
+            &mut tables,
+            &mut output,
+        )
+        .unwrap();
+        let expected_idmap = output.idmap.clone();
+
+        simplify_columnar_tables(
+            &samples,
+            SimplificationFlags::VALIDATE_ALL,
+            &mut SimplificationBuffers::new(),
+            &mut columns,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(output.idmap, expected_idmap);
+        assert_same_data(&tables, &columns);
+    }
+
+    #[test]
+    fn test_simplify_simulated_tables() {
+        use crate::wright_fisher::*;
+        let simulate = || {
+            let mut tables = neutral_wf(
+                PopulationParams::new(100, 100000, 5.0, 0.0),
+                SimulationParams::new(None, 42, 100, SimulationFlags::empty()),
+            )
+            .unwrap();
+            tables.sort_tables(TableSortingFlags::empty());
+            tables
+        };
+        let mut tables = simulate();
+        let mut columns = ColumnarTableCollection::from(simulate());
+
+        let mut samples = SamplesInfo::new();
+        samples.samples = tables.sample_nodes();
+        let mut output = SimplificationOutput::new();
+        crate::simplify_tables_without_state(
+            &samples,
+            SimplificationFlags::VALIDATE_ALL,
+            &mut tables,
+            &mut output,
+        )
+        .unwrap();
+        let expected_idmap = output.idmap.clone();
+        assert!(tables.num_edges() > 1024);
+
+        simplify_columnar_tables(
+            &samples,
+            SimplificationFlags::VALIDATE_ALL,
+            &mut SimplificationBuffers::new(),
+            &mut columns,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(output.idmap, expected_idmap);
+        assert_same_data(&tables, &columns);
+    }
+
+    #[test]
+    fn test_simplify_error_keeps_tables() {
+        let mut columns = ColumnarTableCollection::from(make_tables());
+        // Unsorted edges
+        let mut samples = SamplesInfo::new();
+        samples.samples = vec![2, 3];
+        let mut output = SimplificationOutput::new();
+        assert!(simplify_columnar_tables(
+            &samples,
+            SimplificationFlags::VALIDATE_ALL,
+            &mut SimplificationBuffers::new(),
+            &mut columns,
+            &mut output,
+        )
+        .is_err());
+        assert_same_data(&make_tables(), &columns);
+    }
+}
@@ -29,6 +29,14 @@ pub enum ForrusttsError {
         #[from]
         value: crate::TablesError,
     },
+    /// An error returned by ``tskit``.
+    #[error("tskit error {code}: {message}")]
+    TskitError {
+        /// The ``tskit`` error code
+        code: i32,
+        /// The error message
+        message: String,
+    },
 }
 
 #[cfg(test)]
@@ -72,4 +80,13 @@ mod test {
             },
         };
     }
+
+    #[test]
+    fn test_tskit_error_display() {
+        let e = ForrusttsError::TskitError {
+            code: -2,
+            message: "Bad node".to_string(),
+        };
+        assert_eq!(e.to_string(), "tskit error -2: Bad node");
+    }
 }
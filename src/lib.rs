@@ -17,6 +17,8 @@
 //!    those of their parents.
 //! 2. The data layout is "array of structures" while
 //!    `tskit` is a "structure of arrays".
+//!    A "structure of arrays" layout is available
+//!    via [``ColumnarTableCollection``].
 //! 3. Metadata is not part of the tables.
 //!    Often, what one thinks of as metadata is data
 //!    used during the simulation.  Thus, it is not
@@ -41,9 +43,13 @@
 // stuff that needs documenting:
 // #![warn(missing_docs)]
 
+pub mod ancestry;
+mod columnar_tables;
 mod edge_buffer;
 mod error;
+mod memory_usage;
 pub mod nested_forward_list;
+pub mod newick;
 mod samples_info;
 mod segment;
 mod simplification_buffers;
@@ -54,21 +60,33 @@ mod simplification_output;
 mod simplify_from_edge_buffer;
 mod simplify_tables;
 mod tables;
+mod tables_io;
+mod trees;
 mod tsdef;
 
+pub use columnar_tables::{
+    simplify_columnar_tables, ColumnarTableCollection, EdgeColumns, MutationColumns, NodeColumns,
+    SiteColumns,
+};
 pub use edge_buffer::EdgeBuffer;
 pub use error::ForrusttsError;
+pub use memory_usage::MemoryUsage;
 pub use samples_info::SamplesInfo;
 pub use segment::Segment;
-pub use simplification_buffers::SimplificationBuffers;
+pub use simplification_buffers::{SimplificationBuffers, SimplificationBuffersMemoryUsage};
 pub use simplification_flags::SimplificationFlags;
 pub use simplification_output::SimplificationOutput;
 pub use simplify_from_edge_buffer::simplify_from_edge_buffer;
 pub use simplify_tables::{simplify_tables, simplify_tables_without_state};
 pub use tables::*;
+pub use tables_io::{TablesIOError, TablesIOResult};
+pub use trees::{Tree, TreeIterator};
 pub use tsdef::*;
 
+pub mod ms;
+pub mod stats;
 pub mod tskit;
+pub mod vcf;
 pub mod wright_fisher;
 
 /// Get the forrustts version number.
@@ -77,4 +95,7 @@ pub fn version() -> &'static str {
 }
 
 // These are testing modules
+#[cfg(test)]
+mod test_fixtures;
+#[cfg(feature = "tskit_rust")]
 mod test_simplify_tables;
@@ -0,0 +1,87 @@
+use std::iter::Sum;
+use std::ops::{Add, AddAssign};
+
+/// Heap memory used by a container, in bytes.
+///
+/// Only memory owned by the container is counted,
+/// and not the size of the container itself.
+///
+/// # Example
+///
+/// ```
+/// let tables = forrustts::TableCollection::new(100).unwrap();
+/// let usage = tables.memory_usage().total();
+/// assert_eq!(usage.used, 0);
+/// assert!(usage.allocated >= usage.used);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Bytes used by the stored data.
+    pub used: usize,
+    /// Bytes allocated, including unused capacity.
+    pub allocated: usize,
+}
+
+impl MemoryUsage {
+    pub(crate) fn of_vec<T>(v: &[T], capacity: usize) -> Self {
+        MemoryUsage {
+            used: std::mem::size_of_val(v),
+            allocated: capacity * std::mem::size_of::<T>(),
+        }
+    }
+
+    // The heap memory of an allelic state.
+    pub(crate) fn of_state(state: &Option<Vec<u8>>) -> Self {
+        match state {
+            Some(s) => MemoryUsage::of_vec(s, s.capacity()),
+            None => MemoryUsage::default(),
+        }
+    }
+}
+
+impl Add for MemoryUsage {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        MemoryUsage {
+            used: self.used + other.used,
+            allocated: self.allocated + other.allocated,
+        }
+    }
+}
+
+impl AddAssign for MemoryUsage {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Sum for MemoryUsage {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(MemoryUsage::default(), |a, b| a + b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_usage() {
+        let mut v: Vec<u32> = Vec::with_capacity(10);
+        v.push(1);
+        v.push(2);
+        let a = MemoryUsage::of_vec(&v, v.capacity());
+        assert_eq!(a.used, 8);
+        assert_eq!(a.allocated, 40);
+        let state = Some(vec![0_u8; 3]);
+        let b = MemoryUsage::of_state(&state);
+        assert_eq!(b.used, 3);
+        assert_eq!(MemoryUsage::of_state(&None), MemoryUsage::default());
+        let total: MemoryUsage = vec![a, b].into_iter().sum();
+        assert_eq!(total, a + b);
+        let mut c = a;
+        c += b;
+        assert_eq!(c, total);
+    }
+}
@@ -0,0 +1,280 @@
+//! Output of haplotypes in the format of Hudson's
+//! [ms](http://home.uchicago.edu/~rhudson1/source/mksamples.html).
+//!
+//! Each replicate is written as:
+//!
+//! ```text
+//! //
+//! segsites: 2
+//! positions: 0.1000 0.7500
+//! 01
+//! 10
+//! ```
+//!
+//! Positions are divided by [``TableCollection::genome_length``],
+//! so that they are in `[0, 1)`.
+//! They are truncated, not rounded, to the precision
+//! (see [``MsWriter::precision``]), so that positions near the end
+//! of the genome are not written as `1`.
+//! Each row is the haplotype of a sample node, where `0` is the
+//! ancestral state and `1` is any derived state.
+//! Only segregating sites are written, which are the sites
+//! where some, but not all, of the samples carry a derived state.
+//!
+//! [``MsWriter``] writes any number of replicates to the same
+//! output, optionally preceded by the header lines that ``ms``
+//! writes (the command line and the random number seeds).
+
+use crate::stats::{StatsError, VariantIterator};
+use crate::tables::TableCollection;
+use crate::tsdef::{IdType, Position};
+use std::io::Write;
+use thiserror::Error;
+
+/// Error type for ``ms`` output.
+#[derive(Error, Debug)]
+pub enum MsError {
+    /// A redirection of a [``std::io::Error``].
+    #[error("{value:?}")]
+    IOError {
+        /// The redirected error
+        #[from]
+        value: std::io::Error,
+    },
+    /// A redirection of a [``StatsError``].
+    #[error("{value:?}")]
+    StatsError {
+        /// The redirected error
+        #[from]
+        value: StatsError,
+    },
+}
+
+/// Result type for ``ms`` output.
+pub type MsResult<T> = Result<T, MsError>;
+
+/// Write replicates in ``ms`` format.
+///
+/// # Example
+///
+/// ```
+/// use forrustts::ms::MsWriter;
+/// use forrustts::TableCollection;
+///
+/// let mut tables = TableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap();
+/// for _ in 0..2 {
+///     tables.add_node(10, 0).unwrap();
+/// }
+/// tables.add_edge(0, 100, 0, 1).unwrap();
+/// tables.add_edge(0, 100, 0, 2).unwrap();
+/// tables.add_site(25, None).unwrap();
+/// tables.add_mutation(2, 0, 0, None, true).unwrap();
+///
+/// let mut writer = MsWriter::new(vec![]);
+/// writer.write_header("ms 2 2 -t 1.0", &[1, 2, 3]).unwrap();
+/// writer.write_replicate(&tables, &[1, 2]).unwrap();
+/// writer.write_replicate(&tables, &[1, 2]).unwrap();
+/// let output = String::from_utf8(writer.into_inner()).unwrap();
+/// let replicate = "//\nsegsites: 1\npositions: 0.2500\n0\n1\n";
+/// assert_eq!(
+///     output,
+///     format!("ms 2 2 -t 1.0\n1 2 3\n\n{}\n{}", replicate, replicate)
+/// );
+/// ```
+pub struct MsWriter<W: Write> {
+    writer: W,
+    precision: usize,
+    num_replicates: usize,
+}
+
+impl<W: Write> MsWriter<W> {
+    /// Create a new writer.
+    ///
+    /// Positions are written with 4 decimal places.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            precision: 4,
+            num_replicates: 0,
+        }
+    }
+
+    /// Set the number of decimal places of the positions.
+    pub fn precision(mut self, precision: usize) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Write the ``ms`` header: the command line
+    /// and the random number seeds.
+    ///
+    /// This function should be called before any replicate
+    /// is written.
+    pub fn write_header(&mut self, command_line: &str, seeds: &[u64]) -> MsResult<()> {
+        writeln!(self.writer, "{}", command_line)?;
+        let seeds: Vec<String> = seeds.iter().map(|s| s.to_string()).collect();
+        writeln!(self.writer, "{}", seeds.join(" "))?;
+        Ok(())
+    }
+
+    /// Write the haplotypes of `samples` as a replicate.
+    ///
+    /// Sites that are not segregating in `samples` are skipped.
+    ///
+    /// # Errors
+    ///
+    /// [``MsError::StatsError``] if `samples` is invalid.
+    pub fn write_replicate(
+        &mut self,
+        tables: &TableCollection,
+        samples: &[IdType],
+    ) -> MsResult<()> {
+        let variants: Vec<_> = VariantIterator::new(tables, samples)?
+            .filter(|v| {
+                let num_derived = v.genotypes().iter().filter(|g| **g != 0).count();
+                num_derived > 0 && num_derived < samples.len()
+            })
+            .collect();
+        let length = tables.genome_length();
+
+        writeln!(self.writer)?;
+        writeln!(self.writer, "//")?;
+        writeln!(self.writer, "segsites: {}", variants.len())?;
+        if !variants.is_empty() {
+            write!(self.writer, "positions:")?;
+            for v in variants.iter() {
+                write!(
+                    self.writer,
+                    " {}",
+                    format_position(v.position(), length, self.precision)
+                )?;
+            }
+            writeln!(self.writer)?;
+            for i in 0..samples.len() {
+                let haplotype: String = variants
+                    .iter()
+                    .map(|v| if v.genotypes()[i] == 0 { '0' } else { '1' })
+                    .collect();
+                writeln!(self.writer, "{}", haplotype)?;
+            }
+        }
+        self.num_replicates += 1;
+        Ok(())
+    }
+
+    /// The number of replicates written.
+    pub fn num_replicates(&self) -> usize {
+        self.num_replicates
+    }
+
+    /// Return the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Write `position / length` with `precision` decimal places,
+/// truncating instead of rounding.
+///
+/// The digits are found by long division, so that no
+/// floating point error can carry into the last digit.
+fn format_position(position: Position, length: Position, precision: usize) -> String {
+    if precision == 0 {
+        return "0".to_string();
+    }
+    let mut rv = String::from("0.");
+    let mut remainder = position as i128;
+    for _ in 0..precision {
+        remainder *= 10;
+        rv.push((b'0' + (remainder / length as i128) as u8) as char);
+        remainder %= length as i128;
+    }
+    rv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::two_trees;
+
+    // The trees of [`two_trees`], with a site at 75 that
+    // is derived in all samples, and sites at 12 and 50 that
+    // are derived in samples 3 and 2.
+    fn make_tables() -> TableCollection {
+        let mut tables = two_trees();
+        tables.add_site(75, Some(b"A".to_vec())).unwrap();
+        tables
+            .add_mutation(1, 0, 0, Some(b"C".to_vec()), true)
+            .unwrap();
+        tables
+            .add_mutation(3, 0, 0, Some(b"G".to_vec()), true)
+            .unwrap();
+        tables.add_site(12, None).unwrap();
+        tables.add_mutation(3, 0, 1, None, true).unwrap();
+        tables.add_site(50, None).unwrap();
+        tables.add_mutation(2, 0, 2, None, true).unwrap();
+        tables
+    }
+
+    #[test]
+    fn test_replicates() {
+        let tables = make_tables();
+        let mut writer = MsWriter::new(vec![]).precision(2);
+        writer.write_replicate(&tables, &[2, 3, 4]).unwrap();
+        writer.write_replicate(&tables, &[4, 2]).unwrap();
+        assert_eq!(writer.num_replicates(), 2);
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        // The site at 75 is derived in all samples,
+        // and the site at 12 is ancestral in the second replicate.
+        assert_eq!(
+            output,
+            "\n//\nsegsites: 2\npositions: 0.12 0.50\n01\n10\n00\n\n//\nsegsites: 1\npositions: 0.50\n0\n1\n"
+        );
+    }
+
+    #[test]
+    fn test_truncated_positions() {
+        let mut tables = two_trees();
+        for (position, node) in [(29, 3), (tables.genome_length() - 1, 1)] {
+            let site = tables.add_site(position, None).unwrap() as usize;
+            tables.add_mutation(node, 0, site, None, true).unwrap();
+        }
+        for (precision, positions) in [(1, "0.2 0.9"), (2, "0.29 0.99")] {
+            let mut writer = MsWriter::new(vec![]).precision(precision);
+            writer.write_replicate(&tables, &[2, 3, 4]).unwrap();
+            let output = String::from_utf8(writer.into_inner()).unwrap();
+            assert_eq!(
+                output,
+                format!("\n//\nsegsites: 2\npositions: {}\n01\n10\n01\n", positions)
+            );
+        }
+        assert_eq!(format_position(99, 100, 0), "0");
+        assert_eq!(format_position(1, 3, 20), "0.33333333333333333333");
+    }
+
+    #[test]
+    fn test_no_sites() {
+        let mut tables = TableCollection::new(10).unwrap();
+        tables.add_node(0, 0).unwrap();
+        let mut writer = MsWriter::new(vec![]);
+        writer.write_replicate(&tables, &[0]).unwrap();
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(output, "\n//\nsegsites: 0\n");
+    }
+
+    #[test]
+    fn test_invalid_samples() {
+        let tables = make_tables();
+        let mut writer = MsWriter::new(vec![]);
+        assert!(matches!(
+            writer.write_replicate(&tables, &[2, 2]),
+            Err(MsError::StatsError { .. })
+        ));
+        assert!(matches!(
+            writer.write_replicate(&tables, &[10]),
+            Err(MsError::StatsError { .. })
+        ));
+        assert_eq!(writer.num_replicates(), 0);
+    }
+}
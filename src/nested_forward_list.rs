@@ -8,6 +8,7 @@
 //! it is public in case anyone finds other uses for
 //! this data structure.
 
+use crate::MemoryUsage;
 use thiserror::Error;
 
 /// Errror type for [``NestedForwardList``] operations.
@@ -471,6 +472,43 @@ impl<Value> NestedForwardList<Value> {
     pub fn is_empty(&self) -> bool {
         self.head_.is_empty()
     }
+
+    /// Return the heap memory used by the lists.
+    ///
+    /// Memory owned by the elements themselves
+    /// is not counted.
+    ///
+    /// ```
+    /// type ListType = forrustts::nested_forward_list::NestedForwardList<i32>;
+    /// let mut l = ListType::new();
+    /// l.extend(1, 1).unwrap();
+    /// // 2 heads, 2 tails, 1 next, and 1 datum
+    /// assert_eq!(l.memory_usage().used, 6 * std::mem::size_of::<i32>());
+    /// ```
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage::of_vec(&self.head_, self.head_.capacity())
+            + MemoryUsage::of_vec(&self.tail_, self.tail_.capacity())
+            + MemoryUsage::of_vec(&self.next_, self.next_.capacity())
+            + MemoryUsage::of_vec(&self.data_, self.data_.capacity())
+    }
+
+    /// Release unused capacity.
+    ///
+    /// ```
+    /// type ListType = forrustts::nested_forward_list::NestedForwardList<i32>;
+    /// let mut l = ListType::new();
+    /// l.extend(100, 1).unwrap();
+    /// l.reset(1);
+    /// l.shrink_to_fit();
+    /// let usage = l.memory_usage();
+    /// assert_eq!(usage.used, usage.allocated);
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.head_.shrink_to_fit();
+        self.tail_.shrink_to_fit();
+        self.next_.shrink_to_fit();
+        self.data_.shrink_to_fit();
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,310 @@
+//! Output of marginal trees in
+//! [Newick](https://en.wikipedia.org/wiki/Newick_format) format.
+//!
+//! Only the parts of a [``Tree``] that are ancestral to its
+//! samples are written.
+//! Sample nodes are labelled `n{id}`, where `id` is the
+//! node's ``ID``.
+//! Internal nodes that are not samples are not labelled.
+//! Non-sample nodes with a single child are removed, and
+//! their branch lengths are added to that of their child.
+//!
+//! Branch lengths are the difference between the (forward)
+//! birth times of a child and its parent.
+//!
+//! A tree with more than one root is written as if the roots
+//! were the children of a node with no label.
+//!
+//! # Example
+//!
+//! ```
+//! use forrustts::newick::newick_trees;
+//! use forrustts::TableCollection;
+//!
+//! let mut tables = TableCollection::new(100).unwrap();
+//! tables.add_node(0, 0).unwrap();
+//! tables.add_node(3, 0).unwrap();
+//! for _ in 0..2 {
+//!     tables.add_node(10, 0).unwrap();
+//! }
+//! tables.add_edge(0, 50, 0, 2).unwrap();
+//! tables.add_edge(0, 50, 0, 3).unwrap();
+//! tables.add_edge(50, 100, 1, 2).unwrap();
+//! tables.add_edge(50, 100, 1, 3).unwrap();
+//!
+//! let trees = newick_trees(&tables, &[2, 3]).unwrap();
+//! assert_eq!(trees.len(), 2);
+//! assert_eq!(trees[0].left, 0);
+//! assert_eq!(trees[0].right, 50);
+//! assert_eq!(trees[0].newick, "(n2:10,n3:10);");
+//! assert_eq!(trees[1].newick, "(n2:7,n3:7);");
+//! ```
+
+use crate::tables::{TableCollection, TablesResult};
+use crate::trees::{Tree, TreeIterator};
+use crate::tsdef::{IdType, Position, Time};
+
+/// A marginal tree in Newick format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NewickTree {
+    /// Left edge of the genomic interval covered by the tree.
+    pub left: Position,
+    /// Right edge (exclusive) of the genomic interval
+    /// covered by the tree.
+    pub right: Position,
+    /// The tree.
+    pub newick: String,
+}
+
+// A node being written, along with the children
+// that are written below it.
+struct Frame {
+    node: IdType,
+    children: Vec<(IdType, Time)>,
+    next: usize,
+    parts: Vec<String>,
+}
+
+// Skip over non-sample nodes having a single child
+// with samples below it.
+fn collapse_unary(tree: &Tree, is_sample: &[bool], mut u: IdType) -> IdType {
+    while !is_sample[u as usize] {
+        let mut children = tree.children(u).filter(|c| tree.num_samples(*c) > 0);
+        match (children.next(), children.next()) {
+            (Some(c), None) => u = c,
+            _ => break,
+        }
+    }
+    u
+}
+
+fn make_frame(tree: &Tree, tables: &TableCollection, is_sample: &[bool], node: IdType) -> Frame {
+    let time = tables.node(node).time;
+    let children = tree
+        .children(node)
+        .filter(|c| tree.num_samples(*c) > 0)
+        .map(|c| {
+            let c = collapse_unary(tree, is_sample, c);
+            (c, tables.node(c).time - time)
+        })
+        .collect();
+    Frame {
+        node,
+        children,
+        next: 0,
+        parts: vec![],
+    }
+}
+
+fn label(is_sample: &[bool], u: IdType) -> String {
+    if is_sample[u as usize] {
+        format!("n{}", u)
+    } else {
+        String::new()
+    }
+}
+
+// Iterative, so that deep trees do not overflow the stack.
+fn subtree_to_newick(
+    tree: &Tree,
+    tables: &TableCollection,
+    is_sample: &[bool],
+    root: IdType,
+) -> String {
+    let mut stack = vec![make_frame(tree, tables, is_sample, root)];
+    loop {
+        let top = stack.last_mut().unwrap();
+        if top.next < top.children.len() {
+            let child = top.children[top.next].0;
+            top.next += 1;
+            let frame = make_frame(tree, tables, is_sample, child);
+            stack.push(frame);
+            continue;
+        }
+        let frame = stack.pop().unwrap();
+        let mut output = String::new();
+        if !frame.parts.is_empty() {
+            output = format!("({})", frame.parts.join(","));
+        }
+        output.push_str(&label(is_sample, frame.node));
+        match stack.last_mut() {
+            Some(parent) => {
+                let length = parent.children[parent.next - 1].1;
+                parent.parts.push(format!("{}:{}", output, length));
+            }
+            None => return output,
+        }
+    }
+}
+
+/// Write a [``Tree``] in Newick format.
+///
+/// # Parameters
+///
+/// * `tree`: the tree
+/// * `tables`: the tables used to build `tree`
+///
+/// # Panics
+///
+/// If `tables` is not the [``TableCollection``] used to build
+/// `tree`.
+pub fn tree_to_newick(tree: &Tree, tables: &TableCollection) -> String {
+    let mut is_sample = vec![false; tables.num_nodes()];
+    for s in tree.samples() {
+        is_sample[*s as usize] = true;
+    }
+    let roots: Vec<String> = tree
+        .roots()
+        .map(|r| {
+            let r = collapse_unary(tree, &is_sample, r);
+            subtree_to_newick(tree, tables, &is_sample, r)
+        })
+        .collect();
+    if roots.len() == 1 {
+        format!("{};", roots[0])
+    } else {
+        format!("({});", roots.join(","))
+    }
+}
+
+fn make_newick_tree(tree: &Tree, tables: &TableCollection) -> NewickTree {
+    let (left, right) = tree.interval();
+    NewickTree {
+        left,
+        right,
+        newick: tree_to_newick(tree, tables),
+    }
+}
+
+/// Write all marginal trees in Newick format.
+///
+/// # Parameters
+///
+/// * `tables`: the tables
+/// * `samples`: the sample nodes
+///
+/// # Errors
+///
+/// See [``TreeIterator::new_with_samples``].
+pub fn newick_trees(tables: &TableCollection, samples: &[IdType]) -> TablesResult<Vec<NewickTree>> {
+    let mut trees = TreeIterator::new_with_samples(tables, samples)?;
+    let mut rv = vec![];
+    while let Some(tree) = trees.next_tree() {
+        rv.push(make_newick_tree(tree, tables));
+    }
+    Ok(rv)
+}
+
+/// Write the marginal tree at `position` in Newick format.
+///
+/// # Parameters
+///
+/// * `tables`: the tables
+/// * `samples`: the sample nodes
+/// * `position`: the genomic position
+///
+/// # Errors
+///
+/// See [``TreeIterator::new_with_samples``] and
+/// [``TreeIterator::tree_at``].
+///
+/// # Example
+///
+/// ```
+/// use forrustts::newick::newick_at;
+/// use forrustts::TableCollection;
+///
+/// let mut tables = TableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap();
+/// tables.add_node(5, 0).unwrap();
+/// for _ in 0..3 {
+///     tables.add_node(10, 0).unwrap();
+/// }
+/// tables.add_edge(0, 100, 0, 1).unwrap();
+/// tables.add_edge(0, 100, 1, 2).unwrap();
+/// tables.add_edge(0, 100, 1, 3).unwrap();
+/// tables.add_edge(0, 100, 0, 4).unwrap();
+///
+/// let tree = newick_at(&tables, &[2, 3, 4], 25).unwrap();
+/// assert_eq!(tree.newick, "((n2:5,n3:5):5,n4:10);");
+/// ```
+pub fn newick_at(
+    tables: &TableCollection,
+    samples: &[IdType],
+    position: Position,
+) -> TablesResult<NewickTree> {
+    let mut trees = TreeIterator::new_with_samples(tables, samples)?;
+    let tree = trees.tree_at(position)?;
+    Ok(make_newick_tree(tree, tables))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::two_trees;
+    use crate::TablesError;
+
+    #[test]
+    fn test_all_trees() {
+        // Node 1 is an internal sample node.
+        let tables = two_trees();
+        let trees = newick_trees(&tables, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(
+            trees,
+            vec![
+                NewickTree {
+                    left: 0,
+                    right: 50,
+                    newick: "((n2:5,n3:5)n1:5,n4:10);".to_string()
+                },
+                NewickTree {
+                    left: 50,
+                    right: 100,
+                    newick: "((n2:5,n4:5)n1:5,n3:10);".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_subset_of_samples() {
+        // Node 1 has a single sample below it, so it is removed.
+        let tables = two_trees();
+        let tree = newick_at(&tables, &[2, 4], 10).unwrap();
+        assert_eq!(tree.newick, "(n2:10,n4:10);");
+        let tree = newick_at(&tables, &[2, 3], 60).unwrap();
+        assert_eq!(tree.newick, "(n2:10,n3:10);");
+        // Sample nodes are kept even if they have a single child.
+        let tree = newick_at(&tables, &[1, 2], 10).unwrap();
+        assert_eq!(tree.newick, "(n2:5)n1;");
+        let tree = newick_at(&tables, &[3], 99).unwrap();
+        assert_eq!(tree.newick, "n3;");
+    }
+
+    #[test]
+    fn test_multiple_roots() {
+        let mut tables = TableCollection::new(10).unwrap();
+        tables.add_node(0, 0).unwrap();
+        tables.add_node(1, 0).unwrap();
+        tables.add_node(1, 0).unwrap();
+        tables.add_node(2, 0).unwrap();
+        tables.add_edge(0, 5, 0, 1).unwrap();
+        tables.add_edge(0, 5, 0, 2).unwrap();
+        let trees = newick_trees(&tables, &[1, 2, 3]).unwrap();
+        assert_eq!(trees[0].newick, "(n3,(n1:1,n2:1));");
+        assert_eq!(trees[1].newick, "(n3,n2,n1);");
+    }
+
+    #[test]
+    fn test_errors() {
+        let tables = two_trees();
+        assert!(matches!(
+            newick_at(&tables, &[2], 100),
+            Err(TablesError::InvalidPosition { found: 100 })
+        ));
+        assert!(matches!(
+            newick_at(&tables, &[2, 2], 0),
+            Err(TablesError::InvalidNodeValue { found: 2 })
+        ));
+    }
+}
@@ -3,6 +3,7 @@ use crate::IdType;
 /// Information about samples used for
 /// table simpilfication.
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SamplesInfo {
     /// A list of sample IDs.
     /// Can include both "alive" and
@@ -40,3 +41,29 @@ mod test {
         assert!(s.edge_buffer_founder_nodes.is_empty());
     }
 }
+
+#[cfg(all(test, feature = "serde"))]
+mod test_serde {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut s = SamplesInfo::new();
+        s.samples = vec![1, 2, 3];
+        s.edge_buffer_founder_nodes = vec![4, 5];
+        let json = serde_json::to_string(&s).unwrap();
+        let s_json: SamplesInfo = serde_json::from_str(&json).unwrap();
+        assert_eq!(s_json.samples, s.samples);
+        assert_eq!(
+            s_json.edge_buffer_founder_nodes,
+            s.edge_buffer_founder_nodes
+        );
+        let encoded = bincode::serialize(&s).unwrap();
+        let s_bincode: SamplesInfo = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(s_bincode.samples, s.samples);
+        assert_eq!(
+            s_bincode.edge_buffer_founder_nodes,
+            s.edge_buffer_founder_nodes
+        );
+    }
+}
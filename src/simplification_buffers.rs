@@ -1,5 +1,6 @@
 use crate::simplification_logic::{AncestryList, SegmentOverlapper};
 use crate::tables::{EdgeTable, NodeTable};
+use crate::MemoryUsage;
 
 /// Holds internal memory used by
 /// simplification machinery.
@@ -37,4 +38,63 @@ impl SimplificationBuffers {
         self.temp_edge_buffer.clear();
         self.new_nodes.clear();
     }
+
+    /// Return the heap memory used by each buffer.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let buffers = forrustts::SimplificationBuffers::new();
+    /// assert_eq!(buffers.memory_usage().total().allocated, 0);
+    /// ```
+    pub fn memory_usage(&self) -> SimplificationBuffersMemoryUsage {
+        SimplificationBuffersMemoryUsage {
+            new_edges: MemoryUsage::of_vec(&self.new_edges, self.new_edges.capacity()),
+            temp_edge_buffer: MemoryUsage::of_vec(
+                &self.temp_edge_buffer,
+                self.temp_edge_buffer.capacity(),
+            ),
+            new_nodes: MemoryUsage::of_vec(&self.new_nodes, self.new_nodes.capacity()),
+            ancestry: self.ancestry.memory_usage(),
+            overlapper: self.overlapper.memory_usage(),
+        }
+    }
+
+    /// Release unused memory.
+    ///
+    /// The buffers keep their contents between calls
+    /// to simplification, so this function does not
+    /// release all memory.
+    /// Doing so requires a new instance.
+    pub fn shrink_to_fit(&mut self) {
+        self.new_edges.shrink_to_fit();
+        self.temp_edge_buffer.shrink_to_fit();
+        self.new_nodes.shrink_to_fit();
+        self.ancestry.shrink_to_fit();
+        self.overlapper.shrink_to_fit();
+    }
+}
+
+/// Heap memory used by [``SimplificationBuffers``].
+///
+/// See [``SimplificationBuffers::memory_usage``].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SimplificationBuffersMemoryUsage {
+    /// Output edges
+    pub new_edges: MemoryUsage,
+    /// Edges buffered for each parent
+    pub temp_edge_buffer: MemoryUsage,
+    /// Output nodes
+    pub new_nodes: MemoryUsage,
+    /// Ancestry segments of each node
+    pub ancestry: MemoryUsage,
+    /// Overlapping ancestry segments
+    pub overlapper: MemoryUsage,
+}
+
+impl SimplificationBuffersMemoryUsage {
+    /// The sum over all buffers.
+    pub fn total(&self) -> MemoryUsage {
+        self.new_edges + self.temp_edge_buffer + self.new_nodes + self.ancestry + self.overlapper
+    }
 }
@@ -1,52 +1,116 @@
 /// Common functions to reuse in various "simplify tables"
 /// functions
 use crate::simplification_logic;
-use crate::validate_edge_table;
+use crate::tables::{validate_edge_rows, EdgeRows, NodeRows};
 use crate::ForrusttsError;
 use crate::SamplesInfo;
 use crate::SimplificationBuffers;
 use crate::SimplificationFlags;
 use crate::SimplificationOutput;
-use crate::{IdType, NULL_ID};
-use crate::{Node, TableCollection};
+use crate::{Edge, EdgeTable, NodeTable, TableCollection};
+use crate::{IdType, Position, NULL_ID};
 
-pub fn validate_tables(
-    tables: &TableCollection,
+/// The tables that simplification reads from
+/// and writes to.
+///
+/// This lets simplification work on both
+/// [``TableCollection``] and
+/// [``ColumnarTableCollection``](crate::ColumnarTableCollection).
+pub(crate) trait SimplificationTables {
+    type Nodes: NodeRows + ?Sized;
+    type Edges: EdgeRows + ?Sized;
+
+    fn genome_length(&self) -> Position;
+    fn node_rows(&self) -> &Self::Nodes;
+    fn edge_rows(&self) -> &Self::Edges;
+    fn has_sites_or_mutations(&self) -> bool;
+
+    /// Overwrite edge `i`, which has already been processed.
+    fn set_edge(&mut self, i: usize, edge: Edge);
+    /// Keep the first `len` edges, then move in `new_edges`.
+    fn finish_edges(&mut self, len: usize, new_edges: &mut EdgeTable);
+    /// Move in `new_nodes`, replacing the node table.
+    fn replace_nodes(&mut self, new_nodes: &mut NodeTable);
+}
+
+impl SimplificationTables for TableCollection {
+    type Nodes = [crate::Node];
+    type Edges = [Edge];
+
+    fn genome_length(&self) -> Position {
+        TableCollection::genome_length(self)
+    }
+
+    fn node_rows(&self) -> &Self::Nodes {
+        &self.nodes_
+    }
+
+    fn edge_rows(&self) -> &Self::Edges {
+        &self.edges_
+    }
+
+    fn has_sites_or_mutations(&self) -> bool {
+        !self.sites_.is_empty() || !self.mutations_.is_empty()
+    }
+
+    fn set_edge(&mut self, i: usize, edge: Edge) {
+        self.edges_[i] = edge;
+    }
+
+    fn finish_edges(&mut self, len: usize, new_edges: &mut EdgeTable) {
+        self.edges_.truncate(len);
+        self.edges_.append(new_edges);
+        self.drop_indexes();
+    }
+
+    fn replace_nodes(&mut self, new_nodes: &mut NodeTable) {
+        std::mem::swap(&mut self.nodes_, new_nodes);
+    }
+}
+
+pub fn validate_tables<T: SimplificationTables + ?Sized>(
+    tables: &T,
     flags: &SimplificationFlags,
 ) -> Result<(), ForrusttsError> {
     if flags.contains(SimplificationFlags::VALIDATE_EDGES) {
-        validate_edge_table(tables.genome_length(), tables.edges(), tables.nodes())?;
+        validate_edge_rows(
+            tables.genome_length(),
+            tables.edge_rows(),
+            tables.node_rows(),
+        )?;
     }
     Ok(())
 }
 
-fn setup_idmap(nodes: &[Node], idmap: &mut Vec<IdType>) {
-    idmap.resize(nodes.len(), NULL_ID);
+fn setup_idmap(num_nodes: usize, idmap: &mut Vec<IdType>) {
+    idmap.resize(num_nodes, NULL_ID);
     idmap.iter_mut().for_each(|x| *x = NULL_ID);
 }
 
-pub fn setup_simplification(
+pub(crate) fn setup_simplification<T: SimplificationTables + ?Sized>(
     samples: &SamplesInfo,
-    tables: &TableCollection,
+    tables: &T,
     flags: SimplificationFlags,
     state: &mut SimplificationBuffers,
     output: &mut SimplificationOutput,
 ) -> Result<(), ForrusttsError> {
-    if !tables.sites_.is_empty() || !tables.mutations_.is_empty() {
+    if tables.has_sites_or_mutations() {
         return Err(ForrusttsError::SimplificationError {
             value: "mutation simplification not yet implemented".to_string(),
         });
     }
 
     validate_tables(tables, &flags)?;
-    setup_idmap(&tables.nodes_, &mut output.idmap);
+    let num_nodes = tables.node_rows().num_rows();
+    setup_idmap(num_nodes, &mut output.idmap);
 
     state.clear();
-    state.ancestry.reset(tables.num_nodes());
+    state.ancestry.reset(num_nodes);
 
     simplification_logic::record_sample_nodes(
         &samples.samples,
-        &tables,
+        tables.node_rows(),
+        tables.genome_length(),
         &mut state.new_nodes,
         &mut state.ancestry,
         &mut output.idmap,
@@ -55,15 +119,15 @@ pub fn setup_simplification(
     Ok(())
 }
 
-pub fn process_parent(
+pub(crate) fn process_parent<T: SimplificationTables + ?Sized>(
     u: IdType,
     (edge_index, num_edges): (usize, usize),
-    tables: &TableCollection,
+    tables: &T,
     state: &mut SimplificationBuffers,
     output: &mut SimplificationOutput,
 ) -> Result<usize, ForrusttsError> {
     let edge_i = simplification_logic::find_parent_child_segment_overlap(
-        &tables.edges_,
+        tables.edge_rows(),
         edge_index,
         num_edges,
         tables.genome_length(),
@@ -73,7 +137,7 @@ pub fn process_parent(
     )?;
 
     simplification_logic::merge_ancestors(
-        &tables.nodes_,
+        tables.node_rows(),
         tables.genome_length(),
         u,
         state,
@@ -81,3 +145,44 @@ pub fn process_parent(
     )?;
     Ok(edge_i)
 }
+
+/// Simplify sorted tables.
+///
+/// Output edges overwrite the input edges that have
+/// already been processed, so that the edge table
+/// is not copied.
+pub(crate) fn simplify_sorted_tables<T: SimplificationTables + ?Sized>(
+    samples: &SamplesInfo,
+    flags: SimplificationFlags,
+    state: &mut SimplificationBuffers,
+    tables: &mut T,
+    output: &mut SimplificationOutput,
+) -> Result<(), ForrusttsError> {
+    setup_simplification(samples, tables, flags, state, output)?;
+
+    let mut edge_i = 0;
+    let num_edges = tables.edge_rows().num_rows();
+    let mut new_edges_inserted: usize = 0;
+    while edge_i < num_edges {
+        edge_i = process_parent(
+            tables.edge_rows().edge_row(edge_i).parent,
+            (edge_i, num_edges),
+            tables,
+            state,
+            output,
+        )?;
+
+        if state.new_edges.len() >= 1024 && new_edges_inserted + state.new_edges.len() < edge_i {
+            for i in state.new_edges.drain(..) {
+                tables.set_edge(new_edges_inserted, i);
+                new_edges_inserted += 1;
+            }
+            assert_eq!(state.new_edges.len(), 0);
+        }
+    }
+
+    tables.finish_edges(new_edges_inserted, &mut state.new_edges);
+    tables.replace_nodes(&mut state.new_nodes);
+
+    Ok(())
+}
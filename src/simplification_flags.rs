@@ -11,6 +11,7 @@ bitflags! {
     /// assert_eq!(e.bits(), 0);
     /// ```
     #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct SimplificationFlags: u32 {
         /// Validate that input edges are sorted
         const VALIDATE_EDGES = 1 << 0;
@@ -4,6 +4,7 @@ use crate::simplification_buffers::SimplificationBuffers;
 use crate::tables::*;
 use crate::tsdef::{IdType, Position, NULL_ID};
 use crate::ForrusttsError;
+use crate::MemoryUsage;
 
 pub struct SegmentOverlapper {
     segment_queue: Vec<Segment>,
@@ -131,12 +132,22 @@ impl SegmentOverlapper {
     pub fn overlap(&self, i: usize) -> &Segment {
         &self.overlapping[i]
     }
+
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage::of_vec(&self.segment_queue, self.segment_queue.capacity())
+            + MemoryUsage::of_vec(&self.overlapping, self.overlapping.capacity())
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.segment_queue.shrink_to_fit();
+        self.overlapping.shrink_to_fit();
+    }
 }
 
 pub type AncestryList = NestedForwardList<Segment>;
 
-pub fn find_parent_child_segment_overlap(
-    edges: &[Edge],
+pub(crate) fn find_parent_child_segment_overlap<E: EdgeRows + ?Sized>(
+    edges: &E,
     edge_index: usize,
     num_edges: usize,
     maxlen: Position,
@@ -148,10 +159,13 @@ pub fn find_parent_child_segment_overlap(
 
     let mut i = edge_index;
 
-    while i < num_edges && edges[i].parent == u {
-        let edge = &edges[i];
+    while i < num_edges {
+        let edge = edges.edge_row(i);
+        if edge.parent != u {
+            break;
+        }
 
-        ancestry.for_each(edges[i].child, |seg: &Segment| {
+        ancestry.for_each(edge.child, |seg: &Segment| {
             if seg.right > edge.left && edge.right > seg.left {
                 overlapper.enqueue(
                     std::cmp::max(seg.left, edge.left),
@@ -241,8 +255,8 @@ fn output_buffered_edges(temp_edge_buffer: &mut EdgeTable, new_edges: &mut EdgeT
     rv
 }
 
-pub fn merge_ancestors(
-    input_nodes: &[Node],
+pub(crate) fn merge_ancestors<N: NodeRows + ?Sized>(
+    input_nodes: &N,
     maxlen: Position,
     parent_input_id: IdType,
     state: &mut SimplificationBuffers,
@@ -275,9 +289,12 @@ pub fn merge_ancestors(
             }
         } else {
             if output_id == NULL_ID {
+                // This node is not in the sample list,
+                // so it cannot remain a sample.
+                let n = input_nodes.node_row(parent_input_id as usize);
                 state.new_nodes.push(Node {
-                    time: input_nodes[parent_input_id as usize].time,
-                    deme: input_nodes[parent_input_id as usize].deme,
+                    flags: n.flags & !NodeFlags::IS_SAMPLE,
+                    ..n
                 });
                 output_id = (state.new_nodes.len() - 1) as IdType;
                 idmap[parent_input_id as usize] = output_id;
@@ -334,9 +351,10 @@ pub fn merge_ancestors(
     Ok(())
 }
 
-pub fn record_sample_nodes(
+pub(crate) fn record_sample_nodes<N: NodeRows + ?Sized>(
     samples: &[IdType],
-    tables: &TableCollection,
+    input_nodes: &N,
+    genome_length: Position,
     new_nodes: &mut NodeTable,
     ancestry: &mut AncestryList,
     idmap: &mut [IdType],
@@ -354,16 +372,16 @@ pub fn record_sample_nodes(
                 value: "invalid sample list!".to_string(),
             });
         }
-        let n = tables.node(*sample);
+        let n = input_nodes.node_row(*sample as usize);
         new_nodes.push(Node {
-            time: n.time,
-            deme: n.deme,
+            flags: n.flags | NodeFlags::IS_SAMPLE,
+            ..n
         });
 
         add_ancestry(
             *sample,
             0,
-            tables.genome_length(),
+            genome_length,
             (new_nodes.len() - 1) as IdType,
             ancestry,
         )?;
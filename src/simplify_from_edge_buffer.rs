@@ -179,7 +179,7 @@ pub fn simplify_from_edge_buffer(
             process_births_from_buffer(head, edge_buffer, state)?;
             state.overlapper.finalize_queue(tables.genome_length());
             simplification_logic::merge_ancestors(
-                &tables.nodes(),
+                tables.nodes_.as_slice(),
                 tables.genome_length(),
                 head,
                 state,
@@ -204,7 +204,7 @@ pub fn simplify_from_edge_buffer(
             edge_i = process_parent(
                 tables.edges_[edge_i].parent,
                 (edge_i, num_edges),
-                &tables,
+                &*tables,
                 state,
                 output,
             )?;
@@ -217,7 +217,7 @@ pub fn simplify_from_edge_buffer(
                 edge_i = process_parent(
                     tables.edges_[edge_i].parent,
                     (edge_i, num_edges),
-                    &tables,
+                    &*tables,
                     state,
                     output,
                 )?;
@@ -253,7 +253,7 @@ pub fn simplify_from_edge_buffer(
         process_births_from_buffer(ex.parent, edge_buffer, state)?;
         state.overlapper.finalize_queue(tables.genome_length());
         simplification_logic::merge_ancestors(
-            &tables.nodes_,
+            tables.nodes_.as_slice(),
             tables.genome_length(),
             ex.parent,
             state,
@@ -266,7 +266,7 @@ pub fn simplify_from_edge_buffer(
         edge_i = process_parent(
             tables.edges_[edge_i].parent,
             (edge_i, num_edges),
-            &tables,
+            &*tables,
             state,
             output,
         )?;
@@ -274,6 +274,7 @@ pub fn simplify_from_edge_buffer(
 
     std::mem::swap(&mut tables.edges_, &mut state.new_edges);
     std::mem::swap(&mut tables.nodes_, &mut state.new_nodes);
+    tables.drop_indexes();
     edge_buffer.reset(tables.num_nodes());
 
     Ok(())
@@ -55,6 +55,12 @@ pub fn simplify_tables_without_state(
 ///
 /// The input tables must be sorted.
 /// See [``TableCollection::sort_tables_for_simplification``].
+///
+/// The [``NodeFlags``] of each sample node are kept,
+/// and [``NodeFlags::IS_SAMPLE``] is set.
+/// Other nodes kept by simplification keep their
+/// flags, except for [``NodeFlags::IS_SAMPLE``],
+/// which is cleared.
 pub fn simplify_tables(
     samples: &SamplesInfo,
     flags: SimplificationFlags,
@@ -62,34 +68,7 @@ pub fn simplify_tables(
     tables: &mut TableCollection,
     output: &mut SimplificationOutput,
 ) -> Result<(), ForrusttsError> {
-    setup_simplification(samples, tables, flags, state, output)?;
-
-    let mut edge_i = 0;
-    let num_edges = tables.num_edges();
-    let mut new_edges_inserted: usize = 0;
-    while edge_i < num_edges {
-        edge_i = process_parent(
-            tables.edges_[edge_i].parent,
-            (edge_i, num_edges),
-            &tables,
-            state,
-            output,
-        )?;
-
-        if state.new_edges.len() >= 1024 && new_edges_inserted + state.new_edges.len() < edge_i {
-            for i in state.new_edges.drain(..) {
-                tables.edges_[new_edges_inserted] = i;
-                new_edges_inserted += 1;
-            }
-            assert_eq!(state.new_edges.len(), 0);
-        }
-    }
-
-    tables.edges_.truncate(new_edges_inserted);
-    tables.edges_.append(&mut state.new_edges);
-    std::mem::swap(&mut tables.nodes_, &mut state.new_nodes);
-
-    Ok(())
+    simplify_sorted_tables(samples, flags, state, tables, output)
 }
 
 #[cfg(test)]
@@ -130,4 +109,76 @@ mod tests {
             |_| panic!(),
         );
     }
+
+    #[test]
+    fn test_simplify_tables_node_flags() {
+        let mut tables = TableCollection::new(100).unwrap();
+        let preserved = NodeFlags::IS_PRESERVED | NodeFlags::IS_SAMPLE;
+        tables.add_node_with_flags(0, 0, preserved).unwrap(); // grandparent
+        tables.add_node(1, 0).unwrap(); // parent
+        tables.add_node_with_flags(1, 0, preserved).unwrap(); // parent
+        tables
+            .add_node_with_flags(2, 0, NodeFlags::IS_ALIVE)
+            .unwrap(); // child
+        tables
+            .add_node_with_flags(2, 0, NodeFlags::IS_ALIVE)
+            .unwrap(); // child
+        tables.add_edge(0, 100, 0, 1).unwrap();
+        tables.add_edge(0, 100, 0, 2).unwrap();
+        tables.add_edge(0, 100, 1, 3).unwrap();
+        tables.add_edge(0, 100, 1, 4).unwrap();
+        tables.sort_tables(TableSortingFlags::empty());
+        tables.build_indexes().unwrap();
+
+        let mut samples = SamplesInfo::new();
+        samples.samples = vec![3, 4];
+        let mut output = SimplificationOutput::new();
+        simplify_tables_without_state(
+            &samples,
+            SimplificationFlags::VALIDATE_ALL,
+            &mut tables,
+            &mut output,
+        )
+        .unwrap();
+
+        // Simplification changes the edge table.
+        assert!(!tables.has_indexes());
+        // The samples keep their flags and are flagged as samples.
+        for node in [3, 4].iter() {
+            assert_eq!(
+                tables.node(output.idmap[*node]).flags,
+                NodeFlags::IS_SAMPLE | NodeFlags::IS_ALIVE
+            );
+        }
+        // The MRCA is kept, but is no longer a sample.
+        assert_eq!(tables.num_nodes(), 3);
+        assert_eq!(tables.node(output.idmap[1]).flags, NodeFlags::empty());
+        assert_eq!(output.idmap[0], crate::NULL_ID);
+        assert_eq!(output.idmap[2], crate::NULL_ID);
+
+        // Simplify again, keeping a preserved node as a sample.
+        let mut tables2 = TableCollection::new(100).unwrap();
+        tables2.add_node_with_flags(0, 0, preserved).unwrap();
+        tables2
+            .add_node_with_flags(1, 0, NodeFlags::IS_ALIVE)
+            .unwrap();
+        tables2
+            .add_node_with_flags(1, 0, NodeFlags::IS_ALIVE)
+            .unwrap();
+        tables2.add_edge(0, 100, 0, 1).unwrap();
+        tables2.add_edge(0, 100, 0, 2).unwrap();
+        samples.samples = vec![0, 1, 2];
+        simplify_tables_without_state(
+            &samples,
+            SimplificationFlags::VALIDATE_ALL,
+            &mut tables2,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(tables2.node(output.idmap[0]).flags, preserved);
+        assert_eq!(
+            tables2.node(output.idmap[1]).flags,
+            NodeFlags::IS_SAMPLE | NodeFlags::IS_ALIVE
+        );
+    }
 }
@@ -0,0 +1,960 @@
+//! Summary statistics calculated from a [``TableCollection``].
+//!
+//! Statistics are computed without exporting to ``tskit``,
+//! using [``TreeIterator``] to visit the marginal trees.
+//!
+//! # Modes
+//!
+//! Statistics are calculated in one of two modes (see [``StatsMode``]).
+//! In [``StatsMode::Site``], they are calculated from the
+//! allele counts at each site.
+//! In [``StatsMode::Branch``], they are calculated from
+//! the branch lengths of the marginal trees, giving
+//! the expected value of the site statistic per unit mutation rate.
+//! Branch lengths are differences in forward [``Time``](crate::Time).
+//! Only branches ancestral to at least one sample
+//! (in any sample set) are counted.
+//!
+//! # Sample sets and windows
+//!
+//! Statistics are calculated for each of a list of sample sets.
+//! Any type that implements ``AsRef<[IdType]>`` may be used
+//! for a sample set.
+//! Sample sets may overlap, but each must be non-empty and cannot
+//! contain the same node twice.
+//!
+//! Windows are given as a sorted list of breakpoints
+//! starting at `0` and ending at the genome length.
+//! Passing [``None``] uses a single window covering the genome.
+//! By default, values are divided by the length of each window.
+//! See [``StatsFlags``].
+//!
+//! The results are indexed first by window and then
+//! by sample set.
+//!
+//! # Requirements
+//!
+//! The edge table does not need to be sorted.
+//! Every mutation must refer to a valid site and node.
+//! At a site with more than one mutation, a mutation on the
+//! same node as another mutation is taken to be the more recent one
+//! if it comes later in the mutation table.
+//! Derived states are compared to identify alleles.
+//! Missing states ([``None``]) never match any other state.
+//!
+//! # Example
+//!
+//! ```
+//! use forrustts::stats::*;
+//! use forrustts::{NodeFlags, TableCollection};
+//!
+//! let mut tables = TableCollection::new(100).unwrap();
+//! tables.add_node(0, 0).unwrap();
+//! for _ in 0..4 {
+//!     tables
+//!         .add_node_with_flags(10, 0, NodeFlags::IS_SAMPLE)
+//!         .unwrap();
+//! }
+//! for c in 1..5 {
+//!     tables.add_edge(0, 100, 0, c).unwrap();
+//! }
+//! let site = tables.add_site(50, Some(vec![0])).unwrap();
+//! tables
+//!     .add_mutation(1, 0, site as usize, Some(vec![1]), true)
+//!     .unwrap();
+//!
+//! let samples = tables.sample_nodes();
+//! let pi = diversity(
+//!     &tables,
+//!     &[&samples],
+//!     None,
+//!     StatsMode::Site,
+//!     StatsFlags::NO_SPAN_NORMALISE,
+//! )
+//! .unwrap();
+//! assert!((pi[0][0] - 0.5).abs() < 1e-12);
+//! ```
+
+use crate::tables::*;
+use crate::trees::TreeIterator;
+use crate::tsdef::{IdType, Position, NULL_ID};
+use bitflags::bitflags;
+use thiserror::Error;
+
+mod divergence;
+mod ld;
+mod sfs;
+mod tmrca;
+mod variants;
+
+pub use divergence::{divergence, f2, f3, f4, fst, sample_sets_by_deme};
+pub use ld::{ld_decay, ld_matrix, LdDecayBin, LdMatrix, LdStat};
+pub use sfs::{allele_frequency_spectrum, FrequencySpectrum};
+pub use tmrca::{mrca, pairwise_coalescence_times, tmrca, PairwiseCoalescenceTimes};
+pub use variants::{GenotypeMatrix, Variant, VariantIterator};
+
+/// Error type for the [``stats``](crate::stats) module.
+#[derive(Error, Debug, PartialEq)]
+pub enum StatsError {
+    /// A redirection of a [``TablesError``].
+    #[error("{value:?}")]
+    TablesError {
+        /// The redirected error
+        #[from]
+        value: TablesError,
+    },
+    /// Raised when the window breakpoints are invalid.
+    #[error("Invalid windows")]
+    InvalidWindows,
+    /// Raised when no sample sets are given,
+    /// or when a sample set is empty.
+    #[error("Empty sample set")]
+    EmptySampleSet,
+    /// Raised when a sample set contains a node more than once.
+    #[error("Duplicate sample: {found:?}")]
+    DuplicateSample {
+        /// The repeated node
+        found: IdType,
+    },
+    /// Raised when an index into the list of
+    /// sample sets is out of range.
+    #[error("Invalid sample set index: {found:?}")]
+    InvalidSampleSetIndex {
+        /// The invalid index
+        found: usize,
+    },
+    /// Raised when a mutation refers to a site that does not exist.
+    #[error("Invalid site: {found:?}")]
+    InvalidSite {
+        /// The invalid site index
+        found: usize,
+    },
+}
+
+/// Result type for the [``stats``](crate::stats) module.
+pub type StatsResult<T> = Result<T, StatsError>;
+
+/// How statistics are calculated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StatsMode {
+    /// Use the allele counts at each site.
+    Site,
+    /// Use the branch lengths of each tree.
+    Branch,
+}
+
+bitflags! {
+    /// Modifies the behavior of statistics calculations.
+    ///
+    /// ```
+    /// let f = forrustts::stats::StatsFlags::default();
+    /// assert!(f.is_empty());
+    /// ```
+    #[derive(Default)]
+    pub struct StatsFlags: u32 {
+        /// Do not divide values by window length.
+        const NO_SPAN_NORMALISE = 1 << 0;
+        /// Distinguish ancestral from derived alleles.
+        /// Used by [``allele_frequency_spectrum``] to return
+        /// the unfolded spectrum.
+        const POLARISED = 1 << 1;
+    }
+}
+
+/// Validated sample sets.
+pub(crate) struct SampleSets {
+    /// Number of nodes in each set.
+    pub(crate) sizes: Vec<f64>,
+    /// `membership[u * num_sets + i]` is `1` if node
+    /// `u` is in set `i`.
+    pub(crate) membership: Vec<f64>,
+    /// The union of all sets, sorted.
+    pub(crate) all_samples: Vec<IdType>,
+}
+
+impl SampleSets {
+    pub(crate) fn new<S: AsRef<[IdType]>>(
+        tables: &TableCollection,
+        sample_sets: &[S],
+    ) -> StatsResult<Self> {
+        if sample_sets.is_empty() {
+            return Err(StatsError::EmptySampleSet);
+        }
+        let num_nodes = tables.num_nodes();
+        let k = sample_sets.len();
+        let mut membership = vec![0.0; num_nodes * k];
+        let mut all_samples = vec![];
+        for (i, set) in sample_sets.iter().map(|s| s.as_ref()).enumerate() {
+            if set.is_empty() {
+                return Err(StatsError::EmptySampleSet);
+            }
+            for &s in set.iter() {
+                if s < 0 || s as usize >= num_nodes {
+                    return Err(TablesError::NodeOutOfBounds.into());
+                }
+                let m = &mut membership[s as usize * k + i];
+                if *m > 0.0 {
+                    return Err(StatsError::DuplicateSample { found: s });
+                }
+                *m = 1.0;
+                all_samples.push(s);
+            }
+        }
+        all_samples.sort_unstable();
+        all_samples.dedup();
+        Ok(SampleSets {
+            sizes: sample_sets
+                .iter()
+                .map(|s| s.as_ref().len() as f64)
+                .collect(),
+            membership,
+            all_samples,
+        })
+    }
+
+    pub(crate) fn num_sets(&self) -> usize {
+        self.sizes.len()
+    }
+}
+
+/// Return the window breakpoints.
+pub(crate) fn make_windows(
+    windows: Option<&[Position]>,
+    genome_length: Position,
+) -> StatsResult<Vec<Position>> {
+    match windows {
+        None => Ok(vec![0, genome_length]),
+        Some(w) => {
+            if w.len() < 2
+                || w[0] != 0
+                || *w.last().unwrap() != genome_length
+                || w.windows(2).any(|x| x[0] >= x[1])
+            {
+                return Err(StatsError::InvalidWindows);
+            }
+            Ok(w.to_vec())
+        }
+    }
+}
+
+/// Site ids sorted by position, and the mutations at each site.
+fn index_sites(tables: &TableCollection) -> StatsResult<(Vec<usize>, Vec<Vec<usize>>)> {
+    let num_nodes = tables.num_nodes();
+    let mut site_mutations = vec![vec![]; tables.sites().len()];
+    for (i, m) in tables.mutations().iter().enumerate() {
+        if m.site >= site_mutations.len() {
+            return Err(StatsError::InvalidSite { found: m.site });
+        }
+        if m.node < 0 || m.node as usize >= num_nodes {
+            return Err(TablesError::NodeOutOfBounds.into());
+        }
+        site_mutations[m.site].push(i);
+    }
+    for s in tables.sites() {
+        if s.position < 0 || s.position >= tables.genome_length() {
+            return Err(TablesError::InvalidPosition { found: s.position }.into());
+        }
+    }
+    let mut order: Vec<usize> = (0..tables.sites().len()).collect();
+    order.sort_by_key(|&i| tables.site(i as IdType).position);
+    Ok((order, site_mutations))
+}
+
+/// Parameters of a call to [``general_stat``].
+pub(crate) struct GeneralStat<'a> {
+    pub(crate) mode: StatsMode,
+    pub(crate) flags: StatsFlags,
+    /// Number of values returned per window.
+    pub(crate) output_dim: usize,
+    /// If `true`, the ancestral allele is skipped (site mode)
+    /// and the complement of each branch is not counted
+    /// (branch mode).
+    pub(crate) polarised: bool,
+    /// Given the count of an allele in each sample set,
+    /// write `output_dim` values.
+    pub(crate) summary: &'a dyn Fn(&[f64], &mut [f64]),
+}
+
+/// Sum a summary function over all alleles (site mode)
+/// or branches (branch mode) within each window.
+///
+/// In branch mode, each branch is weighted by its length
+/// and by the span of its tree within the window.
+/// Unless polarised, the summary is also applied to the
+/// number of samples in each set that are not below
+/// the branch (or that do not carry the allele).
+pub(crate) fn general_stat(
+    tables: &TableCollection,
+    sample_sets: &SampleSets,
+    windows: &[Position],
+    stat: &GeneralStat,
+) -> StatsResult<Vec<Vec<f64>>> {
+    let (site_order, site_mutations) = index_sites(tables)?;
+    let k = sample_sets.num_sets();
+    let dim = stat.output_dim;
+    let num_windows = windows.len() - 1;
+    let mut rv = vec![vec![0.0; dim]; num_windows];
+
+    let mut counts = vec![0.0; tables.num_nodes() * k];
+    let mut order: Vec<IdType> = vec![];
+    let mut stack: Vec<IdType> = vec![];
+    let mut x = vec![0.0; k];
+    let mut out = vec![0.0; dim];
+    let mut tree_total = vec![0.0; dim];
+    let mut next_site = 0;
+    let mut site_window = 0;
+
+    let mut trees = TreeIterator::new_with_samples(tables, &sample_sets.all_samples)?;
+    while let Some(tree) = trees.next_tree() {
+        let (left, right) = tree.interval();
+
+        // Preorder traversal, then accumulate counts from the leaves up.
+        order.clear();
+        stack.extend(tree.roots());
+        while let Some(u) = stack.pop() {
+            order.push(u);
+            stack.extend(tree.children(u));
+        }
+        for &u in order.iter().rev() {
+            let ui = u as usize;
+            for i in 0..k {
+                counts[ui * k + i] += sample_sets.membership[ui * k + i];
+            }
+            let p = tree.parent(u);
+            if p != NULL_ID {
+                for i in 0..k {
+                    counts[p as usize * k + i] += counts[ui * k + i];
+                }
+            }
+        }
+
+        match stat.mode {
+            StatsMode::Branch => {
+                tree_total.iter_mut().for_each(|t| *t = 0.0);
+                for &u in &order {
+                    let p = tree.parent(u);
+                    if p == NULL_ID {
+                        continue;
+                    }
+                    let length = (tables.node(u).time - tables.node(p).time) as f64;
+                    let ucounts = &counts[u as usize * k..(u as usize + 1) * k];
+                    add_summary(stat, ucounts, length, &mut out, &mut tree_total);
+                    if !stat.polarised {
+                        for i in 0..k {
+                            x[i] = sample_sets.sizes[i] - ucounts[i];
+                        }
+                        add_summary(stat, &x, length, &mut out, &mut tree_total);
+                    }
+                }
+                for (w, values) in rv.iter_mut().enumerate() {
+                    let overlap =
+                        std::cmp::min(right, windows[w + 1]) - std::cmp::max(left, windows[w]);
+                    if overlap > 0 {
+                        for (v, t) in values.iter_mut().zip(&tree_total) {
+                            *v += t * overlap as f64;
+                        }
+                    }
+                }
+            }
+            StatsMode::Site => {
+                while next_site < site_order.len()
+                    && tables.site(site_order[next_site] as IdType).position < right
+                {
+                    let site = site_order[next_site];
+                    let position = tables.site(site as IdType).position;
+                    while windows[site_window + 1] <= position {
+                        site_window += 1;
+                    }
+                    site_stat(
+                        tables,
+                        tree.parents(),
+                        site,
+                        &site_mutations[site],
+                        &counts,
+                        sample_sets,
+                        stat,
+                        &mut rv[site_window],
+                    );
+                    next_site += 1;
+                }
+            }
+        }
+
+        for &u in &order {
+            for i in 0..k {
+                counts[u as usize * k + i] = 0.0;
+            }
+        }
+    }
+
+    if !stat.flags.contains(StatsFlags::NO_SPAN_NORMALISE) {
+        for (w, values) in rv.iter_mut().enumerate() {
+            let span = (windows[w + 1] - windows[w]) as f64;
+            values.iter_mut().for_each(|v| *v /= span);
+        }
+    }
+    Ok(rv)
+}
+
+fn add_summary(stat: &GeneralStat, x: &[f64], weight: f64, out: &mut [f64], total: &mut [f64]) {
+    out.iter_mut().for_each(|o| *o = 0.0);
+    (stat.summary)(x, out);
+    for (t, o) in total.iter_mut().zip(out.iter()) {
+        *t += weight * o;
+    }
+}
+
+/// Apply the summary function to the alleles at a site.
+#[allow(clippy::too_many_arguments)]
+fn site_stat(
+    tables: &TableCollection,
+    parents: &[IdType],
+    site: usize,
+    mutations: &[usize],
+    counts: &[f64],
+    sample_sets: &SampleSets,
+    stat: &GeneralStat,
+    values: &mut [f64],
+) {
+    let k = sample_sets.num_sets();
+    let node_counts = |u: IdType| &counts[u as usize * k..(u as usize + 1) * k];
+
+    // The counts of each mutation's allele are the counts below
+    // its node, minus those below any more recent mutations
+    // that descend from it.
+    let mut allele_counts: Vec<Vec<f64>> = mutations
+        .iter()
+        .map(|&m| node_counts(tables.mutation(m as IdType).node).to_vec())
+        .collect();
+    for (j, &m) in mutations.iter().enumerate() {
+        if let Some(pj) = parent_mutation(tables, parents, mutations, j) {
+            let c = node_counts(tables.mutation(m as IdType).node);
+            for i in 0..k {
+                allele_counts[pj][i] -= c[i];
+            }
+        }
+    }
+
+    // Group mutations into alleles by state.
+    // The ancestral allele is first.
+    let mut states: Vec<Option<&[u8]>> =
+        vec![tables.site(site as IdType).ancestral_state.as_deref()];
+    let mut alleles: Vec<Vec<f64>> = vec![sample_sets.sizes.clone()];
+    for (j, &m) in mutations.iter().enumerate() {
+        for i in 0..k {
+            alleles[0][i] -= allele_counts[j][i];
+        }
+        let state = tables.mutation(m as IdType).derived_state.as_deref();
+        let existing = match state {
+            Some(_) => states.iter().position(|s| s.is_some() && *s == state),
+            None => None,
+        };
+        match existing {
+            Some(a) => {
+                for i in 0..k {
+                    alleles[a][i] += allele_counts[j][i];
+                }
+            }
+            None => {
+                states.push(state);
+                alleles.push(allele_counts[j].clone());
+            }
+        }
+    }
+
+    let mut out = vec![0.0; stat.output_dim];
+    let first = if stat.polarised { 1 } else { 0 };
+    for a in &alleles[first..] {
+        add_summary(stat, a, 1.0, &mut out, values);
+    }
+}
+
+/// The index (in `mutations`) of the closest
+/// older mutation above mutation `j` at the same site.
+fn parent_mutation(
+    tables: &TableCollection,
+    parents: &[IdType],
+    mutations: &[usize],
+    j: usize,
+) -> Option<usize> {
+    let node = tables.mutation(mutations[j] as IdType).node;
+    let on_node = |u: IdType, end: usize| {
+        (0..end)
+            .rev()
+            .find(|&i| tables.mutation(mutations[i] as IdType).node == u)
+    };
+    if let Some(i) = on_node(node, j) {
+        return Some(i);
+    }
+    let mut u = parents[node as usize];
+    while u != NULL_ID {
+        if let Some(i) = on_node(u, mutations.len()) {
+            return Some(i);
+        }
+        u = parents[u as usize];
+    }
+    None
+}
+
+/// Apply a summary function to each sample set separately.
+fn one_way_stat<S: AsRef<[IdType]>>(
+    tables: &TableCollection,
+    sample_sets: &[S],
+    windows: Option<&[Position]>,
+    mode: StatsMode,
+    flags: StatsFlags,
+    summary: &dyn Fn(f64, f64) -> f64,
+) -> StatsResult<Vec<Vec<f64>>> {
+    let sets = SampleSets::new(tables, sample_sets)?;
+    let windows = make_windows(windows, tables.genome_length())?;
+    let sizes = sets.sizes.clone();
+    let f = move |x: &[f64], out: &mut [f64]| {
+        for i in 0..x.len() {
+            out[i] = summary(x[i], sizes[i]);
+        }
+    };
+    let stat = GeneralStat {
+        mode,
+        flags,
+        output_dim: sets.num_sets(),
+        polarised: false,
+        summary: &f,
+    };
+    general_stat(tables, &sets, &windows, &stat)
+}
+
+/// Nucleotide diversity.
+///
+/// The mean number of differences between pairs of distinct
+/// samples from each sample set.
+///
+/// # Parameters
+///
+/// * `tables`: the tables
+/// * `sample_sets`: the sample sets
+/// * `windows`: window breakpoints, or [``None``] for a single window
+/// * `mode`: see [``StatsMode``]
+/// * `flags`: see [``StatsFlags``]
+///
+/// # Returns
+///
+/// One value per window and sample set.
+/// The value is `NaN` for a sample set of size one.
+///
+/// # Errors
+///
+/// [``StatsError``] if the sample sets, windows, or tables
+/// are invalid.
+pub fn diversity<S: AsRef<[IdType]>>(
+    tables: &TableCollection,
+    sample_sets: &[S],
+    windows: Option<&[Position]>,
+    mode: StatsMode,
+    flags: StatsFlags,
+) -> StatsResult<Vec<Vec<f64>>> {
+    one_way_stat(tables, sample_sets, windows, mode, flags, &|x, n| {
+        x * (n - x) / (n * (n - 1.))
+    })
+}
+
+/// The number of segregating sites.
+///
+/// A site with `k` alleles present in a sample set contributes `k - 1`.
+/// In branch mode, this is the total length of branches
+/// that have some, but not all, of a sample set below them.
+///
+/// See [``diversity``] for the parameters.
+///
+/// # Errors
+///
+/// [``StatsError``] if the sample sets, windows, or tables
+/// are invalid.
+pub fn segregating_sites<S: AsRef<[IdType]>>(
+    tables: &TableCollection,
+    sample_sets: &[S],
+    windows: Option<&[Position]>,
+    mode: StatsMode,
+    flags: StatsFlags,
+) -> StatsResult<Vec<Vec<f64>>> {
+    one_way_stat(tables, sample_sets, windows, mode, flags, &|x, n| {
+        if x > 0. {
+            1. - x / n
+        } else {
+            0.
+        }
+    })
+}
+
+fn harmonic_number(n: f64, power: i32) -> f64 {
+    (1..n as usize).map(|i| 1. / (i as f64).powi(power)).sum()
+}
+
+/// Watterson's estimator of theta.
+///
+/// The number of segregating sites divided by
+/// `a_n`, the harmonic number of `n - 1` for a sample set
+/// of size `n`.
+///
+/// See [``diversity``] for the parameters.
+///
+/// # Errors
+///
+/// [``StatsError``] if the sample sets, windows, or tables
+/// are invalid.
+pub fn watterson_theta<S: AsRef<[IdType]>>(
+    tables: &TableCollection,
+    sample_sets: &[S],
+    windows: Option<&[Position]>,
+    mode: StatsMode,
+    flags: StatsFlags,
+) -> StatsResult<Vec<Vec<f64>>> {
+    let mut rv = segregating_sites(tables, sample_sets, windows, mode, flags)?;
+    for values in rv.iter_mut() {
+        for (v, s) in values.iter_mut().zip(sample_sets) {
+            *v /= harmonic_number(s.as_ref().len() as f64, 1);
+        }
+    }
+    Ok(rv)
+}
+
+/// Tajima's D.
+///
+/// Calculated from [``diversity``] and [``segregating_sites``]
+/// using Tajima (1989).
+/// The value is `NaN` when there are no
+/// segregating sites or a sample set has fewer than four samples.
+///
+/// See [``diversity``] for the parameters.
+/// Unlike the other statistics, this function takes no
+/// [``StatsFlags``].
+/// The variance in the denominator of D assumes that the
+/// number of segregating sites is a count, so both parts
+/// are always calculated with
+/// [``StatsFlags::NO_SPAN_NORMALISE``].
+/// Dividing them by the window length would change D.
+///
+/// # Errors
+///
+/// [``StatsError``] if the sample sets, windows, or tables
+/// are invalid.
+pub fn tajimas_d<S: AsRef<[IdType]>>(
+    tables: &TableCollection,
+    sample_sets: &[S],
+    windows: Option<&[Position]>,
+    mode: StatsMode,
+) -> StatsResult<Vec<Vec<f64>>> {
+    let flags = StatsFlags::NO_SPAN_NORMALISE;
+    let pi = diversity(tables, sample_sets, windows, mode, flags)?;
+    let mut rv = segregating_sites(tables, sample_sets, windows, mode, flags)?;
+    for (values, pi) in rv.iter_mut().zip(pi) {
+        for ((v, pi), s) in values.iter_mut().zip(pi).zip(sample_sets) {
+            *v = tajimas_d_from_parts(pi, *v, s.as_ref().len() as f64);
+        }
+    }
+    Ok(rv)
+}
+
+fn tajimas_d_from_parts(pi: f64, segsites: f64, n: f64) -> f64 {
+    if n < 4. {
+        return f64::NAN;
+    }
+    let a1 = harmonic_number(n, 1);
+    let a2 = harmonic_number(n, 2);
+    let b1 = (n + 1.) / (3. * (n - 1.));
+    let b2 = 2. * (n * n + n + 3.) / (9. * n * (n - 1.));
+    let c1 = b1 - 1. / a1;
+    let c2 = b2 - (n + 2.) / (a1 * n) + a2 / (a1 * a1);
+    let e1 = c1 / a1;
+    let e2 = c2 / (a1 * a1 + a2);
+    (pi - segsites / a1) / (e1 * segsites + e2 * segsites * (segsites - 1.)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::*;
+    use crate::wright_fisher::*;
+
+    /// Mean pairwise differences, by comparing haplotypes.
+    fn naive_site_diversity(tables: &TableCollection, samples: &[IdType]) -> f64 {
+        let mut haplotypes = vec![vec![]; samples.len()];
+        let mut tree_iter = TreeIterator::new_with_samples(tables, samples).unwrap();
+        let mut sites: Vec<usize> = (0..tables.sites().len()).collect();
+        sites.sort_by_key(|&s| tables.site(s as IdType).position);
+        let mut next = 0;
+        while let Some(tree) = tree_iter.next_tree() {
+            while next < sites.len()
+                && tables.site(sites[next] as IdType).position < tree.interval().1
+            {
+                for (h, &s) in haplotypes.iter_mut().zip(samples) {
+                    // The allele is the most recent mutation above the sample.
+                    let mut allele = None;
+                    let mut u = s;
+                    while u != NULL_ID && allele.is_none() {
+                        allele = tables
+                            .mutations()
+                            .iter()
+                            .enumerate()
+                            .rev()
+                            .find(|(_, m)| m.site == sites[next] && m.node == u)
+                            .map(|(i, _)| i);
+                        u = tree.parent(u);
+                    }
+                    let site = tables.site(sites[next] as IdType);
+                    h.push(match allele {
+                        Some(i) => tables.mutation(i as IdType).derived_state.clone(),
+                        None => site.ancestral_state.clone(),
+                    });
+                }
+                next += 1;
+            }
+        }
+        let mut diffs = 0.;
+        for i in 0..samples.len() {
+            for j in (i + 1)..samples.len() {
+                diffs += haplotypes[i]
+                    .iter()
+                    .zip(&haplotypes[j])
+                    .filter(|(a, b)| a != b)
+                    .count() as f64;
+            }
+        }
+        let n = samples.len() as f64;
+        diffs / (n * (n - 1.) / 2.)
+    }
+
+    /// Mean TMRCA times two, averaged over the genome.
+    fn naive_branch_diversity(tables: &TableCollection, samples: &[IdType]) -> f64 {
+        let mut rv = 0.;
+        let mut tree_iter = TreeIterator::new_with_samples(tables, samples).unwrap();
+        while let Some(tree) = tree_iter.next_tree() {
+            let (left, right) = tree.interval();
+            let mut total = 0.;
+            for i in 0..samples.len() {
+                for j in (i + 1)..samples.len() {
+                    let mut path = vec![];
+                    let mut u = samples[i];
+                    while u != NULL_ID {
+                        path.push(u);
+                        u = tree.parent(u);
+                    }
+                    let mut u = samples[j];
+                    while !path.contains(&u) {
+                        u = tree.parent(u);
+                    }
+                    let t = tables.node(u).time;
+                    total += (tables.node(samples[i]).time - t + tables.node(samples[j]).time - t)
+                        as f64;
+                }
+            }
+            let n = samples.len() as f64;
+            rv += total / (n * (n - 1.) / 2.) * (right - left) as f64;
+        }
+        rv / tables.genome_length() as f64
+    }
+
+    #[test]
+    fn test_branch_diversity() {
+        let tables = two_trees();
+        let samples = tables.sample_nodes();
+        let pi = diversity(
+            &tables,
+            &[&samples],
+            None,
+            StatsMode::Branch,
+            StatsFlags::empty(),
+        )
+        .unwrap();
+        // Each tree: pairs have TMRCA 5, 10, 10 generations ago.
+        assert!(close(pi[0][0], (10. + 20. + 20.) / 3.));
+        assert!(close(pi[0][0], naive_branch_diversity(&tables, &samples)));
+
+        let segsites = segregating_sites(
+            &tables,
+            &[&samples],
+            None,
+            StatsMode::Branch,
+            StatsFlags::empty(),
+        )
+        .unwrap();
+        assert!(close(segsites[0][0], 25.));
+    }
+
+    #[test]
+    fn test_site_stats() {
+        let mut tables = two_trees();
+        // Site 0 is segregating only in the first tree.
+        tables.add_site(10, Some(vec![0])).unwrap();
+        tables.add_mutation(1, 0, 0, Some(vec![1]), true).unwrap();
+        // A site with three alleles.
+        tables.add_site(70, Some(vec![0])).unwrap();
+        tables.add_mutation(2, 0, 1, Some(vec![1]), true).unwrap();
+        tables.add_mutation(3, 0, 1, Some(vec![2]), true).unwrap();
+        // A site fixed for the derived state.
+        tables.add_site(60, Some(vec![0])).unwrap();
+        tables.add_mutation(0, 0, 2, Some(vec![1]), true).unwrap();
+        let samples = tables.sample_nodes();
+        let flags = StatsFlags::NO_SPAN_NORMALISE;
+
+        let pi = diversity(&tables, &[&samples], None, StatsMode::Site, flags).unwrap();
+        assert!(close(pi[0][0], 2. / 3. + 1.));
+        assert!(close(pi[0][0], naive_site_diversity(&tables, &samples)));
+        let s = segregating_sites(&tables, &[&samples], None, StatsMode::Site, flags).unwrap();
+        assert!(close(s[0][0], 3.));
+        let theta = watterson_theta(&tables, &[&samples], None, StatsMode::Site, flags).unwrap();
+        assert!(close(theta[0][0], 3. / 1.5));
+
+        let windows = [0, 50, 100];
+        let pi = diversity(
+            &tables,
+            &[&samples[..], &samples[..2]],
+            Some(&windows),
+            StatsMode::Site,
+            StatsFlags::empty(),
+        )
+        .unwrap();
+        assert_eq!(pi.len(), 2);
+        assert!(close(pi[0][0], 2. / 3. / 50.));
+        assert!(close(pi[1][0], 1. / 50.));
+        // Samples 2 and 3 share the mutation on node 1
+        assert!(close(pi[0][1], 0.));
+        assert!(close(pi[1][1], 1. / 50.));
+    }
+
+    #[test]
+    fn test_recurrent_mutation() {
+        let mut tables = two_trees();
+        tables.add_site(10, Some(vec![0])).unwrap();
+        // Back mutation to the ancestral state below another mutation.
+        tables.add_mutation(1, 0, 0, Some(vec![1]), true).unwrap();
+        tables.add_mutation(2, 0, 0, Some(vec![0]), true).unwrap();
+        // Two mutations on the same node: the later one is more recent.
+        tables.add_site(20, Some(vec![0])).unwrap();
+        tables.add_mutation(4, 0, 1, Some(vec![1]), true).unwrap();
+        tables.add_mutation(4, 0, 1, Some(vec![2]), true).unwrap();
+        let samples = tables.sample_nodes();
+        let flags = StatsFlags::NO_SPAN_NORMALISE;
+        let pi = diversity(&tables, &[&samples], None, StatsMode::Site, flags).unwrap();
+        assert!(close(pi[0][0], naive_site_diversity(&tables, &samples)));
+        assert!(close(pi[0][0], 4. / 3.));
+        let s = segregating_sites(&tables, &[&samples], None, StatsMode::Site, flags).unwrap();
+        assert!(close(s[0][0], 2.));
+    }
+
+    #[test]
+    fn test_simulated_data() {
+        let mut tables = neutral_wf(
+            PopulationParams::new(50, 10000, 1e-3, 0.0),
+            SimulationParams::new(Some(10), 4321, 200, SimulationFlags::empty()),
+        )
+        .unwrap();
+        let samples = tables.sample_nodes();
+        let positions = [13, 101, 2200, 5001, 9999];
+        for (i, p) in positions.iter().enumerate() {
+            let site = tables.add_site(*p, Some(vec![0])).unwrap();
+            let node = samples[i * 7];
+            tables
+                .add_mutation(node, 0, site as usize, Some(vec![1]), true)
+                .unwrap();
+        }
+        let flags = StatsFlags::NO_SPAN_NORMALISE;
+        let pi = diversity(&tables, &[&samples], None, StatsMode::Site, flags).unwrap();
+        assert!(close(pi[0][0], naive_site_diversity(&tables, &samples)));
+        let pi = diversity(
+            &tables,
+            &[&samples[..20]],
+            None,
+            StatsMode::Branch,
+            StatsFlags::empty(),
+        )
+        .unwrap();
+        assert!(close(
+            pi[0][0],
+            naive_branch_diversity(&tables, &samples[..20])
+        ));
+
+        // Windowed values sum to the total.
+        let windows = [0, 1000, 5000, 10000];
+        let w = diversity(
+            &tables,
+            &[&samples],
+            Some(&windows),
+            StatsMode::Branch,
+            flags,
+        )
+        .unwrap();
+        let total = diversity(&tables, &[&samples], None, StatsMode::Branch, flags).unwrap();
+        assert!(close(w.iter().map(|x| x[0]).sum::<f64>(), total[0][0]));
+    }
+
+    #[test]
+    fn test_tajimas_d() {
+        // Computed independently from Tajima (1989),
+        // for n = 10, pi = 3, S = 5.
+        let d = tajimas_d_from_parts(3., 5., 10.);
+        assert!(close(d, 2.796382629788921));
+        assert!(tajimas_d_from_parts(0., 0., 10.).is_nan());
+        assert!(tajimas_d_from_parts(1., 1., 2.).is_nan());
+
+        assert!(tajimas_d_from_parts(1., 1., 3.).is_nan());
+
+        let tables = two_trees();
+        let samples = tables.sample_nodes();
+        let d = tajimas_d(&tables, &[&samples], None, StatsMode::Branch).unwrap();
+        assert!(d[0][0].is_nan());
+
+        let tables = neutral_wf(
+            PopulationParams::new(20, 1000, 1e-3, 0.0),
+            SimulationParams::new(Some(10), 99, 100, SimulationFlags::empty()),
+        )
+        .unwrap();
+        let samples = tables.sample_nodes();
+        let windows = [0, 500, 1000];
+        let flags = StatsFlags::NO_SPAN_NORMALISE;
+        let mode = StatsMode::Branch;
+        let d = tajimas_d(&tables, &[&samples], Some(&windows), mode).unwrap();
+        let pi = diversity(&tables, &[&samples], Some(&windows), mode, flags).unwrap();
+        let s = segregating_sites(&tables, &[&samples], Some(&windows), mode, flags).unwrap();
+        for w in 0..2 {
+            let expected = tajimas_d_from_parts(pi[w][0], s[w][0], samples.len() as f64);
+            assert!(close(d[w][0], expected));
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let tables = two_trees();
+        let f = StatsFlags::empty();
+        let mode = StatsMode::Site;
+        assert_eq!(
+            diversity(&tables, &[] as &[Vec<IdType>], None, mode, f),
+            Err(StatsError::EmptySampleSet)
+        );
+        assert_eq!(
+            diversity(&tables, &[vec![]], None, mode, f),
+            Err(StatsError::EmptySampleSet)
+        );
+        assert_eq!(
+            diversity(&tables, &[[2, 2]], None, mode, f),
+            Err(StatsError::DuplicateSample { found: 2 })
+        );
+        assert_eq!(
+            diversity(&tables, &[[2, 5]], None, mode, f),
+            Err(StatsError::TablesError {
+                value: TablesError::NodeOutOfBounds
+            })
+        );
+        for w in [vec![0, 50], vec![10, 100], vec![0, 50, 50, 100], vec![0]] {
+            assert_eq!(
+                diversity(&tables, &[[2, 3]], Some(&w), mode, f),
+                Err(StatsError::InvalidWindows)
+            );
+        }
+        let mut tables = two_trees();
+        tables.add_mutation(2, 0, 0, None, true).unwrap();
+        assert_eq!(
+            diversity(&tables, &[[2, 3]], None, mode, f),
+            Err(StatsError::InvalidSite { found: 0 })
+        );
+    }
+}
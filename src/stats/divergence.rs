@@ -0,0 +1,412 @@
+use super::*;
+
+/// Group sample nodes by [``Node::deme``].
+///
+/// Element `i` of the return value contains the
+/// nodes in `samples` whose deme is `i`, in the order
+/// that they appear in `samples`.
+/// The length of the return value is one more than
+/// the largest deme found.
+///
+/// # Errors
+///
+/// Will return [``TablesError::NodeOutOfBounds``] if a sample
+/// is not in the node table.
+///
+/// # Example
+///
+/// ```
+/// let mut tables = forrustts::TableCollection::new(100).unwrap();
+/// tables.add_node(0, 1).unwrap();
+/// tables.add_node(0, 0).unwrap();
+/// tables.add_node(0, 1).unwrap();
+/// let sets = forrustts::stats::sample_sets_by_deme(&tables, &[0, 1, 2]).unwrap();
+/// assert_eq!(sets, vec![vec![1], vec![0, 2]]);
+/// ```
+pub fn sample_sets_by_deme(
+    tables: &TableCollection,
+    samples: &[IdType],
+) -> StatsResult<Vec<Vec<IdType>>> {
+    let mut rv: Vec<Vec<IdType>> = vec![];
+    for &s in samples {
+        if s < 0 || s as usize >= tables.num_nodes() {
+            return Err(TablesError::NodeOutOfBounds.into());
+        }
+        let deme = tables.node(s).deme as usize;
+        if deme >= rv.len() {
+            rv.resize(deme + 1, vec![]);
+        }
+        rv[deme].push(s);
+    }
+    Ok(rv)
+}
+
+/// Apply a summary function to tuples of sample sets.
+///
+/// The summary function is given the allele counts
+/// and sizes of the sample sets in each tuple.
+fn multi_way_stat<S: AsRef<[IdType]>>(
+    tables: &TableCollection,
+    sample_sets: &[S],
+    tuples: &[&[usize]],
+    windows: Option<&[Position]>,
+    mode: StatsMode,
+    flags: StatsFlags,
+    summary: &dyn Fn(&[f64], &[f64]) -> f64,
+) -> StatsResult<Vec<Vec<f64>>> {
+    let sets = SampleSets::new(tables, sample_sets)?;
+    let windows = make_windows(windows, tables.genome_length())?;
+    for &t in tuples {
+        if let Some(&i) = t.iter().find(|&&i| i >= sets.num_sets()) {
+            return Err(StatsError::InvalidSampleSetIndex { found: i });
+        }
+    }
+    let sizes: Vec<Vec<f64>> = tuples
+        .iter()
+        .map(|t| t.iter().map(|&i| sets.sizes[i]).collect())
+        .collect();
+    let buffer = std::cell::RefCell::new(vec![]);
+    let f = |x: &[f64], out: &mut [f64]| {
+        let mut xt = buffer.borrow_mut();
+        for (o, (t, n)) in out.iter_mut().zip(tuples.iter().zip(&sizes)) {
+            xt.clear();
+            xt.extend(t.iter().map(|&i| x[i]));
+            *o = summary(&xt, n);
+        }
+    };
+    let stat = GeneralStat {
+        mode,
+        flags,
+        output_dim: tuples.len(),
+        polarised: false,
+        summary: &f,
+    };
+    general_stat(tables, &sets, &windows, &stat)
+}
+
+/// Mean genetic divergence (``dxy``) between pairs of sample sets.
+///
+/// The mean number of differences between a sample
+/// from one set and a sample from the other.
+///
+/// # Parameters
+///
+/// * `tables`: the tables
+/// * `sample_sets`: the sample sets
+/// * `indexes`: pairs of indexes into `sample_sets`
+/// * `windows`: window breakpoints, or [``None``] for a single window
+/// * `mode`: see [``StatsMode``]
+/// * `flags`: see [``StatsFlags``]
+///
+/// # Returns
+///
+/// One value per window and pair of sample sets.
+///
+/// # Errors
+///
+/// [``StatsError``] if the sample sets, indexes, windows, or tables
+/// are invalid.
+pub fn divergence<S: AsRef<[IdType]>>(
+    tables: &TableCollection,
+    sample_sets: &[S],
+    indexes: &[(usize, usize)],
+    windows: Option<&[Position]>,
+    mode: StatsMode,
+    flags: StatsFlags,
+) -> StatsResult<Vec<Vec<f64>>> {
+    let tuples: Vec<[usize; 2]> = indexes.iter().map(|&(a, b)| [a, b]).collect();
+    let tuples: Vec<&[usize]> = tuples.iter().map(|t| &t[..]).collect();
+    multi_way_stat(
+        tables,
+        sample_sets,
+        &tuples,
+        windows,
+        mode,
+        flags,
+        &|x, n| x[0] * (n[1] - x[1]) / (n[0] * n[1]),
+    )
+}
+
+/// Hudson's ``Fst`` between pairs of sample sets.
+///
+/// Calculated as `1 - (pi_a + pi_b) / (2 * dxy)`,
+/// where `pi` is the [``diversity``] within each set and
+/// `dxy` is the [``divergence``] between them.
+/// Each term is summed over the window before taking the ratio.
+///
+/// See [``divergence``] for the parameters.
+/// Span normalisation does not affect the result.
+///
+/// # Errors
+///
+/// [``StatsError``] if the sample sets, indexes, windows, or tables
+/// are invalid.
+pub fn fst<S: AsRef<[IdType]>>(
+    tables: &TableCollection,
+    sample_sets: &[S],
+    indexes: &[(usize, usize)],
+    windows: Option<&[Position]>,
+    mode: StatsMode,
+) -> StatsResult<Vec<Vec<f64>>> {
+    let flags = StatsFlags::NO_SPAN_NORMALISE;
+    let dxy = divergence(tables, sample_sets, indexes, windows, mode, flags)?;
+    let pi = diversity(tables, sample_sets, windows, mode, flags)?;
+    Ok(dxy
+        .iter()
+        .zip(pi)
+        .map(|(dxy, pi)| {
+            dxy.iter()
+                .zip(indexes)
+                .map(|(d, &(a, b))| 1. - (pi[a] + pi[b]) / (2. * d))
+                .collect()
+        })
+        .collect())
+}
+
+// The f statistics are symmetric with respect to
+// which allele is derived. Summing over all alleles
+// counts each biallelic site twice, hence the factor of 0.5.
+
+/// Unbiased estimate of p^2 from sampling without replacement.
+fn p_squared(x: f64, n: f64) -> f64 {
+    x * (x - 1.) / (n * (n - 1.))
+}
+
+/// Patterson's ``f2`` between pairs of sample sets.
+///
+/// `f2(A, B)` estimates `(p_A - p_B)^2`, where `p` is an allele frequency.
+/// Sample sets must contain at least two samples.
+///
+/// See [``divergence``] for the parameters.
+///
+/// # Errors
+///
+/// [``StatsError``] if the sample sets, indexes, windows, or tables
+/// are invalid.
+pub fn f2<S: AsRef<[IdType]>>(
+    tables: &TableCollection,
+    sample_sets: &[S],
+    indexes: &[(usize, usize)],
+    windows: Option<&[Position]>,
+    mode: StatsMode,
+    flags: StatsFlags,
+) -> StatsResult<Vec<Vec<f64>>> {
+    let tuples: Vec<[usize; 2]> = indexes.iter().map(|&(a, b)| [a, b]).collect();
+    let tuples: Vec<&[usize]> = tuples.iter().map(|t| &t[..]).collect();
+    multi_way_stat(
+        tables,
+        sample_sets,
+        &tuples,
+        windows,
+        mode,
+        flags,
+        &|x, n| {
+            0.5 * (p_squared(x[0], n[0]) + p_squared(x[1], n[1]) - 2. * x[0] * x[1] / (n[0] * n[1]))
+        },
+    )
+}
+
+/// Patterson's ``f3`` for triples of sample sets.
+///
+/// `f3(A; B, C)` estimates `(p_A - p_B)(p_A - p_C)`.
+/// The first set of each triple must contain at least two samples.
+///
+/// See [``divergence``] for the parameters.
+///
+/// # Errors
+///
+/// [``StatsError``] if the sample sets, indexes, windows, or tables
+/// are invalid.
+pub fn f3<S: AsRef<[IdType]>>(
+    tables: &TableCollection,
+    sample_sets: &[S],
+    indexes: &[(usize, usize, usize)],
+    windows: Option<&[Position]>,
+    mode: StatsMode,
+    flags: StatsFlags,
+) -> StatsResult<Vec<Vec<f64>>> {
+    let tuples: Vec<[usize; 3]> = indexes.iter().map(|&(a, b, c)| [a, b, c]).collect();
+    let tuples: Vec<&[usize]> = tuples.iter().map(|t| &t[..]).collect();
+    multi_way_stat(
+        tables,
+        sample_sets,
+        &tuples,
+        windows,
+        mode,
+        flags,
+        &|x, n| {
+            let p = |i: usize| x[i] / n[i];
+            0.5 * (p_squared(x[0], n[0]) - p(0) * p(1) - p(0) * p(2) + p(1) * p(2))
+        },
+    )
+}
+
+/// Patterson's ``f4`` for quadruples of sample sets.
+///
+/// `f4(A, B; C, D)` estimates `(p_A - p_B)(p_C - p_D)`.
+///
+/// See [``divergence``] for the parameters.
+///
+/// # Errors
+///
+/// [``StatsError``] if the sample sets, indexes, windows, or tables
+/// are invalid.
+pub fn f4<S: AsRef<[IdType]>>(
+    tables: &TableCollection,
+    sample_sets: &[S],
+    indexes: &[(usize, usize, usize, usize)],
+    windows: Option<&[Position]>,
+    mode: StatsMode,
+    flags: StatsFlags,
+) -> StatsResult<Vec<Vec<f64>>> {
+    let tuples: Vec<[usize; 4]> = indexes.iter().map(|&(a, b, c, d)| [a, b, c, d]).collect();
+    let tuples: Vec<&[usize]> = tuples.iter().map(|t| &t[..]).collect();
+    multi_way_stat(
+        tables,
+        sample_sets,
+        &tuples,
+        windows,
+        mode,
+        flags,
+        &|x, n| {
+            let p = |i: usize| x[i] / n[i];
+            0.5 * (p(0) - p(1)) * (p(2) - p(3))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::close;
+    use crate::wright_fisher::*;
+
+    // Two demes of two samples each.
+    // Node 0 is the root, nodes 1 and 2 are
+    // the ancestors of demes 0 and 1.
+    fn make_tables() -> TableCollection {
+        let mut tables = TableCollection::new(100).unwrap();
+        tables.add_node(0, 0).unwrap();
+        tables.add_node(5, 0).unwrap();
+        tables.add_node(5, 1).unwrap();
+        for deme in [0, 0, 1, 1] {
+            tables
+                .add_node_with_flags(10, deme, NodeFlags::IS_SAMPLE)
+                .unwrap();
+        }
+        tables.add_edge(0, 100, 0, 1).unwrap();
+        tables.add_edge(0, 100, 0, 2).unwrap();
+        tables.add_edge(0, 100, 1, 3).unwrap();
+        tables.add_edge(0, 100, 1, 4).unwrap();
+        tables.add_edge(0, 100, 2, 5).unwrap();
+        tables.add_edge(0, 100, 2, 6).unwrap();
+        // A fixed difference between demes
+        tables.add_site(10, Some(vec![0])).unwrap();
+        tables.add_mutation(1, 0, 0, Some(vec![1]), true).unwrap();
+        // A polymorphism within deme 1
+        tables.add_site(20, Some(vec![0])).unwrap();
+        tables.add_mutation(6, 0, 1, Some(vec![1]), true).unwrap();
+        tables
+    }
+
+    #[test]
+    fn test_sample_sets_by_deme() {
+        let tables = make_tables();
+        let sets = sample_sets_by_deme(&tables, &tables.sample_nodes()).unwrap();
+        assert_eq!(sets, vec![vec![3, 4], vec![5, 6]]);
+        assert_eq!(
+            sample_sets_by_deme(&tables, &[7]),
+            Err(StatsError::TablesError {
+                value: TablesError::NodeOutOfBounds
+            })
+        );
+    }
+
+    #[test]
+    fn test_divergence_and_fst() {
+        let tables = make_tables();
+        let sets = sample_sets_by_deme(&tables, &tables.sample_nodes()).unwrap();
+        let flags = StatsFlags::NO_SPAN_NORMALISE;
+        let dxy = divergence(&tables, &sets, &[(0, 1)], None, StatsMode::Site, flags).unwrap();
+        // Every pair differs at site 0, and half differ at site 1.
+        assert!(close(dxy[0][0], 1.5));
+        let f = fst(&tables, &sets, &[(0, 1)], None, StatsMode::Site).unwrap();
+        // pi is 0 in deme 0 and 1 in deme 1.
+        assert!(close(f[0][0], 1. - 1. / 3.));
+
+        let dxy = divergence(
+            &tables,
+            &sets,
+            &[(0, 1), (1, 0)],
+            None,
+            StatsMode::Branch,
+            StatsFlags::empty(),
+        )
+        .unwrap();
+        assert!(close(dxy[0][0], 20.));
+        assert!(close(dxy[0][1], 20.));
+        let f = fst(&tables, &sets, &[(0, 1)], None, StatsMode::Branch).unwrap();
+        assert!(close(f[0][0], 0.5));
+
+        assert_eq!(
+            divergence(&tables, &sets, &[(0, 2)], None, StatsMode::Site, flags),
+            Err(StatsError::InvalidSampleSetIndex { found: 2 })
+        );
+    }
+
+    #[test]
+    fn test_f_statistics() {
+        let tables = make_tables();
+        let sets = sample_sets_by_deme(&tables, &tables.sample_nodes()).unwrap();
+        let flags = StatsFlags::NO_SPAN_NORMALISE;
+        let f = f2(&tables, &sets, &[(0, 1)], None, StatsMode::Site, flags).unwrap();
+        // Site 0 contributes (1 - 0)^2.  At site 1, the estimates of
+        // p^2 and p_A * p_B are both zero.
+        assert!(close(f[0][0], 1.));
+    }
+
+    #[test]
+    fn test_f_statistic_identities() {
+        let mut tables = neutral_wf(
+            PopulationParams::new(20, 1000, 1e-3, 0.0),
+            SimulationParams::new(Some(10), 31415, 100, SimulationFlags::empty()),
+        )
+        .unwrap();
+        let samples = tables.sample_nodes();
+        for (i, p) in [3, 100, 400, 401, 999].iter().enumerate() {
+            let site = tables.add_site(*p, Some(vec![0])).unwrap() as usize;
+            tables
+                .add_mutation(samples[3 * i], 0, site, Some(vec![1]), true)
+                .unwrap();
+        }
+        let sets: Vec<&[IdType]> = samples.chunks(10).collect();
+        let windows = [0, 500, 1000];
+        let flags = StatsFlags::empty();
+        for mode in [StatsMode::Site, StatsMode::Branch] {
+            let pairs: Vec<(usize, usize)> = (0..4)
+                .flat_map(|a| (0..4).map(move |b| (a, b)))
+                .filter(|(a, b)| a != b)
+                .collect();
+            let d = divergence(&tables, &sets, &pairs, Some(&windows), mode, flags).unwrap();
+            let pi = diversity(&tables, &sets, Some(&windows), mode, flags).unwrap();
+            let f2s = f2(&tables, &sets, &pairs, Some(&windows), mode, flags).unwrap();
+            let f3s = f3(&tables, &sets, &[(0, 1, 2)], Some(&windows), mode, flags).unwrap();
+            let f4s = f4(&tables, &sets, &[(0, 1, 2, 3)], Some(&windows), mode, flags).unwrap();
+            let idx = |a: usize, b: usize| pairs.iter().position(|&p| p == (a, b)).unwrap();
+            for w in 0..2 {
+                let f2_ab = |a: usize, b: usize| f2s[w][idx(a, b)];
+                for (i, &(a, b)) in pairs.iter().enumerate() {
+                    assert!(close(f2s[w][i], d[w][i] - (pi[w][a] + pi[w][b]) / 2.));
+                }
+                assert!(close(
+                    f3s[w][0],
+                    (f2_ab(0, 1) + f2_ab(0, 2) - f2_ab(1, 2)) / 2.
+                ));
+                assert!(close(
+                    f4s[w][0],
+                    (f2_ab(0, 3) + f2_ab(1, 2) - f2_ab(0, 2) - f2_ab(1, 3)) / 2.
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,477 @@
+use super::*;
+
+/// The linkage disequilibrium statistic to calculate.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LdStat {
+    /// The squared correlation coefficient, ``r^2``.
+    RSquared,
+    /// Lewontin's ``D'``, which is ``D`` divided by its
+    /// maximum possible magnitude given the allele frequencies.
+    /// The sign of ``D`` is kept.
+    DPrime,
+}
+
+/// A matrix of linkage disequilibrium values
+/// between pairs of sites.
+///
+/// Sites are ordered by position.
+/// A dense matrix stores all pairs.
+/// A banded matrix stores, for each site, the values
+/// for the next `bandwidth` sites.
+///
+/// See [``ld_matrix``].
+#[derive(Clone, Debug, PartialEq)]
+pub struct LdMatrix {
+    sites: Vec<usize>,
+    positions: Vec<Position>,
+    bandwidth: Option<usize>,
+    values: Vec<f64>,
+    polymorphic: Vec<bool>,
+}
+
+impl LdMatrix {
+    /// The number of sites.
+    pub fn num_sites(&self) -> usize {
+        self.sites.len()
+    }
+
+    /// The site table indexes of each row/column.
+    pub fn sites(&self) -> &[usize] {
+        &self.sites
+    }
+
+    /// The position of each site.
+    pub fn positions(&self) -> &[Position] {
+        &self.positions
+    }
+
+    /// The bandwidth, or [``None``] for a dense matrix.
+    pub fn bandwidth(&self) -> Option<usize> {
+        self.bandwidth
+    }
+
+    /// The stored values.
+    ///
+    /// For a dense matrix, this is a `num_sites` by `num_sites`
+    /// matrix in row-major order.
+    /// For a banded matrix, row `i` has `bandwidth` values,
+    /// for sites `i + 1` through `i + bandwidth`.
+    /// Values past the last site are `NaN`.
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// The value for sites `i` and `j`.
+    ///
+    /// Returns [``None``] if either index is out of range
+    /// or if the pair is outside of the band.
+    /// The value for `i == j` is `1` if the site is
+    /// polymorphic and `NaN` otherwise.
+    pub fn get(&self, i: usize, j: usize) -> Option<f64> {
+        let n = self.num_sites();
+        if i >= n || j >= n {
+            return None;
+        }
+        match self.bandwidth {
+            None => Some(self.values[i * n + j]),
+            Some(b) => {
+                let (i, j) = (std::cmp::min(i, j), std::cmp::max(i, j));
+                if i == j {
+                    Some(diagonal_value(self.polymorphic[i]))
+                } else if j - i <= b {
+                    Some(self.values[i * b + j - i - 1])
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// The mean LD for pairs of sites within a range of distances.
+///
+/// See [``ld_decay``].
+#[derive(Clone, Debug, PartialEq)]
+pub struct LdDecayBin {
+    /// The smallest distance in this bin.
+    pub distance: Position,
+    /// The mean value.
+    /// `NaN` if there are no pairs.
+    pub mean: f64,
+    /// The number of pairs of sites.
+    pub num_pairs: usize,
+}
+
+/// Sites in the window, in position order, and a bitset of
+/// the samples carrying a derived allele at each site.
+fn derived_genotypes(
+    tables: &TableCollection,
+    samples: &[IdType],
+    window: (Position, Position),
+) -> StatsResult<(Vec<usize>, Vec<Vec<u64>>)> {
+    let words = samples.len().div_ceil(64);
+    let mut sites = vec![];
+    let mut genotypes = vec![];
+    for variant in VariantIterator::new(tables, samples)? {
+        if variant.position() < window.0 {
+            continue;
+        }
+        if variant.position() >= window.1 {
+            break;
+        }
+        let mut bits = vec![0_u64; words];
+        for (i, _) in variant
+            .genotypes()
+            .iter()
+            .enumerate()
+            .filter(|(_, &g)| g != 0)
+        {
+            bits[i / 64] |= 1 << (i % 64);
+        }
+        sites.push(variant.site());
+        genotypes.push(bits);
+    }
+    Ok((sites, genotypes))
+}
+
+fn count_ones(bits: &[u64]) -> f64 {
+    bits.iter().map(|b| b.count_ones()).sum::<u32>() as f64
+}
+
+fn is_polymorphic(bits: &[u64], n: f64) -> bool {
+    let count = count_ones(bits);
+    count > 0. && count < n
+}
+
+fn diagonal_value(polymorphic: bool) -> f64 {
+    if polymorphic {
+        1.
+    } else {
+        f64::NAN
+    }
+}
+
+fn ld_value(a: &[u64], b: &[u64], n: f64, stat: LdStat) -> f64 {
+    let pa = count_ones(a) / n;
+    let pb = count_ones(b) / n;
+    let pab = a
+        .iter()
+        .zip(b)
+        .map(|(x, y)| (x & y).count_ones())
+        .sum::<u32>() as f64
+        / n;
+    let d = pab - pa * pb;
+    match stat {
+        LdStat::RSquared => d * d / (pa * (1. - pa) * pb * (1. - pb)),
+        LdStat::DPrime => {
+            let dmax = if d > 0. {
+                f64::min(pa * (1. - pb), (1. - pa) * pb)
+            } else {
+                f64::min(pa * pb, (1. - pa) * (1. - pb))
+            };
+            d / dmax
+        }
+    }
+}
+
+fn validate_window(
+    tables: &TableCollection,
+    window: Option<(Position, Position)>,
+) -> StatsResult<(Position, Position)> {
+    match window {
+        None => Ok((0, tables.genome_length())),
+        Some((left, right)) => {
+            if left < 0 || left >= right || right > tables.genome_length() {
+                Err(StatsError::InvalidWindows)
+            } else {
+                Ok((left, right))
+            }
+        }
+    }
+}
+
+/// Linkage disequilibrium between pairs of sites.
+///
+/// Each site is treated as biallelic: a sample
+/// either carries the ancestral state or a derived state.
+/// Values involving a site that is not polymorphic
+/// in `samples` are `NaN`, including the diagonal.
+/// The diagonal is `1` for polymorphic sites.
+///
+/// # Parameters
+///
+/// * `tables`: the tables
+/// * `samples`: the samples
+/// * `window`: the interval `[left, right)` containing the sites,
+///   or [``None``] for the whole genome
+/// * `stat`: see [``LdStat``]
+/// * `bandwidth`: if not [``None``], return a banded matrix
+///   (see [``LdMatrix``]).
+///
+/// # Errors
+///
+/// [``StatsError``] if the samples, window, or tables
+/// are invalid.
+///
+/// # Example
+///
+/// ```
+/// use forrustts::stats::*;
+/// use forrustts::TableCollection;
+///
+/// let mut tables = TableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap();
+/// tables.add_node(5, 0).unwrap();
+/// tables.add_node(10, 0).unwrap();
+/// tables.add_node(10, 0).unwrap();
+/// tables.add_node(10, 0).unwrap();
+/// tables.add_edge(0, 100, 0, 1).unwrap();
+/// tables.add_edge(0, 100, 1, 2).unwrap();
+/// tables.add_edge(0, 100, 1, 3).unwrap();
+/// tables.add_edge(0, 100, 0, 4).unwrap();
+/// // Two mutations on the same branch
+/// for position in [10, 20] {
+///     let site = tables.add_site(position, Some(vec![0])).unwrap();
+///     tables.add_mutation(1, 0, site as usize, Some(vec![1]), true).unwrap();
+/// }
+///
+/// let ld = ld_matrix(&tables, &[2, 3, 4], None, LdStat::RSquared, None).unwrap();
+/// assert_eq!(ld.num_sites(), 2);
+/// assert!((ld.get(0, 1).unwrap() - 1.).abs() < 1e-12);
+/// ```
+pub fn ld_matrix(
+    tables: &TableCollection,
+    samples: &[IdType],
+    window: Option<(Position, Position)>,
+    stat: LdStat,
+    bandwidth: Option<usize>,
+) -> StatsResult<LdMatrix> {
+    SampleSets::new(tables, &[samples])?;
+    let window = validate_window(tables, window)?;
+    let (sites, genotypes) = derived_genotypes(tables, samples, window)?;
+    let n = samples.len() as f64;
+    let m = sites.len();
+    let polymorphic: Vec<bool> = genotypes.iter().map(|g| is_polymorphic(g, n)).collect();
+    let values = match bandwidth {
+        None => {
+            let mut values = vec![0.; m * m];
+            for i in 0..m {
+                values[i * m + i] = diagonal_value(polymorphic[i]);
+                for j in (i + 1)..m {
+                    let v = ld_value(&genotypes[i], &genotypes[j], n, stat);
+                    values[i * m + j] = v;
+                    values[j * m + i] = v;
+                }
+            }
+            values
+        }
+        Some(b) => {
+            let mut values = vec![f64::NAN; m * b];
+            for i in 0..m {
+                for j in (i + 1)..std::cmp::min(m, i + b + 1) {
+                    values[i * b + j - i - 1] = ld_value(&genotypes[i], &genotypes[j], n, stat);
+                }
+            }
+            values
+        }
+    };
+    Ok(LdMatrix {
+        positions: sites
+            .iter()
+            .map(|&s| tables.site(s as IdType).position)
+            .collect(),
+        sites,
+        bandwidth,
+        values,
+        polymorphic,
+    })
+}
+
+/// The decay of linkage disequilibrium with distance.
+///
+/// Pairs of sites less than `max_distance` apart
+/// are grouped into bins of width `bin_width`,
+/// by the distance between their positions.
+/// Pairs whose value is `NaN` are skipped.
+///
+/// See [``ld_matrix``] for the other parameters.
+///
+/// # Errors
+///
+/// [``StatsError::InvalidWindows``] if `bin_width` or `max_distance`
+/// is not positive, and [``StatsError``] if the samples, window, or tables
+/// are invalid.
+pub fn ld_decay(
+    tables: &TableCollection,
+    samples: &[IdType],
+    window: Option<(Position, Position)>,
+    stat: LdStat,
+    bin_width: Position,
+    max_distance: Position,
+) -> StatsResult<Vec<LdDecayBin>> {
+    if bin_width < 1 || max_distance < 1 {
+        return Err(StatsError::InvalidWindows);
+    }
+    SampleSets::new(tables, &[samples])?;
+    let window = validate_window(tables, window)?;
+    let (sites, genotypes) = derived_genotypes(tables, samples, window)?;
+    let n = samples.len() as f64;
+    let num_bins = ((max_distance + bin_width - 1) / bin_width) as usize;
+    let mut sums = vec![0.; num_bins];
+    let mut counts = vec![0; num_bins];
+    let position = |i: usize| tables.site(sites[i] as IdType).position;
+    for i in 0..sites.len() {
+        for j in (i + 1)..sites.len() {
+            let distance = position(j) - position(i);
+            if distance >= max_distance {
+                break;
+            }
+            let v = ld_value(&genotypes[i], &genotypes[j], n, stat);
+            if !v.is_nan() {
+                let bin = (distance / bin_width) as usize;
+                sums[bin] += v;
+                counts[bin] += 1;
+            }
+        }
+    }
+    Ok(sums
+        .iter()
+        .zip(counts)
+        .enumerate()
+        .map(|(i, (s, c))| LdDecayBin {
+            distance: i as Position * bin_width,
+            mean: if c > 0 { s / c as f64 } else { f64::NAN },
+            num_pairs: c,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::*;
+    use crate::wright_fisher::*;
+
+    // The balanced tree of [`balanced_tree`] over the whole genome.
+    fn make_tables() -> TableCollection {
+        let mut tables = balanced_tree(None);
+        // Sites are added out of position order.
+        for (position, node) in [(30, 3), (10, 1), (20, 1), (40, 5), (50, 0)] {
+            let site = tables.add_site(position, Some(vec![0])).unwrap() as usize;
+            tables
+                .add_mutation(node, 0, site, Some(vec![1]), true)
+                .unwrap();
+        }
+        tables
+    }
+
+    #[test]
+    fn test_dense_matrix() {
+        let tables = make_tables();
+        let samples = tables.sample_nodes();
+        let ld = ld_matrix(&tables, &samples, None, LdStat::RSquared, None).unwrap();
+        assert_eq!(ld.num_sites(), 5);
+        assert_eq!(ld.sites(), &[1, 2, 0, 3, 4]);
+        assert_eq!(ld.positions(), &[10, 20, 30, 40, 50]);
+        assert!(close(ld.get(0, 1).unwrap(), 1.));
+        // pA = 1/2, pB = 1/4, pAB = 1/4, D = 1/8
+        assert!(close(ld.get(0, 2).unwrap(), (1. / 64.) / (0.25 * 3. / 16.)));
+        // pA = 1/2, pB = 1/4, pAB = 0, D = -1/8
+        assert!(close(ld.get(0, 3).unwrap(), 1. / 3.));
+        assert_eq!(ld.get(0, 3), ld.get(3, 0));
+        assert_eq!(ld.get(0, 0), Some(1.));
+        // Site 4 is fixed
+        assert!(ld.get(0, 4).unwrap().is_nan());
+        assert!(ld.get(4, 4).unwrap().is_nan());
+        assert_eq!(ld.get(0, 5), None);
+
+        let ld = ld_matrix(&tables, &samples, None, LdStat::DPrime, None).unwrap();
+        assert!(close(ld.get(0, 1).unwrap(), 1.));
+        assert!(close(ld.get(0, 2).unwrap(), 1.));
+        assert!(close(ld.get(0, 3).unwrap(), -1.));
+    }
+
+    #[test]
+    fn test_banded_matrix() {
+        let tables = make_tables();
+        let samples = tables.sample_nodes();
+        let dense = ld_matrix(&tables, &samples, None, LdStat::DPrime, None).unwrap();
+        let banded = ld_matrix(&tables, &samples, None, LdStat::DPrime, Some(2)).unwrap();
+        assert_eq!(banded.bandwidth(), Some(2));
+        assert_eq!(banded.values().len(), 10);
+        assert_eq!(banded.get(0, 0), Some(1.));
+        assert!(banded.get(4, 4).unwrap().is_nan());
+        for i in 0..5 {
+            for j in 0..5 {
+                if i.max(j) - i.min(j) <= 2 {
+                    let (a, b) = (dense.get(i, j).unwrap(), banded.get(i, j).unwrap());
+                    assert!(a == b || (a.is_nan() && b.is_nan()));
+                } else {
+                    assert!(banded.get(i, j).is_none());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_window() {
+        let tables = make_tables();
+        let samples = tables.sample_nodes();
+        let ld = ld_matrix(&tables, &samples, Some((15, 40)), LdStat::RSquared, None).unwrap();
+        assert_eq!(ld.positions(), &[20, 30]);
+        for w in [(10, 10), (-1, 10), (0, 101)] {
+            assert_eq!(
+                ld_matrix(&tables, &samples, Some(w), LdStat::RSquared, None),
+                Err(StatsError::InvalidWindows)
+            );
+        }
+    }
+
+    #[test]
+    fn test_ld_decay() {
+        let tables = make_tables();
+        let samples = tables.sample_nodes();
+        let bins = ld_decay(&tables, &samples, None, LdStat::DPrime, 15, 30).unwrap();
+        assert_eq!(bins.len(), 2);
+        // Distances of 10: (10, 20), (20, 30), (30, 40)
+        assert_eq!(bins[0].distance, 0);
+        assert_eq!(bins[0].num_pairs, 3);
+        assert!(close(bins[0].mean, (1. + 1. - 1.) / 3.));
+        // Distance of 20: (10, 30), (20, 40)
+        assert_eq!(bins[1].distance, 15);
+        assert_eq!(bins[1].num_pairs, 2);
+        assert!(close(bins[1].mean, 0.));
+        assert_eq!(
+            ld_decay(&tables, &samples, None, LdStat::DPrime, 0, 30),
+            Err(StatsError::InvalidWindows)
+        );
+    }
+
+    #[test]
+    fn test_simulated_data() {
+        let mut tables = neutral_wf(
+            PopulationParams::new(50, 1000, 1e-3, 0.0),
+            SimulationParams::new(Some(10), 2718, 100, SimulationFlags::empty()),
+        )
+        .unwrap();
+        let samples = tables.sample_nodes();
+        for (i, position) in [5, 50, 500, 900].iter().enumerate() {
+            let site = tables.add_site(*position, Some(vec![0])).unwrap() as usize;
+            tables
+                .add_mutation(samples[11 * i], 0, site, Some(vec![1]), true)
+                .unwrap();
+        }
+        let ld = ld_matrix(&tables, &samples, None, LdStat::RSquared, None).unwrap();
+        assert_eq!(ld.num_sites(), 4);
+        for i in 0..4 {
+            for j in 0..4 {
+                if i != j {
+                    // Each mutation is carried by one distinct sample.
+                    let n = samples.len() as f64;
+                    let expected = 1. / ((n - 1.) * (n - 1.));
+                    assert!(close(ld.get(i, j).unwrap(), expected));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use super::*;
+
+/// An allele frequency spectrum.
+///
+/// For `k` sample sets, this is a `k`-dimensional array
+/// whose dimensions are the sample set sizes plus one.
+/// The entry at `[x_1, ..., x_k]` is the number of alleles
+/// (site mode) or the branch length (branch mode) found in
+/// `x_i` samples of set `i`.
+///
+/// Values are stored in row-major order: the index
+/// of the last sample set varies fastest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrequencySpectrum {
+    dims: Vec<usize>,
+    values: Vec<f64>,
+}
+
+impl FrequencySpectrum {
+    fn new(dims: Vec<usize>, values: Vec<f64>) -> Self {
+        FrequencySpectrum { dims, values }
+    }
+
+    /// The size of each dimension.
+    pub fn dims(&self) -> &[usize] {
+        &self.dims
+    }
+
+    /// All values, in row-major order.
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// The value for the given counts in each sample set.
+    ///
+    /// Returns [``None``] if the number of counts does not match
+    /// the number of sample sets or if a count is out of range.
+    pub fn get(&self, counts: &[usize]) -> Option<f64> {
+        flat_index(&self.dims, counts).map(|i| self.values[i])
+    }
+}
+
+fn flat_index(dims: &[usize], counts: &[usize]) -> Option<usize> {
+    if counts.len() != dims.len() {
+        return None;
+    }
+    let mut index = 0;
+    for (&c, &d) in counts.iter().zip(dims) {
+        if c >= d {
+            return None;
+        }
+        index = index * d + c;
+    }
+    Some(index)
+}
+
+/// Return `x` or its complement, whichever has fewer samples in total.
+/// Ties are broken by taking the lexicographically smaller value.
+fn fold(x: &[usize], sizes: &[usize], folded: &mut Vec<usize>) {
+    folded.clear();
+    let total: usize = x.iter().sum();
+    let n: usize = sizes.iter().sum();
+    let complement = x.iter().zip(sizes).map(|(&c, &s)| s - c);
+    if 2 * total < n {
+        folded.extend_from_slice(x);
+    } else if 2 * total > n {
+        folded.extend(complement);
+    } else {
+        let complement: Vec<usize> = complement.collect();
+        folded.extend_from_slice(std::cmp::min(x, complement.as_slice()));
+    }
+}
+
+/// The allele frequency spectrum.
+///
+/// If `flags` contains [``StatsFlags::POLARISED``], the
+/// unfolded spectrum is returned, counting derived alleles.
+/// Otherwise, the spectrum is folded, so that each
+/// entry counts alleles regardless of which state is ancestral.
+/// In a folded spectrum, a biallelic site contributes one.
+///
+/// With more than one sample set, the joint spectrum is returned.
+///
+/// # Parameters
+///
+/// * `tables`: the tables
+/// * `sample_sets`: the sample sets
+/// * `windows`: window breakpoints, or [``None``] for a single window
+/// * `mode`: see [``StatsMode``]
+/// * `flags`: see [``StatsFlags``]
+///
+/// # Returns
+///
+/// One spectrum per window.
+///
+/// # Errors
+///
+/// [``StatsError``] if the sample sets, windows, or tables
+/// are invalid.
+///
+/// # Example
+///
+/// ```
+/// use forrustts::stats::*;
+/// use forrustts::{SamplesInfo, TableCollection};
+///
+/// let mut tables = TableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap();
+/// for _ in 0..4 {
+///     tables.add_node(10, 0).unwrap();
+/// }
+/// for c in 1..5 {
+///     tables.add_edge(0, 100, 0, c).unwrap();
+/// }
+/// let site = tables.add_site(50, Some(vec![0])).unwrap();
+/// tables
+///     .add_mutation(1, 0, site as usize, Some(vec![1]), true)
+///     .unwrap();
+///
+/// let mut samples = SamplesInfo::new();
+/// samples.samples = vec![1, 2, 3, 4];
+/// let sfs = allele_frequency_spectrum(
+///     &tables,
+///     &[&samples.samples],
+///     None,
+///     StatsMode::Site,
+///     StatsFlags::POLARISED | StatsFlags::NO_SPAN_NORMALISE,
+/// )
+/// .unwrap();
+/// assert_eq!(sfs[0].values(), &[0., 1., 0., 0., 0.]);
+/// ```
+pub fn allele_frequency_spectrum<S: AsRef<[IdType]>>(
+    tables: &TableCollection,
+    sample_sets: &[S],
+    windows: Option<&[Position]>,
+    mode: StatsMode,
+    flags: StatsFlags,
+) -> StatsResult<Vec<FrequencySpectrum>> {
+    let sets = SampleSets::new(tables, sample_sets)?;
+    let windows = make_windows(windows, tables.genome_length())?;
+    let sizes: Vec<usize> = sets.sizes.iter().map(|&n| n as usize).collect();
+    let dims: Vec<usize> = sizes.iter().map(|&n| n + 1).collect();
+    let polarised = flags.contains(StatsFlags::POLARISED);
+
+    let x = std::cell::RefCell::new((vec![0; sizes.len()], vec![]));
+    let f = |counts: &[f64], out: &mut [f64]| {
+        let mut buffers = x.borrow_mut();
+        let (x, folded) = &mut *buffers;
+        for (xi, &c) in x.iter_mut().zip(counts) {
+            *xi = c as usize;
+        }
+        if polarised {
+            out[flat_index(&dims, x).unwrap()] += 1.;
+        } else {
+            fold(x, &sizes, folded);
+            out[flat_index(&dims, folded).unwrap()] += 0.5;
+        }
+    };
+    let stat = GeneralStat {
+        mode,
+        flags,
+        output_dim: dims.iter().product(),
+        polarised,
+        summary: &f,
+    };
+    let rv = general_stat(tables, &sets, &windows, &stat)?;
+    Ok(rv
+        .into_iter()
+        .map(|values| FrequencySpectrum::new(dims.clone(), values))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::*;
+    use crate::wright_fisher::*;
+
+    // The two trees of [`two_trees`], with a mutation
+    // on node 1 at position 10 and on node 3 at position 70.
+    fn make_tables() -> TableCollection {
+        let mut tables = two_trees();
+        tables.add_site(10, Some(vec![0])).unwrap();
+        tables.add_mutation(1, 0, 0, Some(vec![1]), true).unwrap();
+        tables.add_site(70, Some(vec![0])).unwrap();
+        tables.add_mutation(3, 0, 1, Some(vec![1]), true).unwrap();
+        tables
+    }
+
+    #[test]
+    fn test_site_sfs() {
+        let tables = make_tables();
+        let samples = tables.sample_nodes();
+        let flags = StatsFlags::NO_SPAN_NORMALISE;
+        let sfs = allele_frequency_spectrum(
+            &tables,
+            &[&samples],
+            None,
+            StatsMode::Site,
+            flags | StatsFlags::POLARISED,
+        )
+        .unwrap();
+        assert_eq!(sfs.len(), 1);
+        assert_eq!(sfs[0].dims(), &[4]);
+        assert_eq!(sfs[0].values(), &[0., 1., 1., 0.]);
+
+        let sfs =
+            allele_frequency_spectrum(&tables, &[&samples], None, StatsMode::Site, flags).unwrap();
+        assert_eq!(sfs[0].values(), &[0., 2., 0., 0.]);
+
+        let windows = [0, 50, 100];
+        let sfs = allele_frequency_spectrum(
+            &tables,
+            &[&samples],
+            Some(&windows),
+            StatsMode::Site,
+            StatsFlags::POLARISED,
+        )
+        .unwrap();
+        assert_eq!(sfs[0].values(), &[0., 0., 1. / 50., 0.]);
+        assert_eq!(sfs[1].values(), &[0., 1. / 50., 0., 0.]);
+    }
+
+    #[test]
+    fn test_branch_sfs() {
+        let tables = make_tables();
+        let samples = tables.sample_nodes();
+        let sfs = allele_frequency_spectrum(
+            &tables,
+            &[&samples],
+            None,
+            StatsMode::Branch,
+            StatsFlags::POLARISED,
+        )
+        .unwrap();
+        assert_eq!(sfs[0].values(), &[0., 20., 5., 0.]);
+        let sfs = allele_frequency_spectrum(
+            &tables,
+            &[&samples],
+            None,
+            StatsMode::Branch,
+            StatsFlags::empty(),
+        )
+        .unwrap();
+        assert_eq!(sfs[0].values(), &[0., 25., 0., 0.]);
+    }
+
+    #[test]
+    fn test_joint_sfs() {
+        let tables = make_tables();
+        let sfs = allele_frequency_spectrum(
+            &tables,
+            &[vec![2, 3], vec![4]],
+            None,
+            StatsMode::Site,
+            StatsFlags::POLARISED | StatsFlags::NO_SPAN_NORMALISE,
+        )
+        .unwrap();
+        assert_eq!(sfs[0].dims(), &[3, 2]);
+        // Site 0 is in 2 and 3, and site 1 is in 3 only.
+        assert_eq!(sfs[0].get(&[2, 0]), Some(1.));
+        assert_eq!(sfs[0].get(&[1, 0]), Some(1.));
+        assert_eq!(sfs[0].values().iter().sum::<f64>(), 2.);
+        assert_eq!(sfs[0].get(&[3, 0]), None);
+        assert_eq!(sfs[0].get(&[0]), None);
+
+        // Folding a joint spectrum
+        let sfs = allele_frequency_spectrum(
+            &tables,
+            &[vec![2, 3], vec![4]],
+            None,
+            StatsMode::Site,
+            StatsFlags::NO_SPAN_NORMALISE,
+        )
+        .unwrap();
+        assert_eq!(sfs[0].get(&[0, 1]), Some(1.));
+        assert_eq!(sfs[0].get(&[1, 0]), Some(1.));
+    }
+
+    #[test]
+    fn test_fold() {
+        let mut folded = vec![];
+        fold(&[1], &[4], &mut folded);
+        assert_eq!(folded, vec![1]);
+        fold(&[3], &[4], &mut folded);
+        assert_eq!(folded, vec![1]);
+        fold(&[2, 0], &[2, 2], &mut folded);
+        assert_eq!(folded, vec![0, 2]);
+        fold(&[1, 2], &[2, 2], &mut folded);
+        assert_eq!(folded, vec![1, 0]);
+    }
+
+    #[test]
+    fn test_simulated_sfs() {
+        let mut tables = neutral_wf(
+            PopulationParams::new(20, 1000, 1e-3, 0.0),
+            SimulationParams::new(Some(10), 666, 100, SimulationFlags::empty()),
+        )
+        .unwrap();
+        let samples = tables.sample_nodes();
+        for (i, p) in [3, 100, 400, 401, 999].iter().enumerate() {
+            let site = tables.add_site(*p, Some(vec![0])).unwrap() as usize;
+            tables
+                .add_mutation(samples[i], 0, site, Some(vec![1]), true)
+                .unwrap();
+        }
+        let n = samples.len();
+        let windows = [0, 400, 1000];
+        for mode in [StatsMode::Site, StatsMode::Branch] {
+            let pi = diversity(
+                &tables,
+                &[&samples],
+                Some(&windows),
+                mode,
+                StatsFlags::empty(),
+            )
+            .unwrap();
+            let sfs = allele_frequency_spectrum(
+                &tables,
+                &[&samples],
+                Some(&windows),
+                mode,
+                StatsFlags::POLARISED,
+            )
+            .unwrap();
+            let folded = allele_frequency_spectrum(
+                &tables,
+                &[&samples],
+                Some(&windows),
+                mode,
+                StatsFlags::empty(),
+            )
+            .unwrap();
+            for w in 0..2 {
+                let pi_from_sfs: f64 = sfs[w]
+                    .values()
+                    .iter()
+                    .enumerate()
+                    .map(|(x, v)| {
+                        let x = x as f64;
+                        let n = n as f64;
+                        2. * x * (n - x) / (n * (n - 1.)) * v
+                    })
+                    .sum();
+                assert!(close(pi[w][0], pi_from_sfs));
+                for x in 1..n {
+                    let expected = if 2 * x < n {
+                        sfs[w].values()[x] + sfs[w].values()[n - x]
+                    } else if 2 * x == n {
+                        sfs[w].values()[x]
+                    } else {
+                        0.
+                    };
+                    assert!(close(folded[w].values()[x], expected));
+                }
+            }
+
+            // Marginals of the joint spectrum are the
+            // spectra of each sample set.  The first entry
+            // depends on which branches are visited.
+            let a = &samples[..10];
+            let b = &samples[10..];
+            let joint =
+                allele_frequency_spectrum(&tables, &[a, b], None, mode, StatsFlags::POLARISED)
+                    .unwrap();
+            let sfs_a = allele_frequency_spectrum(&tables, &[a], None, mode, StatsFlags::POLARISED)
+                .unwrap();
+            let dims = joint[0].dims().to_vec();
+            for i in 1..dims[0] {
+                let marginal: f64 = (0..dims[1]).map(|j| joint[0].get(&[i, j]).unwrap()).sum();
+                assert!(close(marginal, sfs_a[0].values()[i]));
+            }
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use super::*;
+use crate::tsdef::Time;
+use std::collections::BTreeMap;
+
+/// The genome-wide distribution of the coalescence
+/// times of pairs of samples.
+///
+/// The coalescence time of a pair is measured from the more
+/// recent of the two samples, as in [``tmrca``].
+/// For samples born at times `t_i` and `t_j` whose MRCA has
+/// time `m`, it is `max(t_i, t_j) - m`.
+///
+/// See [``pairwise_coalescence_times``].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PairwiseCoalescenceTimes {
+    coalescence_times: Vec<Time>,
+    weights: Vec<f64>,
+    uncoalesced: f64,
+}
+
+impl PairwiseCoalescenceTimes {
+    /// The distinct coalescence times of pairs of samples,
+    /// in increasing order.
+    pub fn coalescence_times(&self) -> &[Time] {
+        &self.coalescence_times
+    }
+
+    /// The fraction of the genome and of pairs of samples
+    /// having each value of
+    /// [``PairwiseCoalescenceTimes::coalescence_times``].
+    pub fn weights(&self) -> &[f64] {
+        &self.weights
+    }
+
+    /// The fraction of the genome and of pairs of samples
+    /// having no common ancestor.
+    ///
+    /// The weights and this value sum to one.
+    pub fn uncoalesced(&self) -> f64 {
+        self.uncoalesced
+    }
+
+    /// The mean coalescence time of the pairs that have
+    /// a common ancestor.
+    ///
+    /// Returns [``f64::NAN``] if no pair has a common ancestor.
+    pub fn mean_coalescence_time(&self) -> f64 {
+        let total: f64 = self.weights.iter().sum();
+        let sum: f64 = self
+            .coalescence_times
+            .iter()
+            .zip(self.weights.iter())
+            .map(|(t, w)| (*t as f64) * w)
+            .sum();
+        sum / total
+    }
+}
+
+/// The most recent common ancestor of a set of nodes
+/// at a genomic position.
+///
+/// # Parameters
+///
+/// * `tables`: the tables
+/// * `nodes`: the nodes
+/// * `position`: the genomic position
+///
+/// # Returns
+///
+/// The ``ID`` of the MRCA, or [``None``] if the nodes
+/// do not have a common ancestor at `position`.
+/// A node is its own ancestor, so the MRCA of a single
+/// node is that node.
+///
+/// # Errors
+///
+/// [``StatsError``] if `nodes` is empty, contains a node more
+/// than once, or refers to invalid nodes, or if `position` is
+/// not in `[0, genome_length)`.
+///
+/// # Example
+///
+/// ```
+/// use forrustts::stats::mrca;
+/// use forrustts::TableCollection;
+///
+/// let mut tables = TableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap();
+/// tables.add_node(5, 0).unwrap();
+/// for _ in 0..3 {
+///     tables.add_node(10, 0).unwrap();
+/// }
+/// tables.add_edge(0, 100, 0, 1).unwrap();
+/// tables.add_edge(0, 100, 1, 2).unwrap();
+/// tables.add_edge(0, 100, 1, 3).unwrap();
+/// tables.add_edge(0, 50, 0, 4).unwrap();
+///
+/// assert_eq!(mrca(&tables, &[2, 3], 75).unwrap(), Some(1));
+/// assert_eq!(mrca(&tables, &[2, 3, 4], 25).unwrap(), Some(0));
+/// assert_eq!(mrca(&tables, &[2, 3, 4], 75).unwrap(), None);
+/// ```
+pub fn mrca(
+    tables: &TableCollection,
+    nodes: &[IdType],
+    position: Position,
+) -> StatsResult<Option<IdType>> {
+    SampleSets::new(tables, &[nodes])?;
+    let mut trees = TreeIterator::new_with_samples(tables, nodes)?;
+    let tree = trees.tree_at(position)?;
+    let mut rv = nodes[0];
+    for &u in &nodes[1..] {
+        rv = tree.mrca(rv, u);
+        if rv == NULL_ID {
+            return Ok(None);
+        }
+    }
+    Ok(Some(rv))
+}
+
+/// The time to the most recent common ancestor of a
+/// set of nodes at a genomic position.
+///
+/// The time is measured from the most recent of `nodes`,
+/// so that it is the largest time of the nodes minus
+/// the time of their MRCA (see [``mrca``]).
+///
+/// # Returns
+///
+/// [``None``] if the nodes do not have a common ancestor
+/// at `position`.
+///
+/// # Errors
+///
+/// See [``mrca``].
+///
+/// # Example
+///
+/// ```
+/// use forrustts::stats::tmrca;
+/// use forrustts::TableCollection;
+///
+/// let mut tables = TableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap();
+/// tables.add_node(8, 0).unwrap();
+/// tables.add_node(10, 0).unwrap();
+/// tables.add_edge(0, 100, 0, 1).unwrap();
+/// tables.add_edge(0, 100, 0, 2).unwrap();
+///
+/// assert_eq!(tmrca(&tables, &[1, 2], 0).unwrap(), Some(10));
+/// ```
+pub fn tmrca(
+    tables: &TableCollection,
+    nodes: &[IdType],
+    position: Position,
+) -> StatsResult<Option<Time>> {
+    let ancestor = match mrca(tables, nodes, position)? {
+        Some(a) => a,
+        None => return Ok(None),
+    };
+    let youngest = nodes.iter().map(|u| tables.node(*u).time).max().unwrap();
+    Ok(Some(youngest - tables.node(ancestor).time))
+}
+
+/// The genome-wide distribution of the coalescence times
+/// of all pairs of samples.
+///
+/// See [``PairwiseCoalescenceTimes``] for how the
+/// times are measured when samples have different ages.
+///
+/// For each marginal tree, each pair of samples is weighted
+/// by the length of the tree's interval.
+/// The weights are divided by the genome length and the
+/// number of pairs.
+///
+/// # Parameters
+///
+/// * `tables`: the tables
+/// * `samples`: the sample nodes
+///
+/// # Errors
+///
+/// [``StatsError``] if `samples` is empty, contains a node more
+/// than once, or refers to invalid nodes.
+///
+/// # Example
+///
+/// ```
+/// use forrustts::stats::pairwise_coalescence_times;
+/// use forrustts::TableCollection;
+///
+/// let mut tables = TableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap();
+/// tables.add_node(5, 0).unwrap();
+/// for _ in 0..3 {
+///     tables.add_node(10, 0).unwrap();
+/// }
+/// tables.add_edge(0, 100, 0, 1).unwrap();
+/// tables.add_edge(0, 100, 1, 2).unwrap();
+/// tables.add_edge(0, 100, 1, 3).unwrap();
+/// tables.add_edge(0, 100, 0, 4).unwrap();
+///
+/// let times = pairwise_coalescence_times(&tables, &[2, 3, 4]).unwrap();
+/// assert_eq!(times.coalescence_times(), &[5, 10]);
+/// assert_eq!(times.weights(), &[1. / 3., 2. / 3.]);
+/// assert_eq!(times.uncoalesced(), 0.);
+/// ```
+pub fn pairwise_coalescence_times(
+    tables: &TableCollection,
+    samples: &[IdType],
+) -> StatsResult<PairwiseCoalescenceTimes> {
+    SampleSets::new(tables, &[samples])?;
+    let n = samples.len() as f64;
+    let num_pairs = n * (n - 1.) / 2.;
+
+    // Samples are grouped into classes by their time,
+    // from the oldest to the youngest.
+    let mut sample_times: Vec<Time> = samples.iter().map(|&s| tables.node(s).time).collect();
+    sample_times.sort_unstable();
+    sample_times.dedup();
+    let num_classes = sample_times.len();
+    let mut class = vec![usize::MAX; tables.num_nodes()];
+    for &s in samples {
+        class[s as usize] = sample_times.binary_search(&tables.node(s).time).unwrap();
+    }
+
+    // The number of samples of each class below each node.
+    let mut counts = vec![0.; tables.num_nodes() * num_classes];
+    // For the children of a node, and the node itself,
+    // the sums and sums of squares of the number of samples
+    // of each class or older.
+    let mut sums = vec![0.; num_classes];
+    let mut squares = vec![0.; num_classes];
+
+    let mut totals = BTreeMap::<Time, f64>::new();
+    let mut trees = TreeIterator::new_with_samples(tables, samples)?;
+    let mut stack = vec![];
+    let mut order = vec![];
+    while let Some(tree) = trees.next_tree() {
+        let (left, right) = tree.interval();
+        let span = (right - left) as f64;
+        order.clear();
+        stack.extend(tree.roots());
+        while let Some(u) = stack.pop() {
+            order.push(u);
+            stack.extend(tree.children(u).filter(|c| tree.num_samples(*c) > 0));
+        }
+        // Children are visited before their parents.
+        for &u in order.iter().rev() {
+            let row = u as usize * num_classes;
+            sums.iter_mut().for_each(|x| *x = 0.);
+            squares.iter_mut().for_each(|x| *x = 0.);
+            counts[row..row + num_classes]
+                .iter_mut()
+                .for_each(|x| *x = 0.);
+            if class[u as usize] != usize::MAX {
+                counts[row + class[u as usize]] = 1.;
+                for k in class[u as usize]..num_classes {
+                    sums[k] += 1.;
+                    squares[k] += 1.;
+                }
+            }
+            for c in tree.children(u).filter(|c| tree.num_samples(*c) > 0) {
+                let child_row = c as usize * num_classes;
+                let mut cumulative = 0.;
+                for k in 0..num_classes {
+                    let x = counts[child_row + k];
+                    counts[row + k] += x;
+                    cumulative += x;
+                    sums[k] += cumulative;
+                    squares[k] += cumulative * cumulative;
+                }
+            }
+            // The number of pairs coalescing at u whose
+            // younger sample is of class k or older.
+            let pairs = |k: usize| (sums[k] * sums[k] - squares[k]) / 2.;
+            let time = tables.node(u).time;
+            let mut previous = 0.;
+            for (k, t) in sample_times.iter().enumerate() {
+                let current = pairs(k);
+                if current > previous {
+                    *totals.entry(t - time).or_insert(0.) += span * (current - previous);
+                }
+                previous = current;
+            }
+        }
+    }
+
+    if num_pairs == 0. {
+        return Ok(PairwiseCoalescenceTimes {
+            coalescence_times: vec![],
+            weights: vec![],
+            uncoalesced: 0.,
+        });
+    }
+    let denominator = (tables.genome_length() as f64) * num_pairs;
+    let coalesced: f64 = totals.values().sum();
+    Ok(PairwiseCoalescenceTimes {
+        coalescence_times: totals.keys().cloned().collect(),
+        weights: totals.values().map(|v| v / denominator).collect(),
+        uncoalesced: 1. - coalesced / denominator,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::two_trees;
+    use crate::wright_fisher::*;
+
+    // The trees of [`two_trees`], with node 5 (time 7)
+    // a child of node 1 in [0, 50) and
+    // not connected to anything in [50, 100).
+    fn make_tables() -> TableCollection {
+        let mut tables = two_trees();
+        tables.add_node(7, 0).unwrap();
+        tables.add_edge(0, 50, 1, 5).unwrap();
+        tables
+    }
+
+    #[test]
+    fn test_mrca() {
+        let tables = make_tables();
+        assert_eq!(mrca(&tables, &[4], 10).unwrap(), Some(4));
+        assert_eq!(mrca(&tables, &[2, 5], 10).unwrap(), Some(1));
+        assert_eq!(mrca(&tables, &[2, 5], 60).unwrap(), None);
+        assert_eq!(mrca(&tables, &[2, 4], 49).unwrap(), Some(0));
+        assert_eq!(mrca(&tables, &[4, 2], 50).unwrap(), Some(1));
+        assert_eq!(mrca(&tables, &[3, 2, 5], 90).unwrap(), None);
+        assert_eq!(mrca(&tables, &[3, 2, 4], 90).unwrap(), Some(0));
+        assert_eq!(tmrca(&tables, &[2, 4], 49).unwrap(), Some(10));
+        assert_eq!(tmrca(&tables, &[1, 5], 0).unwrap(), Some(2));
+        assert_eq!(tmrca(&tables, &[2, 5], 50).unwrap(), None);
+    }
+
+    #[test]
+    fn test_pairwise_coalescence_times() {
+        let tables = make_tables();
+        let times = pairwise_coalescence_times(&tables, &[2, 3, 4]).unwrap();
+        // In each tree, one pair coalesces at node 1
+        // and two pairs coalesce at node 0.
+        let denom = 300.;
+        assert_eq!(times.coalescence_times(), &[5, 10]);
+        let expected = [100. / denom, 200. / denom];
+        for (w, e) in times.weights().iter().zip(expected.iter()) {
+            assert!((w - e).abs() < 1e-12);
+        }
+        assert_eq!(times.uncoalesced(), 0.);
+        let mean = (5. * 100. + 10. * 200.) / 300.;
+        assert!((times.mean_coalescence_time() - mean).abs() < 1e-12);
+
+        // Samples of different ages.
+        // In [0, 50), pair (1, 5) coalesces at node 1, 2 units
+        // after node 5, and pairs (1, 4) and (4, 5) coalesce
+        // at node 0, 10 units after node 4.
+        // In [50, 100), only pair (1, 4) coalesces, at node 1.
+        let times = pairwise_coalescence_times(&tables, &[5, 1, 4]).unwrap();
+        assert_eq!(times.coalescence_times(), &[2, 5, 10]);
+        let expected = [50. / denom, 50. / denom, 100. / denom];
+        for (w, e) in times.weights().iter().zip(expected.iter()) {
+            assert!((w - e).abs() < 1e-12);
+        }
+        assert!((times.uncoalesced() - 100. / denom).abs() < 1e-12);
+        for (pair, position) in [([1, 5], 0), ([4, 5], 0), ([1, 4], 60)] {
+            let t = tmrca(&tables, &pair, position).unwrap().unwrap();
+            assert!(times.coalescence_times().contains(&t));
+        }
+
+        let times = pairwise_coalescence_times(&tables, &[5]).unwrap();
+        assert!(times.coalescence_times().is_empty());
+        assert!(times.mean_coalescence_time().is_nan());
+    }
+
+    #[test]
+    fn test_simulated_data() {
+        let tables = neutral_wf(
+            PopulationParams::new(50, 10000, 5e-3, 0.0),
+            SimulationParams::new(Some(25), 8123, 500, SimulationFlags::empty()),
+        )
+        .unwrap();
+        let samples = tables.sample_nodes();
+        let times = pairwise_coalescence_times(&tables, &samples[..10]).unwrap();
+        let total: f64 = times.weights().iter().sum::<f64>() + times.uncoalesced();
+        assert!((total - 1.).abs() < 1e-9);
+
+        // Compare to pairwise queries at a few positions.
+        for position in [0, 1234, 9999] {
+            for i in 0..10 {
+                for j in (i + 1)..10 {
+                    let pair = [samples[i], samples[j]];
+                    let a = mrca(&tables, &pair, position).unwrap();
+                    let t = tmrca(&tables, &pair, position).unwrap();
+                    match a {
+                        Some(a) => assert_eq!(
+                            t.unwrap(),
+                            tables.node(samples[i]).time - tables.node(a).time
+                        ),
+                        None => assert!(t.is_none()),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let tables = make_tables();
+        assert_eq!(mrca(&tables, &[], 0), Err(StatsError::EmptySampleSet));
+        assert_eq!(
+            mrca(&tables, &[2, 2], 0),
+            Err(StatsError::DuplicateSample { found: 2 })
+        );
+        assert_eq!(
+            tmrca(&tables, &[2], 100),
+            Err(TablesError::InvalidPosition { found: 100 }.into())
+        );
+        assert_eq!(
+            pairwise_coalescence_times(&tables, &[20]),
+            Err(TablesError::NodeOutOfBounds.into())
+        );
+    }
+}
@@ -0,0 +1,436 @@
+use super::*;
+use std::collections::VecDeque;
+
+/// The alleles and genotypes at a [``Site``].
+///
+/// See [``VariantIterator``].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variant {
+    site: usize,
+    position: Position,
+    alleles: Vec<Vec<u8>>,
+    genotypes: Vec<i32>,
+}
+
+impl Variant {
+    /// The index of the site in the site table.
+    pub fn site(&self) -> usize {
+        self.site
+    }
+
+    /// The position of the site.
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// The distinct allelic states at this site.
+    ///
+    /// The first allele is the ancestral state.
+    /// The others are the derived states of the
+    /// site's mutations, in the order first seen.
+    pub fn alleles(&self) -> &[Vec<u8>] {
+        &self.alleles
+    }
+
+    /// The number of alleles.
+    pub fn num_alleles(&self) -> usize {
+        self.alleles.len()
+    }
+
+    /// For each sample, the index of its allele
+    /// in [``Variant::alleles``].
+    pub fn genotypes(&self) -> &[i32] {
+        &self.genotypes
+    }
+}
+
+/// Iterate over the [``Variant``]s of a [``TableCollection``],
+/// in order of site position.
+///
+/// Each sample carries the state of the most recent mutation
+/// above it in the tree covering the site, or the
+/// ancestral state if there is no such mutation.
+/// A mutation is more recent than another if its node has a larger
+/// [``Time``](crate::Time).
+/// For mutations on the same node, the one that comes later in the
+/// mutation table is more recent.
+///
+/// States that are [``None``] are replaced by default values.
+/// The defaults are `b"0"` for ancestral states and
+/// `b"1"` for derived states.
+/// See [``VariantIterator::with_default_states``].
+///
+/// # Example
+///
+/// ```
+/// use forrustts::stats::VariantIterator;
+/// use forrustts::TableCollection;
+///
+/// let mut tables = TableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap();
+/// tables.add_node(10, 0).unwrap();
+/// tables.add_node(10, 0).unwrap();
+/// tables.add_edge(0, 100, 0, 1).unwrap();
+/// tables.add_edge(0, 100, 0, 2).unwrap();
+/// tables.add_site(20, Some(b"A".to_vec())).unwrap();
+/// tables.add_mutation(2, 0, 0, Some(b"T".to_vec()), true).unwrap();
+/// tables.add_site(10, None).unwrap();
+/// tables.add_mutation(1, 0, 1, None, true).unwrap();
+///
+/// let variants: Vec<_> = VariantIterator::new(&tables, &[1, 2]).unwrap().collect();
+/// assert_eq!(variants[0].position(), 10);
+/// assert_eq!(variants[0].alleles(), &[b"0".to_vec(), b"1".to_vec()]);
+/// assert_eq!(variants[0].genotypes(), &[1, 0]);
+/// assert_eq!(variants[1].alleles(), &[b"A".to_vec(), b"T".to_vec()]);
+/// assert_eq!(variants[1].genotypes(), &[0, 1]);
+/// ```
+pub struct VariantIterator<'a> {
+    tables: &'a TableCollection,
+    trees: TreeIterator<'a>,
+    samples: Vec<IdType>,
+    sample_index: Vec<usize>,
+    site_order: Vec<usize>,
+    site_mutations: Vec<Vec<usize>>,
+    next_site: usize,
+    default_ancestral_state: Vec<u8>,
+    default_derived_state: Vec<u8>,
+    buffer: VecDeque<Variant>,
+}
+
+impl<'a> VariantIterator<'a> {
+    /// Iterate over variants for the given samples.
+    ///
+    /// # Errors
+    ///
+    /// [``StatsError``] if the samples or tables are invalid.
+    pub fn new(tables: &'a TableCollection, samples: &[IdType]) -> StatsResult<Self> {
+        SampleSets::new(tables, &[samples])?;
+        let (site_order, site_mutations) = index_sites(tables)?;
+        let mut sample_index = vec![usize::MAX; tables.num_nodes()];
+        for (i, &s) in samples.iter().enumerate() {
+            sample_index[s as usize] = i;
+        }
+        Ok(VariantIterator {
+            tables,
+            trees: TreeIterator::new_with_samples(tables, samples)?,
+            samples: samples.to_vec(),
+            sample_index,
+            site_order,
+            site_mutations,
+            next_site: 0,
+            default_ancestral_state: b"0".to_vec(),
+            default_derived_state: b"1".to_vec(),
+            buffer: VecDeque::new(),
+        })
+    }
+
+    /// Set the states used when a site's ancestral state
+    /// or a mutation's derived state is [``None``].
+    pub fn with_default_states(mut self, ancestral: &[u8], derived: &[u8]) -> Self {
+        self.default_ancestral_state = ancestral.to_vec();
+        self.default_derived_state = derived.to_vec();
+        self
+    }
+
+    /// The samples, in the order used for genotypes.
+    pub fn samples(&self) -> &[IdType] {
+        &self.samples
+    }
+
+    /// Collect the remaining variants into a [``GenotypeMatrix``].
+    pub fn genotype_matrix(self) -> GenotypeMatrix {
+        let num_samples = self.samples.len();
+        let variants: Vec<Variant> = self.collect();
+        let num_sites = variants.len();
+        let mut values = vec![0; num_samples * num_sites];
+        for (j, v) in variants.iter().enumerate() {
+            for (i, &g) in v.genotypes.iter().enumerate() {
+                values[i * num_sites + j] = g;
+            }
+        }
+        GenotypeMatrix {
+            num_samples,
+            sites: variants.iter().map(|v| v.site).collect(),
+            positions: variants.iter().map(|v| v.position).collect(),
+            values,
+        }
+    }
+
+    /// Calculate the variants for the sites in the next tree.
+    /// Returns `false` when there are no more trees.
+    fn fill_buffer(&mut self) -> bool {
+        let VariantIterator {
+            tables,
+            trees,
+            samples,
+            sample_index,
+            site_order,
+            site_mutations,
+            next_site,
+            default_ancestral_state,
+            default_derived_state,
+            buffer,
+        } = self;
+        let tree = match trees.next_tree() {
+            Some(tree) => tree,
+            None => return false,
+        };
+        let mut stack = vec![];
+        while *next_site < site_order.len()
+            && tables.site(site_order[*next_site] as IdType).position < tree.interval().1
+        {
+            let site = site_order[*next_site];
+            let s = tables.site(site as IdType);
+            let mut alleles = vec![s
+                .ancestral_state
+                .clone()
+                .unwrap_or_else(|| default_ancestral_state.clone())];
+            let mut genotypes = vec![0; samples.len()];
+
+            // Older mutations are applied first, so that
+            // more recent ones overwrite them.
+            let mut mutations = site_mutations[site].clone();
+            mutations.sort_by_key(|&m| tables.node(tables.mutation(m as IdType).node).time);
+            for m in mutations {
+                let m = tables.mutation(m as IdType);
+                let state = m.derived_state.as_ref().unwrap_or(default_derived_state);
+                let allele = match alleles.iter().position(|a| a == state) {
+                    Some(a) => a,
+                    None => {
+                        alleles.push(state.clone());
+                        alleles.len() - 1
+                    }
+                };
+                stack.push(m.node);
+                while let Some(u) = stack.pop() {
+                    let i = sample_index[u as usize];
+                    if i != usize::MAX {
+                        genotypes[i] = allele as i32;
+                    }
+                    stack.extend(tree.children(u));
+                }
+            }
+            buffer.push_back(Variant {
+                site,
+                position: s.position,
+                alleles,
+                genotypes,
+            });
+            *next_site += 1;
+        }
+        true
+    }
+}
+
+impl<'a> Iterator for VariantIterator<'a> {
+    type Item = Variant;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffer.is_empty() {
+            if self.next_site >= self.site_order.len() || !self.fill_buffer() {
+                return None;
+            }
+        }
+        self.buffer.pop_front()
+    }
+}
+
+/// A dense matrix of genotypes.
+///
+/// Rows are samples and columns are sites, in
+/// order of position.
+/// Values are stored in row-major order, so that
+/// each sample's haplotype is contiguous.
+///
+/// See [``VariantIterator::genotype_matrix``].
+///
+/// # Example
+///
+/// ```
+/// use forrustts::stats::VariantIterator;
+/// use forrustts::TableCollection;
+///
+/// let mut tables = TableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap();
+/// tables.add_node(10, 0).unwrap();
+/// tables.add_node(10, 0).unwrap();
+/// tables.add_edge(0, 100, 0, 1).unwrap();
+/// tables.add_edge(0, 100, 0, 2).unwrap();
+/// for (position, node) in [(10, 1), (20, 2), (30, 0)] {
+///     let site = tables.add_site(position, None).unwrap();
+///     tables.add_mutation(node, 0, site as usize, None, true).unwrap();
+/// }
+///
+/// let matrix = VariantIterator::new(&tables, &[1, 2])
+///     .unwrap()
+///     .genotype_matrix();
+/// assert_eq!(matrix.num_samples(), 2);
+/// assert_eq!(matrix.num_sites(), 3);
+/// assert_eq!(matrix.haplotype(0), &[1, 0, 1]);
+/// assert_eq!(matrix.haplotype(1), &[0, 1, 1]);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenotypeMatrix {
+    num_samples: usize,
+    sites: Vec<usize>,
+    positions: Vec<Position>,
+    values: Vec<i32>,
+}
+
+impl GenotypeMatrix {
+    /// The number of samples (rows).
+    pub fn num_samples(&self) -> usize {
+        self.num_samples
+    }
+
+    /// The number of sites (columns).
+    pub fn num_sites(&self) -> usize {
+        self.sites.len()
+    }
+
+    /// The site table index of each column.
+    pub fn sites(&self) -> &[usize] {
+        &self.sites
+    }
+
+    /// The position of each column.
+    pub fn positions(&self) -> &[Position] {
+        &self.positions
+    }
+
+    /// All values, in row-major order.
+    pub fn values(&self) -> &[i32] {
+        &self.values
+    }
+
+    /// The genotypes of sample `i`.
+    ///
+    /// # Panics
+    ///
+    /// If `i` is out of range.
+    pub fn haplotype(&self, i: usize) -> &[i32] {
+        let n = self.num_sites();
+        &self.values[i * n..(i + 1) * n]
+    }
+
+    /// The genotype of sample `i` at site `j`.
+    pub fn get(&self, i: usize, j: usize) -> Option<i32> {
+        if i >= self.num_samples || j >= self.num_sites() {
+            None
+        } else {
+            Some(self.values[i * self.num_sites() + j])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::balanced_tree;
+
+    #[test]
+    fn test_variants() {
+        let mut tables = balanced_tree(Some(50));
+        tables.add_site(60, Some(b"A".to_vec())).unwrap();
+        tables
+            .add_mutation(1, 0, 0, Some(b"G".to_vec()), true)
+            .unwrap();
+        tables.add_site(10, Some(b"A".to_vec())).unwrap();
+        // Mutations are added out of time order.
+        tables
+            .add_mutation(3, 0, 1, Some(b"T".to_vec()), true)
+            .unwrap();
+        tables
+            .add_mutation(1, 0, 1, Some(b"G".to_vec()), true)
+            .unwrap();
+        // A back mutation
+        tables
+            .add_mutation(6, 0, 1, Some(b"A".to_vec()), true)
+            .unwrap();
+        tables
+            .add_mutation(0, 0, 1, Some(b"C".to_vec()), true)
+            .unwrap();
+        // A site without mutations
+        tables.add_site(90, None).unwrap();
+
+        let samples = tables.sample_nodes();
+        let variants: Vec<Variant> = VariantIterator::new(&tables, &samples).unwrap().collect();
+        assert_eq!(variants.len(), 3);
+
+        let v = &variants[0];
+        assert_eq!(v.site(), 1);
+        assert_eq!(v.position(), 10);
+        assert_eq!(
+            v.alleles(),
+            &[b"A".to_vec(), b"C".to_vec(), b"G".to_vec(), b"T".to_vec()]
+        );
+        assert_eq!(v.num_alleles(), 4);
+        assert_eq!(v.genotypes(), &[3, 2, 1, 0]);
+
+        let v = &variants[1];
+        assert_eq!(v.site(), 0);
+        assert_eq!(v.genotypes(), &[1, 0, 1, 0]);
+
+        let v = &variants[2];
+        assert_eq!(v.alleles(), &[b"0".to_vec()]);
+        assert_eq!(v.genotypes(), &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_default_states() {
+        let mut tables = balanced_tree(Some(50));
+        tables.add_site(10, None).unwrap();
+        tables.add_mutation(1, 0, 0, None, true).unwrap();
+        tables.add_mutation(5, 0, 0, None, true).unwrap();
+        let variants: Vec<Variant> = VariantIterator::new(&tables, &[3, 5, 6])
+            .unwrap()
+            .with_default_states(b"ref", b"alt")
+            .collect();
+        assert_eq!(variants[0].alleles(), &[b"ref".to_vec(), b"alt".to_vec()]);
+        assert_eq!(variants[0].genotypes(), &[1, 1, 0]);
+    }
+
+    #[test]
+    fn test_genotype_matrix() {
+        let mut tables = balanced_tree(Some(50));
+        for (position, node) in [(20, 1), (70, 2), (80, 5), (40, 4)] {
+            let site = tables.add_site(position, None).unwrap() as usize;
+            tables.add_mutation(node, 0, site, None, true).unwrap();
+        }
+        let samples = tables.sample_nodes();
+        let matrix = VariantIterator::new(&tables, &samples)
+            .unwrap()
+            .genotype_matrix();
+        assert_eq!(matrix.num_samples(), 4);
+        assert_eq!(matrix.num_sites(), 4);
+        assert_eq!(matrix.sites(), &[0, 3, 1, 2]);
+        assert_eq!(matrix.positions(), &[20, 40, 70, 80]);
+        assert_eq!(matrix.haplotype(0), &[1, 0, 0, 0]);
+        assert_eq!(matrix.haplotype(1), &[1, 1, 1, 0]);
+        assert_eq!(matrix.haplotype(2), &[0, 0, 0, 1]);
+        assert_eq!(matrix.haplotype(3), &[0, 0, 1, 0]);
+        assert_eq!(matrix.get(1, 2), Some(1));
+        assert_eq!(matrix.get(4, 0), None);
+        assert_eq!(matrix.values().len(), 16);
+
+        // Columns agree with the variants
+        let variants = VariantIterator::new(&tables, &samples).unwrap();
+        for (j, v) in variants.enumerate() {
+            for (i, &g) in v.genotypes().iter().enumerate() {
+                assert_eq!(matrix.get(i, j), Some(g));
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut tables = balanced_tree(Some(50));
+        assert!(VariantIterator::new(&tables, &[3, 3]).is_err());
+        assert!(VariantIterator::new(&tables, &[7]).is_err());
+        tables.add_mutation(3, 0, 0, None, true).unwrap();
+        assert_eq!(
+            VariantIterator::new(&tables, &[3]).err(),
+            Some(StatsError::InvalidSite { found: 0 })
+        );
+    }
+}
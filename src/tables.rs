@@ -1,4 +1,5 @@
 use crate::tsdef::{IdType, Position, Time, NULL_ID};
+use crate::MemoryUsage;
 use bitflags::bitflags;
 use std::cmp::Ordering;
 use thiserror::Error;
@@ -67,17 +68,78 @@ pub enum TablesError {
     #[error("Duplicate edges")]
     /// Can be raised by [``validate_edge_table``]
     DuplicateEdges,
+    #[error("Invalid node mapping")]
+    /// Raised by [``TableCollection::union``] when the
+    /// node mapping has the wrong length or maps two
+    /// nodes to the same node.
+    InvalidNodeMapping,
+    #[error("Shared history differs")]
+    /// Raised by [``TableCollection::union``] when the
+    /// shared nodes or the edges between them differ.
+    SharedHistoryMismatch,
+    #[error("Invalid site: {found:?}")]
+    /// Raised when a mutation refers to a site
+    /// that does not exist.
+    InvalidSite {
+        /// The invalid site index
+        found: usize,
+    },
 }
 
 /// Result type for operations on tables
 pub type TablesResult<T> = std::result::Result<T, TablesError>;
 
+bitflags! {
+    /// Flags describing a [``Node``].
+    ///
+    /// [``NodeFlags::IS_SAMPLE``] has the same value as
+    /// ``tskit``'s ``TSK_NODE_IS_SAMPLE``, and all flags are
+    /// exported to ``tskit`` unchanged.
+    /// The bits in [``NodeFlags::USER_BITS``] are reserved
+    /// for client code.
+    ///
+    /// ```
+    /// use forrustts::NodeFlags;
+    /// let f = NodeFlags::IS_SAMPLE | NodeFlags::IS_ALIVE;
+    /// assert!(f.is_sample());
+    /// assert!(f.contains(NodeFlags::IS_ALIVE));
+    /// assert!(!f.contains(NodeFlags::IS_PRESERVED));
+    /// // A user-defined flag
+    /// let f = NodeFlags::from_bits(1 << 20).unwrap();
+    /// assert!(NodeFlags::USER_BITS.contains(f));
+    /// ```
+    #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct NodeFlags: u32 {
+        /// The node is a sample.
+        const IS_SAMPLE = 1 << 0;
+        /// The node is alive.
+        const IS_ALIVE = 1 << 1;
+        /// The node is preserved, *e.g.* as an ancient sample.
+        const IS_PRESERVED = 1 << 2;
+        /// The bits available to client code.
+        const USER_BITS = 0xFFFF_0000;
+    }
+}
+
+impl NodeFlags {
+    /// [``true``] if [``NodeFlags::IS_SAMPLE``] is set.
+    pub fn is_sample(&self) -> bool {
+        self.contains(NodeFlags::IS_SAMPLE)
+    }
+}
+
 /// A Node of a tree sequence
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Node {
     /// Birth time
     pub time: Time,
     /// Population (deme) of node
     pub deme: IdType,
+    /// Flags describing the node.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub flags: NodeFlags,
 }
 
 /// An Edge is a transmission event
@@ -85,7 +147,8 @@ pub struct Node {
 /// An edge is a record of transmission of
 /// a half-open chunk of genome `[left, right)`
 /// from `parent` to `child`.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Edge {
     /// Left end
     pub left: Position,
@@ -103,6 +166,8 @@ pub struct Edge {
 
 /// A Site is the location and
 /// ancestral state of a tables::MutationRecord
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Site {
     /// Position of the mutation
     pub position: Position,
@@ -115,6 +180,8 @@ pub struct Site {
 /// A MutationRecord is the minimal information
 /// needed about a mutation to track it
 /// on a tree sequence.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MutationRecord {
     /// The node where the mutation maps
     pub node: IdType,
@@ -140,7 +207,7 @@ pub type SiteTable = Vec<Site>;
 /// A Mutation table
 pub type MutationTable = Vec<MutationRecord>;
 
-fn position_non_negative(x: Position) -> TablesResult<()> {
+pub(crate) fn position_non_negative(x: Position) -> TablesResult<()> {
     if x < 0 {
         Err(TablesError::InvalidPosition { found: x })
     } else {
@@ -148,7 +215,7 @@ fn position_non_negative(x: Position) -> TablesResult<()> {
     }
 }
 
-fn node_non_negative(x: IdType) -> TablesResult<()> {
+pub(crate) fn node_non_negative(x: IdType) -> TablesResult<()> {
     if x < 0 {
         Err(TablesError::InvalidNodeValue { found: x })
     } else {
@@ -164,7 +231,7 @@ fn node_non_negative(x: IdType) -> TablesResult<()> {
 //    }
 //}
 
-fn deme_non_negative(x: IdType) -> TablesResult<()> {
+pub(crate) fn deme_non_negative(x: IdType) -> TablesResult<()> {
     if x < 0 {
         Err(TablesError::InvalidDeme { found: x })
     } else {
@@ -172,13 +239,12 @@ fn deme_non_negative(x: IdType) -> TablesResult<()> {
     }
 }
 
-fn edge_table_add_row(
-    edges: &mut EdgeTable,
+pub(crate) fn validate_edge_row(
     left: Position,
     right: Position,
     parent: IdType,
     child: IdType,
-) -> TablesResult<IdType> {
+) -> TablesResult<()> {
     if right <= left {
         return Err(TablesError::InvalidLeftRight {
             found: (left, right),
@@ -187,7 +253,66 @@ fn edge_table_add_row(
     position_non_negative(left)?;
     position_non_negative(right)?;
     node_non_negative(parent)?;
-    node_non_negative(child)?;
+    node_non_negative(child)
+}
+
+pub(crate) fn validate_site_position(
+    position: Position,
+    genome_length: Position,
+) -> TablesResult<()> {
+    if position >= genome_length || position < 0 {
+        return Err(TablesError::InvalidPosition { found: position });
+    }
+    Ok(())
+}
+
+/// Access to the rows of a node table,
+/// whatever its memory layout.
+pub(crate) trait NodeRows {
+    fn num_rows(&self) -> usize;
+    fn node_time(&self, i: usize) -> Time;
+    fn node_row(&self, i: usize) -> Node;
+}
+
+/// Access to the rows of an edge table,
+/// whatever its memory layout.
+pub(crate) trait EdgeRows {
+    fn num_rows(&self) -> usize;
+    fn edge_row(&self, i: usize) -> Edge;
+}
+
+impl NodeRows for [Node] {
+    fn num_rows(&self) -> usize {
+        self.len()
+    }
+
+    fn node_time(&self, i: usize) -> Time {
+        self[i].time
+    }
+
+    fn node_row(&self, i: usize) -> Node {
+        self[i].clone()
+    }
+}
+
+impl EdgeRows for [Edge] {
+    fn num_rows(&self) -> usize {
+        self.len()
+    }
+
+    fn edge_row(&self, i: usize) -> Edge {
+        self[i]
+    }
+}
+
+fn edge_table_add_row(
+    edges: &mut EdgeTable,
+    left: Position,
+    right: Position,
+    parent: IdType,
+    child: IdType,
+) -> TablesResult<IdType> {
+    validate_edge_row(left, right, parent, child)?;
 
     edges.push(Edge {
         left,
@@ -200,10 +325,15 @@ fn edge_table_add_row(
 }
 
 // NOTE: we allow negative times, in order to support "precapitation".
-fn node_table_add_row(nodes: &mut NodeTable, time: Time, deme: IdType) -> TablesResult<IdType> {
+fn node_table_add_row(
+    nodes: &mut NodeTable,
+    time: Time,
+    deme: IdType,
+    flags: NodeFlags,
+) -> TablesResult<IdType> {
     //time_non_negative(time)?;
     deme_non_negative(deme)?;
-    nodes.push(Node { time, deme });
+    nodes.push(Node { time, deme, flags });
 
     Ok((nodes.len() - 1) as IdType)
 }
@@ -240,34 +370,33 @@ fn mutation_table_add_row(
     Ok((mutations.len() - 1) as IdType)
 }
 
-fn sort_edge_table(nodes: &[Node], edges: &mut EdgeTable) {
-    // NOTE: it may by more idiomatic to
-    // not use a slice here, and instead allow
-    // the range-checking?
-    edges.sort_by(|a, b| {
-        let aindex = a.parent as usize;
-        let bindex = b.parent as usize;
-        let ta = nodes[aindex].time;
-        let tb = nodes[bindex].time;
-        if ta == tb {
-            if a.parent == b.parent {
-                if a.child == b.child {
-                    return a.left.cmp(&b.left);
-                }
-                return a.child.cmp(&b.child);
+/// The order of edges for simplification.
+pub(crate) fn edge_sort_order<N: NodeRows + ?Sized>(nodes: &N, a: &Edge, b: &Edge) -> Ordering {
+    let ta = nodes.node_time(a.parent as usize);
+    let tb = nodes.node_time(b.parent as usize);
+    if ta == tb {
+        if a.parent == b.parent {
+            if a.child == b.child {
+                return a.left.cmp(&b.left);
             }
-            return a.parent.cmp(&b.parent);
+            return a.child.cmp(&b.child);
         }
-        ta.cmp(&tb).reverse()
-    });
+        return a.parent.cmp(&b.parent);
+    }
+    ta.cmp(&tb).reverse()
+}
+
+/// The order of mutations, given the positions of their sites.
+pub(crate) fn mutation_sort_order(a: Position, b: Position) -> Ordering {
+    a.cmp(&b).reverse()
+}
+
+fn sort_edge_table(nodes: &[Node], edges: &mut EdgeTable) {
+    edges.sort_by(|a, b| edge_sort_order(nodes, a, b));
 }
 
 fn sort_mutation_table(sites: &[Site], mutations: &mut MutationTable) {
-    mutations.sort_by(|a, b| {
-        let pa = sites[a.site].position;
-        let pb = sites[b.site].position;
-        pa.cmp(&pb).reverse()
-    });
+    mutations.sort_by(|a, b| mutation_sort_order(sites[a.site].position, sites[b.site].position));
 }
 
 bitflags! {
@@ -337,25 +466,36 @@ bitflags! {
 /// assert_eq!(rv, true);
 /// ```
 pub fn validate_edge_table(len: Position, edges: &[Edge], nodes: &[Node]) -> TablesResult<bool> {
-    if edges.is_empty() {
+    validate_edge_rows(len, edges, nodes)
+}
+
+/// See [``validate_edge_table``].
+pub(crate) fn validate_edge_rows<E: EdgeRows + ?Sized, N: NodeRows + ?Sized>(
+    len: Position,
+    edges: &E,
+    nodes: &N,
+) -> TablesResult<bool> {
+    if edges.num_rows() == 0 {
         return Ok(true);
     }
-    let mut parent_seen = vec![0; nodes.len()];
-    let mut last_parent: usize = edges[0].parent as usize;
-    let mut last_child: usize = edges[0].child as usize;
-    let mut last_left: Position = edges[0].left;
-
-    for (i, edge) in edges.iter().enumerate() {
+    let first = edges.edge_row(0);
+    let mut parent_seen = vec![0; nodes.num_rows()];
+    let mut last_parent: usize = first.parent as usize;
+    let mut last_child: usize = first.child as usize;
+    let mut last_left: Position = first.left;
+
+    for i in 0..edges.num_rows() {
+        let edge = edges.edge_row(i);
         if edge.parent == NULL_ID {
             return Err(TablesError::NullParent);
         }
         if edge.child == NULL_ID {
             return Err(TablesError::NullChild);
         }
-        if edge.parent < 0 || edge.parent as usize >= nodes.len() {
+        if edge.parent < 0 || edge.parent as usize >= nodes.num_rows() {
             return Err(TablesError::NodeOutOfBounds);
         }
-        if edge.child < 0 || edge.child as usize >= nodes.len() {
+        if edge.child < 0 || edge.child as usize >= nodes.num_rows() {
             return Err(TablesError::NodeOutOfBounds);
         }
         if edge.left < 0 || edge.left > len {
@@ -371,7 +511,8 @@ pub fn validate_edge_table(len: Position, edges: &[Edge], nodes: &[Node]) -> Tab
         }
 
         // child time must be > parent time b/c time goes forwards
-        if nodes[edge.child as usize].time <= nodes[edge.parent as usize].time {
+        let parent_time = nodes.node_time(edge.parent as usize);
+        if nodes.node_time(edge.child as usize) <= parent_time {
             return Err(TablesError::NodeTimesUnordered);
         }
 
@@ -380,10 +521,11 @@ pub fn validate_edge_table(len: Position, edges: &[Edge], nodes: &[Node]) -> Tab
         }
 
         if i > 0 {
-            if nodes[edge.parent as usize].time > nodes[last_parent].time {
+            let last_parent_time = nodes.node_time(last_parent);
+            if parent_time > last_parent_time {
                 return Err(TablesError::ParentTimesUnsorted);
             }
-            if nodes[edge.parent as usize].time == nodes[last_parent].time {
+            if parent_time == last_parent_time {
                 if edge.parent as usize == last_parent {
                     if (edge.child as usize) < last_child {
                         return Err(TablesError::EdgesNotSortedByChild);
@@ -408,7 +550,59 @@ pub fn validate_edge_table(len: Position, edges: &[Edge], nodes: &[Node]) -> Tab
     Ok(true)
 }
 
+// Edge insertion and removal orders.
+// See TableCollection::build_indexes.
+#[derive(Debug)]
+pub(crate) struct EdgeIndexes {
+    pub(crate) insertion: Vec<IdType>,
+    pub(crate) removal: Vec<IdType>,
+}
+
+pub(crate) fn make_edge_indexes(nodes: &[Node], edges: &[Edge]) -> EdgeIndexes {
+    let mut insertion: Vec<IdType> = (0..edges.len() as IdType).collect();
+    insertion.sort_by(|&i, &j| {
+        let (a, b) = (&edges[i as usize], &edges[j as usize]);
+        a.left
+            .cmp(&b.left)
+            .then(
+                nodes[a.parent as usize]
+                    .time
+                    .cmp(&nodes[b.parent as usize].time)
+                    .reverse(),
+            )
+            .then(a.parent.cmp(&b.parent))
+            .then(a.child.cmp(&b.child))
+    });
+    let mut removal: Vec<IdType> = (0..edges.len() as IdType).collect();
+    removal.sort_by(|&i, &j| {
+        let (a, b) = (&edges[i as usize], &edges[j as usize]);
+        a.right
+            .cmp(&b.right)
+            .then(
+                nodes[a.parent as usize]
+                    .time
+                    .cmp(&nodes[b.parent as usize].time),
+            )
+            .then(b.parent.cmp(&a.parent))
+            .then(b.child.cmp(&a.child))
+    });
+    EdgeIndexes { insertion, removal }
+}
+
 /// A collection of node, edge, site, and mutation tables.
+///
+/// If the `serde` feature is enabled, this type implements
+/// `serde::Serialize` and `serde::Deserialize`.
+/// Deserialization returns an error for invalid
+/// genome lengths and invalid rows.
+///
+/// Two instances are equal if they have the same genome
+/// length and the same rows in each table.
+/// The edge indexes (see [``TableCollection::build_indexes``])
+/// are not compared.
+/// See [``TableCollection::diff``] to find where two
+/// instances differ.
+#[derive(Debug)]
 pub struct TableCollection {
     length_: Position, // Not visible outside of this module
 
@@ -416,6 +610,7 @@ pub struct TableCollection {
     pub(crate) edges_: EdgeTable,
     pub(crate) sites_: SiteTable,
     pub(crate) mutations_: MutationTable,
+    pub(crate) indexes_: Option<EdgeIndexes>,
 }
 
 impl TableCollection {
@@ -439,6 +634,7 @@ impl TableCollection {
             edges_: EdgeTable::new(),
             sites_: SiteTable::new(),
             mutations_: MutationTable::new(),
+            indexes_: None,
         })
     }
 
@@ -466,7 +662,75 @@ impl TableCollection {
     /// assert_eq!(id, 0);
     /// ```
     pub fn add_node(&mut self, time: Time, deme: IdType) -> TablesResult<IdType> {
-        node_table_add_row(&mut self.nodes_, time, deme)
+        node_table_add_row(&mut self.nodes_, time, deme, NodeFlags::empty())
+    }
+
+    /// Add a [``Node``] with [``NodeFlags``] to the [``NodeTable``]
+    ///
+    /// # Parameters
+    ///
+    /// * `time`, a [``Time``] representing the birth time.
+    /// * `deme` a valid [``IdType``] representing deme where the node is found.
+    /// * `flags`, the node's [``NodeFlags``].
+    ///
+    /// # Returns
+    ///
+    /// See [``TableCollection::add_node``].
+    ///
+    /// # Errors
+    ///
+    /// Will return [``TablesError``] if `deme < 0`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use forrustts::NodeFlags;
+    /// let mut tables = forrustts::TableCollection::new(100).unwrap();
+    /// let id = tables
+    ///     .add_node_with_flags(1, 0, NodeFlags::IS_SAMPLE)
+    ///     .unwrap();
+    /// assert!(tables.node(id).flags.is_sample());
+    /// ```
+    pub fn add_node_with_flags(
+        &mut self,
+        time: Time,
+        deme: IdType,
+        flags: NodeFlags,
+    ) -> TablesResult<IdType> {
+        node_table_add_row(&mut self.nodes_, time, deme, flags)
+    }
+
+    /// Set the [``NodeFlags``] of a node.
+    ///
+    /// # Errors
+    ///
+    /// Will return [``TablesError::NodeOutOfBounds``] if `node`
+    /// is not a valid node ``ID``.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use forrustts::NodeFlags;
+    /// let mut tables = forrustts::TableCollection::new(100).unwrap();
+    /// let id = tables.add_node(1, 0).unwrap();
+    /// tables.set_node_flags(id, NodeFlags::IS_ALIVE).unwrap();
+    /// assert_eq!(tables.node(id).flags, NodeFlags::IS_ALIVE);
+    /// ```
+    pub fn set_node_flags(&mut self, node: IdType, flags: NodeFlags) -> TablesResult<()> {
+        if node < 0 || node as usize >= self.nodes_.len() {
+            return Err(TablesError::NodeOutOfBounds);
+        }
+        self.nodes_[node as usize].flags = flags;
+        Ok(())
+    }
+
+    /// Return the ``ID``s of all nodes with
+    /// [``NodeFlags::IS_SAMPLE``] set.
+    pub fn sample_nodes(&self) -> Vec<IdType> {
+        self.enumerate_nodes()
+            .filter(|(_, n)| n.flags.is_sample())
+            .map(|(i, _)| i as IdType)
+            .collect()
     }
 
     /// Add an [``Edge``] to the [``EdgeTable``].
@@ -502,7 +766,9 @@ impl TableCollection {
         parent: IdType,
         child: IdType,
     ) -> TablesResult<IdType> {
-        edge_table_add_row(&mut self.edges_, left, right, parent, child)
+        let rv = edge_table_add_row(&mut self.edges_, left, right, parent, child)?;
+        self.drop_indexes();
+        Ok(rv)
     }
 
     /// Add a [``Site``] to the [``SiteTable``];
@@ -547,9 +813,7 @@ impl TableCollection {
         position: Position,
         ancestral_state: Option<Vec<u8>>,
     ) -> TablesResult<IdType> {
-        if position >= self.length_ || position < 0 {
-            return Err(TablesError::InvalidPosition { found: position });
-        }
+        validate_site_position(position, self.length_)?;
         site_table_add_row(&mut self.sites_, position, ancestral_state)
     }
 
@@ -694,13 +958,99 @@ impl TableCollection {
     }
 
     /// Sort all tables for simplification.
+    ///
+    /// Sorting the edge table invalidates any
+    /// edge indexes.  See [``TableCollection::build_indexes``].
     pub fn sort_tables(&mut self, flags: TableSortingFlags) {
         if !flags.contains(TableSortingFlags::SKIP_EDGE_TABLE) {
             sort_edge_table(&self.nodes_, &mut self.edges_);
+            self.drop_indexes();
         }
         sort_mutation_table(&self.sites_, &mut self.mutations_);
     }
 
+    /// Build the edge insertion and removal indexes.
+    ///
+    /// Edges are inserted in order of increasing left
+    /// position and then of decreasing parent [``Time``],
+    /// so that the youngest parents come first.
+    /// Edges are removed in order of increasing right position
+    /// and then of increasing parent [``Time``].
+    ///
+    /// The indexes are dropped whenever the edge table changes.
+    /// [``TreeIterator``](crate::TreeIterator) uses them when present.
+    ///
+    /// # Errors
+    ///
+    /// Will return [``TablesError::NodeOutOfBounds``] if an
+    /// edge refers to a node that is not in the node table.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut tables = forrustts::TableCollection::new(100).unwrap();
+    /// tables.add_node(0, 0).unwrap();
+    /// tables.add_node(1, 0).unwrap();
+    /// tables.add_node(2, 0).unwrap();
+    /// tables.add_edge(0, 100, 0, 1).unwrap();
+    /// tables.add_edge(0, 50, 1, 2).unwrap();
+    /// assert!(!tables.has_indexes());
+    /// tables.build_indexes().unwrap();
+    /// assert!(tables.has_indexes());
+    /// assert_eq!(tables.edge_insertion_order().unwrap(), &[1, 0]);
+    /// assert_eq!(tables.edge_removal_order().unwrap(), &[1, 0]);
+    /// tables.add_edge(50, 100, 0, 2).unwrap();
+    /// assert!(!tables.has_indexes());
+    /// ```
+    pub fn build_indexes(&mut self) -> TablesResult<()> {
+        let num_nodes = self.nodes_.len();
+        for e in &self.edges_ {
+            if e.parent < 0
+                || e.child < 0
+                || e.parent as usize >= num_nodes
+                || e.child as usize >= num_nodes
+            {
+                return Err(TablesError::NodeOutOfBounds);
+            }
+        }
+        self.indexes_ = Some(make_edge_indexes(&self.nodes_, &self.edges_));
+        Ok(())
+    }
+
+    /// Return `true` if the edge indexes are present
+    /// and up to date.
+    pub fn has_indexes(&self) -> bool {
+        match &self.indexes_ {
+            Some(i) => {
+                i.insertion.len() == self.edges_.len() && i.removal.len() == self.edges_.len()
+            }
+            None => false,
+        }
+    }
+
+    /// Remove the edge indexes, if present.
+    pub fn drop_indexes(&mut self) {
+        self.indexes_ = None;
+    }
+
+    /// The edge insertion order, if the indexes are
+    /// present and up to date.
+    pub fn edge_insertion_order(&self) -> Option<&[IdType]> {
+        match self.has_indexes() {
+            true => self.indexes_.as_ref().map(|i| i.insertion.as_slice()),
+            false => None,
+        }
+    }
+
+    /// The edge removal order, if the indexes are
+    /// present and up to date.
+    pub fn edge_removal_order(&self) -> Option<&[IdType]> {
+        match self.has_indexes() {
+            true => self.indexes_.as_ref().map(|i| i.removal.as_slice()),
+            false => None,
+        }
+    }
+
     /// Run a validation check on the tables.
     pub fn validate(&self, flags: TableValidationFlags) -> TablesResult<bool> {
         if flags.contains(TableValidationFlags::VALIDATE_ALL) {
@@ -708,6 +1058,539 @@ impl TableCollection {
         }
         Ok(true)
     }
+
+    /// Restrict the tables to a subset of nodes.
+    ///
+    /// Unlike simplification, no new nodes or edges are created.
+    ///
+    /// # Parameters
+    ///
+    /// * `nodes`: the nodes to keep.
+    ///   Node `nodes[i]` becomes node `i`.
+    ///
+    /// # Returns
+    ///
+    /// A vector mapping the input node ``ID``s to the output
+    /// ``ID``s, with [``NULL_ID``] for nodes that are removed.
+    ///
+    /// # Notes
+    ///
+    /// * Edges whose parent or child is removed are removed.
+    ///   The other edges keep their order.
+    /// * Mutations whose node is removed are removed.
+    /// * The site table is not changed.
+    /// * Depending on the order of `nodes`, the edge table may
+    ///   need sorting (see [``TableCollection::sort_tables``]).
+    ///
+    /// # Errors
+    ///
+    /// Will return [``TablesError::NodeOutOfBounds``] if `nodes`
+    /// contains an invalid node ``ID`` and
+    /// [``TablesError::InvalidNodeValue``] if `nodes` contains a node
+    /// more than once.
+    /// If an error is returned, the tables are unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut tables = forrustts::TableCollection::new(100).unwrap();
+    /// tables.add_node(0, 0).unwrap();
+    /// tables.add_node(0, 1).unwrap();
+    /// tables.add_node(1, 1).unwrap();
+    /// tables.add_edge(0, 50, 0, 2).unwrap();
+    /// tables.add_edge(50, 100, 1, 2).unwrap();
+    ///
+    /// let idmap = tables.subset(&[2, 1]).unwrap();
+    /// assert_eq!(idmap, vec![forrustts::NULL_ID, 1, 0]);
+    /// assert_eq!(tables.num_nodes(), 2);
+    /// assert_eq!(tables.node(0).time, 1);
+    /// assert_eq!(tables.num_edges(), 1);
+    /// assert_eq!(tables.edge(0).parent, 1);
+    /// assert_eq!(tables.edge(0).child, 0);
+    /// ```
+    pub fn subset(&mut self, nodes: &[IdType]) -> TablesResult<Vec<IdType>> {
+        let mut idmap = vec![NULL_ID; self.nodes_.len()];
+        for (i, &u) in nodes.iter().enumerate() {
+            if u < 0 || u as usize >= self.nodes_.len() {
+                return Err(TablesError::NodeOutOfBounds);
+            }
+            if idmap[u as usize] != NULL_ID {
+                return Err(TablesError::InvalidNodeValue { found: u });
+            }
+            idmap[u as usize] = i as IdType;
+        }
+        let map = |u: IdType| match u >= 0 && (u as usize) < idmap.len() {
+            true => idmap[u as usize],
+            false => NULL_ID,
+        };
+
+        self.nodes_ = nodes
+            .iter()
+            .map(|&u| self.nodes_[u as usize].clone())
+            .collect();
+        self.edges_
+            .retain(|e| map(e.parent) != NULL_ID && map(e.child) != NULL_ID);
+        for e in self.edges_.iter_mut() {
+            e.parent = map(e.parent);
+            e.child = map(e.child);
+        }
+        self.mutations_.retain(|m| map(m.node) != NULL_ID);
+        for m in self.mutations_.iter_mut() {
+            m.node = map(m.node);
+        }
+        self.drop_indexes();
+        Ok(idmap)
+    }
+
+    /// Return the heap memory used by each table.
+    ///
+    /// The memory of the sites and mutations includes
+    /// their ancestral and derived states.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut tables = forrustts::TableCollection::new(100).unwrap();
+    /// tables.add_site(1, Some(vec![0; 10])).unwrap();
+    /// let usage = tables.memory_usage();
+    /// assert_eq!(
+    ///     usage.sites.used,
+    ///     std::mem::size_of::<forrustts::Site>() + 10
+    /// );
+    /// assert_eq!(usage.total().used, usage.sites.used);
+    /// ```
+    pub fn memory_usage(&self) -> TableCollectionMemoryUsage {
+        TableCollectionMemoryUsage {
+            nodes: MemoryUsage::of_vec(&self.nodes_, self.nodes_.capacity()),
+            edges: MemoryUsage::of_vec(&self.edges_, self.edges_.capacity()),
+            sites: MemoryUsage::of_vec(&self.sites_, self.sites_.capacity())
+                + self
+                    .sites_
+                    .iter()
+                    .map(|s| MemoryUsage::of_state(&s.ancestral_state))
+                    .sum(),
+            mutations: MemoryUsage::of_vec(&self.mutations_, self.mutations_.capacity())
+                + self
+                    .mutations_
+                    .iter()
+                    .map(|m| MemoryUsage::of_state(&m.derived_state))
+                    .sum(),
+            indexes: match &self.indexes_ {
+                Some(i) => {
+                    MemoryUsage::of_vec(&i.insertion, i.insertion.capacity())
+                        + MemoryUsage::of_vec(&i.removal, i.removal.capacity())
+                }
+                None => MemoryUsage::default(),
+            },
+        }
+    }
+
+    /// Add the nodes, edges, sites, and mutations of `other`
+    /// that are not shared with `self`.
+    ///
+    /// This is useful for merging the results of simulations
+    /// that started from the same tables.
+    ///
+    /// # Parameters
+    ///
+    /// * `other`: the tables to merge into `self`
+    /// * `node_mapping`: for each node of `other`, the
+    ///   corresponding node of `self`, or [``NULL_ID``] if
+    ///   the node is not shared.
+    ///
+    /// # Returns
+    ///
+    /// A vector mapping the node ``ID``s of `other` to
+    /// their ``ID``s in `self`.
+    ///
+    /// # Notes
+    ///
+    /// * Nodes of `other` that are not shared are appended
+    ///   in order.
+    /// * Edges of `other` whose parent or child is not shared
+    ///   are appended.
+    /// * Mutations of `other` on nodes that are not shared are
+    ///   appended.
+    ///   Mutations on shared nodes must already be in `self`.
+    ///   Each is assigned to a site of `self` with the same position
+    ///   and ancestral state, which is added if there is none.
+    /// * The tables will usually need sorting
+    ///   (see [``TableCollection::sort_tables``]).
+    ///
+    /// # Errors
+    ///
+    /// * [``TablesError::InvalidNodeMapping``] if the length of
+    ///   `node_mapping` differs from the number of nodes of `other`,
+    ///   or if two nodes are mapped to the same node.
+    /// * [``TablesError::NodeOutOfBounds``] if `node_mapping`
+    ///   contains a node that is not in `self`.
+    /// * [``TablesError::SharedHistoryMismatch``] if the genome
+    ///   lengths differ, if a shared node has a different
+    ///   time or deme in the two tables, if the edges
+    ///   between shared nodes differ, or if the mutations
+    ///   on shared nodes differ.
+    ///   Mutations are compared by node, site position, ancestral
+    ///   state, derived state, key, and neutrality.
+    /// * [``TablesError::InvalidSite``] if a mutation of `other`
+    ///   refers to a site that is not in `other`.
+    ///
+    /// If an error is returned, the tables are unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use forrustts::{TableCollection, NULL_ID};
+    ///
+    /// let shared = || {
+    ///     let mut tables = TableCollection::new(100).unwrap();
+    ///     tables.add_node(0, 0).unwrap();
+    ///     tables
+    /// };
+    /// let mut tables = shared();
+    /// let mut other = shared();
+    /// tables.add_node(1, 0).unwrap();
+    /// tables.add_edge(0, 100, 0, 1).unwrap();
+    /// other.add_node(1, 1).unwrap();
+    /// other.add_edge(0, 100, 0, 1).unwrap();
+    ///
+    /// let idmap = tables.union(&other, &[0, NULL_ID]).unwrap();
+    /// assert_eq!(idmap, vec![0, 2]);
+    /// assert_eq!(tables.num_nodes(), 3);
+    /// assert_eq!(tables.node(2).deme, 1);
+    /// assert_eq!(tables.num_edges(), 2);
+    /// assert_eq!(tables.edge(1).child, 2);
+    /// ```
+    pub fn union(
+        &mut self,
+        other: &TableCollection,
+        node_mapping: &[IdType],
+    ) -> TablesResult<Vec<IdType>> {
+        if node_mapping.len() != other.nodes_.len() {
+            return Err(TablesError::InvalidNodeMapping);
+        }
+        let mut is_shared = vec![false; self.nodes_.len()];
+        for (i, &u) in node_mapping.iter().enumerate() {
+            if u == NULL_ID {
+                continue;
+            }
+            if u < 0 || u as usize >= self.nodes_.len() {
+                return Err(TablesError::NodeOutOfBounds);
+            }
+            if is_shared[u as usize] {
+                return Err(TablesError::InvalidNodeMapping);
+            }
+            is_shared[u as usize] = true;
+            let (a, b) = (&self.nodes_[u as usize], &other.nodes_[i]);
+            if a.time != b.time || a.deme != b.deme {
+                return Err(TablesError::SharedHistoryMismatch);
+            }
+        }
+        if self.length_ != other.length_ {
+            return Err(TablesError::SharedHistoryMismatch);
+        }
+        let in_other = |u: IdType| u >= 0 && (u as usize) < node_mapping.len();
+        if other
+            .edges_
+            .iter()
+            .any(|e| !in_other(e.parent) || !in_other(e.child))
+            || other.mutations_.iter().any(|m| !in_other(m.node))
+        {
+            return Err(TablesError::NodeOutOfBounds);
+        }
+        if let Some(m) = other
+            .mutations_
+            .iter()
+            .find(|m| m.site >= other.sites_.len())
+        {
+            return Err(TablesError::InvalidSite { found: m.site });
+        }
+        let shared = |u: IdType| node_mapping[u as usize] != NULL_ID;
+
+        let key = |e: &Edge| (e.parent, e.child, e.left, e.right);
+        let mut shared_edges: Vec<_> = other
+            .edges_
+            .iter()
+            .filter(|e| shared(e.parent) && shared(e.child))
+            .map(|e| {
+                (
+                    node_mapping[e.parent as usize],
+                    node_mapping[e.child as usize],
+                    e.left,
+                    e.right,
+                )
+            })
+            .collect();
+        let is_shared_in_self =
+            |u: IdType| u >= 0 && (u as usize) < is_shared.len() && is_shared[u as usize];
+        let mut self_edges: Vec<_> = self
+            .edges_
+            .iter()
+            .filter(|e| is_shared_in_self(e.parent) && is_shared_in_self(e.child))
+            .map(key)
+            .collect();
+        shared_edges.sort_unstable();
+        self_edges.sort_unstable();
+        if shared_edges != self_edges {
+            return Err(TablesError::SharedHistoryMismatch);
+        }
+
+        let mutation_key = |sites: &[Site], node: IdType, m: &MutationRecord| {
+            let site = &sites[m.site];
+            (
+                node,
+                site.position,
+                site.ancestral_state.clone(),
+                m.derived_state.clone(),
+                m.key,
+                m.neutral,
+            )
+        };
+        let mut shared_mutations: Vec<_> = other
+            .mutations_
+            .iter()
+            .filter(|m| shared(m.node))
+            .map(|m| mutation_key(&other.sites_, node_mapping[m.node as usize], m))
+            .collect();
+        let mut self_mutations: Vec<_> = self
+            .mutations_
+            .iter()
+            .filter(|m| is_shared_in_self(m.node))
+            .map(|m| mutation_key(&self.sites_, m.node, m))
+            .collect();
+        shared_mutations.sort_unstable();
+        self_mutations.sort_unstable();
+        if shared_mutations != self_mutations {
+            return Err(TablesError::SharedHistoryMismatch);
+        }
+
+        let mut idmap = node_mapping.to_vec();
+        for (i, n) in other.nodes_.iter().enumerate() {
+            if idmap[i] == NULL_ID {
+                self.nodes_.push(n.clone());
+                idmap[i] = (self.nodes_.len() - 1) as IdType;
+            }
+        }
+        for e in other.edges_.iter() {
+            if !shared(e.parent) || !shared(e.child) {
+                self.edges_.push(Edge {
+                    left: e.left,
+                    right: e.right,
+                    parent: idmap[e.parent as usize],
+                    child: idmap[e.child as usize],
+                });
+            }
+        }
+        let mut sites = std::collections::HashMap::new();
+        for (i, site) in self.sites_.iter().enumerate() {
+            sites
+                .entry((site.position, site.ancestral_state.clone()))
+                .or_insert(i);
+        }
+        for m in other.mutations_.iter().filter(|m| !shared(m.node)) {
+            let site = &other.sites_[m.site];
+            let site = *sites
+                .entry((site.position, site.ancestral_state.clone()))
+                .or_insert_with(|| {
+                    self.sites_.push(site.clone());
+                    self.sites_.len() - 1
+                });
+            self.mutations_.push(MutationRecord {
+                node: idmap[m.node as usize],
+                site,
+                ..m.clone()
+            });
+        }
+        self.drop_indexes();
+        Ok(idmap)
+    }
+
+    /// Find the first differing row of each table.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut tables = forrustts::TableCollection::new(100).unwrap();
+    /// tables.add_node(0, 0).unwrap();
+    /// let mut other = forrustts::TableCollection::new(100).unwrap();
+    /// other.add_node(0, 0).unwrap();
+    /// assert!(tables.diff(&other).is_empty());
+    /// assert!(tables == other);
+    ///
+    /// other.add_node(1, 0).unwrap();
+    /// let diff = tables.diff(&other);
+    /// let nodes = diff.nodes.unwrap();
+    /// assert_eq!(nodes.row, 1);
+    /// assert!(nodes.this.is_none());
+    /// assert_eq!(nodes.other.unwrap().time, 1);
+    /// assert!(diff.edges.is_none());
+    /// ```
+    pub fn diff(&self, other: &TableCollection) -> TableCollectionDiff {
+        TableCollectionDiff {
+            genome_length: match self.length_ == other.length_ {
+                true => None,
+                false => Some((self.length_, other.length_)),
+            },
+            nodes: first_row_diff(&self.nodes_, &other.nodes_),
+            edges: first_row_diff(&self.edges_, &other.edges_),
+            sites: first_row_diff(&self.sites_, &other.sites_),
+            mutations: first_row_diff(&self.mutations_, &other.mutations_),
+        }
+    }
+}
+
+impl PartialEq for TableCollection {
+    fn eq(&self, other: &Self) -> bool {
+        self.length_ == other.length_
+            && self.nodes_ == other.nodes_
+            && self.edges_ == other.edges_
+            && self.sites_ == other.sites_
+            && self.mutations_ == other.mutations_
+    }
+}
+
+/// Heap memory used by a [``TableCollection``].
+///
+/// See [``TableCollection::memory_usage``].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TableCollectionMemoryUsage {
+    /// The node table
+    pub nodes: MemoryUsage,
+    /// The edge table
+    pub edges: MemoryUsage,
+    /// The site table
+    pub sites: MemoryUsage,
+    /// The mutation table
+    pub mutations: MemoryUsage,
+    /// The edge indexes
+    pub indexes: MemoryUsage,
+}
+
+impl TableCollectionMemoryUsage {
+    /// The sum over all tables.
+    pub fn total(&self) -> MemoryUsage {
+        self.nodes + self.edges + self.sites + self.mutations + self.indexes
+    }
+}
+
+/// The first differing row of a table.
+///
+/// See [``TableCollection::diff``].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RowDiff<T> {
+    /// The index of the row
+    pub row: usize,
+    /// The row of the table on which
+    /// [``TableCollection::diff``] was called,
+    /// or [``None``] if that table has fewer rows.
+    pub this: Option<T>,
+    /// The row of the other table,
+    /// or [``None``] if that table has fewer rows.
+    pub other: Option<T>,
+}
+
+/// The differences between two [``TableCollection``]s.
+///
+/// Each field is [``None``] if there is no difference.
+/// See [``TableCollection::diff``].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TableCollectionDiff {
+    /// The two genome lengths
+    pub genome_length: Option<(Position, Position)>,
+    /// The first differing row of the node tables
+    pub nodes: Option<RowDiff<Node>>,
+    /// The first differing row of the edge tables
+    pub edges: Option<RowDiff<Edge>>,
+    /// The first differing row of the site tables
+    pub sites: Option<RowDiff<Site>>,
+    /// The first differing row of the mutation tables
+    pub mutations: Option<RowDiff<MutationRecord>>,
+}
+
+impl TableCollectionDiff {
+    /// `true` if there are no differences.
+    pub fn is_empty(&self) -> bool {
+        self == &TableCollectionDiff::default()
+    }
+}
+
+fn first_row_diff<T: Clone + PartialEq>(this: &[T], other: &[T]) -> Option<RowDiff<T>> {
+    (0..std::cmp::max(this.len(), other.len()))
+        .find(|&i| this.get(i) != other.get(i))
+        .map(|row| RowDiff {
+            row,
+            this: this.get(row).cloned(),
+            other: other.get(row).cloned(),
+        })
+}
+
+// The genome length of a TableCollection
+// is private, so (de)serialization goes through
+// these types.  Deserialization adds each row
+// via the public API so that the usual validation applies.
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct TableCollectionRef<'a> {
+    genome_length: Position,
+    nodes: &'a NodeTable,
+    edges: &'a EdgeTable,
+    sites: &'a SiteTable,
+    mutations: &'a MutationTable,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct TableCollectionData {
+    genome_length: Position,
+    nodes: NodeTable,
+    edges: EdgeTable,
+    sites: SiteTable,
+    mutations: MutationTable,
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<TableCollectionData> for TableCollection {
+    type Error = TablesError;
+
+    fn try_from(data: TableCollectionData) -> TablesResult<Self> {
+        let mut tables = TableCollection::new(data.genome_length)?;
+        for n in data.nodes {
+            tables.add_node_with_flags(n.time, n.deme, n.flags)?;
+        }
+        for e in data.edges {
+            tables.add_edge(e.left, e.right, e.parent, e.child)?;
+        }
+        for s in data.sites {
+            tables.add_site(s.position, s.ancestral_state)?;
+        }
+        for m in data.mutations {
+            tables.add_mutation(m.node, m.key, m.site, m.derived_state, m.neutral)?;
+        }
+        Ok(tables)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for TableCollection {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TableCollectionRef {
+            genome_length: self.length_,
+            nodes: &self.nodes_,
+            edges: &self.edges_,
+            sites: &self.sites_,
+            mutations: &self.mutations_,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for TableCollection {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use std::convert::TryFrom;
+        let data = TableCollectionData::deserialize(deserializer)?;
+        TableCollection::try_from(data).map_err(serde::de::Error::custom)
+    }
 }
 
 #[cfg(test)]
@@ -869,4 +1752,362 @@ mod test_tables {
             Err(_) => panic!(),
         }
     }
+
+    #[test]
+    fn test_node_flags() {
+        let mut tables = TableCollection::new(10).unwrap();
+        tables.add_node(0, 0).unwrap();
+        tables
+            .add_node_with_flags(1, 0, NodeFlags::IS_SAMPLE)
+            .unwrap();
+        tables.add_node(1, 0).unwrap();
+        assert!(tables.node(0).flags.is_empty());
+        assert_eq!(tables.sample_nodes(), vec![1]);
+        tables
+            .set_node_flags(2, NodeFlags::IS_SAMPLE | NodeFlags::IS_ALIVE)
+            .unwrap();
+        assert_eq!(tables.sample_nodes(), vec![1, 2]);
+        assert_eq!(
+            tables.set_node_flags(3, NodeFlags::IS_SAMPLE),
+            Err(TablesError::NodeOutOfBounds)
+        );
+        assert_eq!(
+            tables.set_node_flags(-1, NodeFlags::IS_SAMPLE),
+            Err(TablesError::NodeOutOfBounds)
+        );
+    }
+
+    #[test]
+    fn test_build_indexes() {
+        let mut tables = TableCollection::new(10).unwrap();
+        tables.add_node(0, 0).unwrap();
+        tables.add_node(1, 0).unwrap();
+        tables.add_node(2, 0).unwrap();
+        tables.add_node(2, 0).unwrap();
+        tables.add_edge(0, 10, 0, 1).unwrap();
+        tables.add_edge(0, 5, 1, 2).unwrap();
+        tables.add_edge(5, 10, 0, 2).unwrap();
+        tables.add_edge(0, 10, 1, 3).unwrap();
+        assert!(!tables.has_indexes());
+        assert!(tables.edge_insertion_order().is_none());
+        tables.build_indexes().unwrap();
+        assert!(tables.has_indexes());
+        // The younger parent (node 1) comes first
+        assert_eq!(tables.edge_insertion_order().unwrap(), &[1, 3, 0, 2]);
+        // The older parent (node 0) comes first
+        assert_eq!(tables.edge_removal_order().unwrap(), &[1, 2, 0, 3]);
+
+        tables.sort_tables(TableSortingFlags::SKIP_EDGE_TABLE);
+        assert!(tables.has_indexes());
+        tables.sort_tables(TableSortingFlags::empty());
+        assert!(!tables.has_indexes());
+
+        tables.build_indexes().unwrap();
+        tables.drop_indexes();
+        assert!(!tables.has_indexes());
+
+        tables.add_edge(0, 10, 0, 4).unwrap();
+        assert_eq!(tables.build_indexes(), Err(TablesError::NodeOutOfBounds));
+        assert!(!tables.has_indexes());
+    }
+
+    #[test]
+    fn test_subset() {
+        let mut tables = TableCollection::new(100).unwrap();
+        tables.add_node(0, 0).unwrap();
+        tables.add_node(0, 1).unwrap();
+        tables
+            .add_node_with_flags(1, 0, NodeFlags::IS_SAMPLE)
+            .unwrap();
+        tables
+            .add_node_with_flags(1, 1, NodeFlags::IS_SAMPLE)
+            .unwrap();
+        tables.add_edge(0, 100, 0, 2).unwrap();
+        tables.add_edge(0, 100, 1, 3).unwrap();
+        tables.add_edge(0, 100, 0, 3).unwrap();
+        tables.add_site(10, None).unwrap();
+        tables.add_mutation(2, 0, 0, None, true).unwrap();
+        tables.add_mutation(3, 1, 0, None, true).unwrap();
+        tables.build_indexes().unwrap();
+
+        let mut subset = TableCollection::new(100).unwrap();
+        subset
+            .add_node_with_flags(1, 1, NodeFlags::IS_SAMPLE)
+            .unwrap();
+        subset.add_node(0, 1).unwrap();
+        subset.add_edge(0, 100, 1, 0).unwrap();
+        subset.add_site(10, None).unwrap();
+        subset.add_mutation(0, 1, 0, None, true).unwrap();
+
+        let idmap = tables.subset(&[3, 1]).unwrap();
+        assert_eq!(idmap, vec![NULL_ID, 1, NULL_ID, 0]);
+        assert!(!tables.has_indexes());
+        let diff = tables.diff(&subset);
+        assert!(diff.is_empty(), "{:?}", diff);
+
+        assert_eq!(tables.subset(&[0, 2]), Err(TablesError::NodeOutOfBounds));
+        assert_eq!(
+            tables.subset(&[1, 1]),
+            Err(TablesError::InvalidNodeValue { found: 1 })
+        );
+        assert!(tables == subset);
+        assert_eq!(tables.subset(&[]).unwrap(), vec![NULL_ID; 2]);
+        assert_eq!(tables.num_nodes(), 0);
+        assert_eq!(tables.num_edges(), 0);
+        assert!(tables.mutations().is_empty());
+        assert_eq!(tables.sites().len(), 1);
+    }
+
+    #[test]
+    fn test_union() {
+        // Node 0 is shared, and has a mutation at a shared site.
+        let shared = || {
+            let mut tables = TableCollection::new(100).unwrap();
+            tables.add_node(0, 0).unwrap();
+            tables.add_site(10, Some(vec![0])).unwrap();
+            tables.add_mutation(0, 0, 0, Some(vec![1]), true).unwrap();
+            tables
+        };
+        let mut tables = shared();
+        tables.add_node(5, 0).unwrap();
+        tables.add_edge(0, 100, 0, 1).unwrap();
+        tables.add_site(20, None).unwrap();
+        tables.add_mutation(1, 1, 1, None, true).unwrap();
+
+        // Node 1 of other is node 2 of tables,
+        // which is a child of node 1 of tables.
+        let mut other = shared();
+        other.add_node(8, 0).unwrap();
+        other.add_node(5, 0).unwrap();
+        other.add_edge(0, 100, 0, 2).unwrap();
+        other.add_edge(0, 100, 2, 1).unwrap();
+        other.add_site(10, Some(vec![0])).unwrap();
+        other.add_site(30, None).unwrap();
+        other.add_mutation(1, 2, 1, Some(vec![2]), true).unwrap();
+        other.add_mutation(1, 3, 2, None, false).unwrap();
+        // The shared node 2 carries the same mutation as node 1 of tables.
+        other.add_site(20, None).unwrap();
+        other.add_mutation(2, 1, 3, None, true).unwrap();
+
+        let mut expected = shared();
+        expected.add_node(5, 0).unwrap();
+        expected.add_edge(0, 100, 0, 1).unwrap();
+        expected.add_site(20, None).unwrap();
+        expected.add_mutation(1, 1, 1, None, true).unwrap();
+        expected.add_node(8, 0).unwrap();
+        expected.add_edge(0, 100, 1, 2).unwrap();
+        expected.add_mutation(2, 2, 0, Some(vec![2]), true).unwrap();
+        expected.add_site(30, None).unwrap();
+        expected.add_mutation(2, 3, 2, None, false).unwrap();
+
+        let idmap = tables.union(&other, &[0, NULL_ID, 1]).unwrap();
+        assert_eq!(idmap, vec![0, 2, 1]);
+        let diff = tables.diff(&expected);
+        assert!(diff.is_empty(), "{:?}", diff);
+    }
+
+    #[test]
+    fn test_union_errors() {
+        let make_tables = |deme| {
+            let mut tables = TableCollection::new(100).unwrap();
+            tables.add_node(0, 0).unwrap();
+            tables.add_node(1, deme).unwrap();
+            tables.add_edge(0, 100, 0, 1).unwrap();
+            tables
+        };
+        let mut tables = make_tables(0);
+        let mut other = make_tables(0);
+        assert_eq!(
+            tables.union(&other, &[0]),
+            Err(TablesError::InvalidNodeMapping)
+        );
+        assert_eq!(
+            tables.union(&other, &[0, 0]),
+            Err(TablesError::InvalidNodeMapping)
+        );
+        assert_eq!(
+            tables.union(&other, &[0, 2]),
+            Err(TablesError::NodeOutOfBounds)
+        );
+        assert_eq!(
+            tables.union(&other, &[1, 0]),
+            Err(TablesError::SharedHistoryMismatch)
+        );
+        assert_eq!(
+            tables.union(&make_tables(1), &[0, 1]),
+            Err(TablesError::SharedHistoryMismatch)
+        );
+        other.add_edge(0, 50, 0, 1).unwrap();
+        assert_eq!(
+            tables.union(&other, &[0, 1]),
+            Err(TablesError::SharedHistoryMismatch)
+        );
+        let mut other = make_tables(1);
+        other.add_mutation(1, 0, 0, None, true).unwrap();
+        assert_eq!(
+            tables.union(&other, &[0, NULL_ID]),
+            Err(TablesError::InvalidSite { found: 0 })
+        );
+        assert!(tables == make_tables(0));
+
+        // The shared histories differ only in a mutation.
+        let with_mutation = |derived_state| {
+            let mut tables = make_tables(0);
+            tables.add_site(10, None).unwrap();
+            tables.add_mutation(1, 0, 0, derived_state, true).unwrap();
+            tables
+        };
+        let mut tables = with_mutation(None);
+        for other in [make_tables(0), with_mutation(Some(vec![1]))] {
+            assert_eq!(
+                tables.union(&other, &[0, 1]),
+                Err(TablesError::SharedHistoryMismatch)
+            );
+        }
+        assert_eq!(
+            make_tables(0).union(&with_mutation(None), &[0, 1]),
+            Err(TablesError::SharedHistoryMismatch)
+        );
+        assert_eq!(
+            tables.union(&with_mutation(None), &[0, 1]).unwrap(),
+            vec![0, 1]
+        );
+        assert!(tables == with_mutation(None));
+
+        let mut tables = make_tables(0);
+        assert_eq!(tables.union(&make_tables(0), &[0, 1]).unwrap(), vec![0, 1]);
+        assert!(tables == make_tables(0));
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let mut tables = TableCollection::new(100).unwrap();
+        tables.add_node(0, 0).unwrap();
+        tables.add_node(1, 0).unwrap();
+        tables.add_edge(0, 100, 0, 1).unwrap();
+        tables.add_site(10, None).unwrap();
+        tables
+            .add_mutation(1, 0, 0, Some(vec![1, 2]), true)
+            .unwrap();
+        let usage = tables.memory_usage();
+        assert_eq!(usage.nodes.used, 2 * std::mem::size_of::<Node>());
+        assert_eq!(usage.edges.used, std::mem::size_of::<Edge>());
+        assert_eq!(usage.sites.used, std::mem::size_of::<Site>());
+        assert_eq!(
+            usage.mutations.used,
+            std::mem::size_of::<MutationRecord>() + 2
+        );
+        assert_eq!(usage.indexes, MemoryUsage::default());
+        tables.build_indexes().unwrap();
+        let usage = tables.memory_usage();
+        assert_eq!(usage.indexes.used, 2 * std::mem::size_of::<IdType>());
+        let total = usage.total();
+        assert!(total.allocated >= total.used);
+    }
+
+    #[test]
+    fn test_diff() {
+        let make_tables = || {
+            let mut tables = TableCollection::new(100).unwrap();
+            tables.add_node(0, 0).unwrap();
+            tables.add_node(1, 0).unwrap();
+            tables.add_edge(0, 100, 0, 1).unwrap();
+            tables.add_site(10, None).unwrap();
+            tables.add_mutation(1, 0, 0, None, true).unwrap();
+            tables
+        };
+        let tables = make_tables();
+        let mut other = make_tables();
+        assert!(tables == other);
+        assert!(tables.diff(&other).is_empty());
+
+        // Indexes are not compared
+        other.build_indexes().unwrap();
+        assert!(tables == other);
+
+        other.add_edge(50, 100, 0, 1).unwrap();
+        other.mutations_[0].neutral = false;
+        assert!(tables != other);
+        let diff = tables.diff(&other);
+        assert!(!diff.is_empty());
+        assert!(diff.genome_length.is_none());
+        assert!(diff.nodes.is_none());
+        assert!(diff.sites.is_none());
+        assert_eq!(
+            diff.edges,
+            Some(RowDiff {
+                row: 1,
+                this: None,
+                other: Some(*other.edge(1)),
+            })
+        );
+        let mutations = diff.mutations.unwrap();
+        assert_eq!(mutations.row, 0);
+        assert!(mutations.this.unwrap().neutral);
+        assert!(!mutations.other.unwrap().neutral);
+
+        let other = TableCollection::new(10).unwrap();
+        let diff = other.diff(&tables);
+        assert_eq!(diff.genome_length, Some((10, 100)));
+        assert_eq!(diff.nodes.unwrap().this, None);
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test_serde {
+    use super::*;
+
+    fn make_tables() -> TableCollection {
+        let mut tables = TableCollection::new(100).unwrap();
+        tables.add_node(0, 0).unwrap();
+        tables
+            .add_node_with_flags(1, 1, NodeFlags::IS_SAMPLE | NodeFlags::IS_ALIVE)
+            .unwrap();
+        tables.add_edge(0, 100, 0, 1).unwrap();
+        tables.add_site(50, Some(b"A".to_vec())).unwrap();
+        tables.add_mutation(1, 7, 0, None, true).unwrap();
+        tables
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let tables = make_tables();
+        let json = serde_json::to_string(&tables).unwrap();
+        let tables_json: TableCollection = serde_json::from_str(&json).unwrap();
+        assert_eq!(tables, tables_json);
+    }
+
+    #[test]
+    fn test_bincode_round_trip() {
+        let tables = make_tables();
+        let encoded = bincode::serialize(&tables).unwrap();
+        let decoded: TableCollection = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(tables, decoded);
+    }
+
+    #[test]
+    fn test_row_round_trip() {
+        let tables = make_tables();
+        let json = serde_json::to_string(tables.edge(0)).unwrap();
+        let e: Edge = serde_json::from_str(&json).unwrap();
+        assert_eq!(e.left, 0);
+        assert_eq!(e.right, 100);
+        let encoded = bincode::serialize(tables.mutation(0)).unwrap();
+        let m: MutationRecord = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(m.key, 7);
+        assert!(m.neutral);
+    }
+
+    #[test]
+    fn test_invalid_genome_length() {
+        let json = r#"{"genome_length":0,"nodes":[],"edges":[],"sites":[],"mutations":[]}"#;
+        assert!(serde_json::from_str::<TableCollection>(json).is_err());
+    }
+
+    #[test]
+    fn test_invalid_site_position() {
+        let json = r#"{"genome_length":10,"nodes":[],"edges":[],
+            "sites":[{"position":10,"ancestral_state":null}],"mutations":[]}"#;
+        assert!(serde_json::from_str::<TableCollection>(json).is_err());
+    }
 }
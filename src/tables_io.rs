@@ -0,0 +1,578 @@
+//! Native binary format for [``TableCollection``].
+//!
+//! The format is little-endian and consists of a header
+//! followed by one section per table (nodes, edges, sites,
+//! and mutations, in that order).
+//!
+//! The header is:
+//!
+//! 1. The 8-byte magic value `FRSTTBLS`.
+//! 2. The format version as a `u32`.
+//! 3. The genome length as an `i64`.
+//!
+//! Each section is:
+//!
+//! 1. The number of rows as a `u64`.
+//! 2. The length of the encoded rows, in bytes, as a `u64`.
+//! 3. The encoded rows.
+//! 4. A CRC-32 checksum of the encoded rows as a `u32`.
+
+use crate::tables::*;
+use crate::tsdef::{IdType, Position, Time};
+use std::io::{Read, Write};
+use thiserror::Error;
+
+/// The first 8 bytes of any file written by
+/// [``TableCollection::dump``].
+const MAGIC: [u8; 8] = *b"FRSTTBLS";
+
+/// Current version of the binary format.
+///
+/// Version 2 added the node flags.
+/// Files written in version 1 can still be read.
+const FORMAT_VERSION: u32 = 2;
+
+/// Error type for reading and writing
+/// [``TableCollection``] data.
+#[derive(Error, Debug)]
+pub enum TablesIOError {
+    /// A redirection of a [``std::io::Error``].
+    #[error("{value:?}")]
+    IOError {
+        /// The redirected error
+        #[from]
+        value: std::io::Error,
+    },
+    /// The input does not start with the expected magic value.
+    #[error("Invalid file header")]
+    InvalidHeader,
+    /// The input was written by an unknown version of the format.
+    #[error("Unsupported format version: {found:?}")]
+    UnsupportedVersion {
+        /// The version found in the input
+        found: u32,
+    },
+    /// The stored checksum of a table does not match its contents.
+    #[error("Checksum mismatch for {table:?} table")]
+    ChecksumMismatch {
+        /// The name of the table
+        table: &'static str,
+    },
+    /// The encoded rows of a table are malformed.
+    #[error("Corrupt data in {table:?} table")]
+    CorruptTable {
+        /// The name of the table
+        table: &'static str,
+    },
+    /// A table is too large to be represented in the output format.
+    #[error("The {table:?} table is too large for the output format")]
+    TableTooLarge {
+        /// The name of the table
+        table: &'static str,
+    },
+    /// A redirection of a [``crate::TablesError``]
+    /// raised when adding the decoded rows.
+    #[error("{value:?}")]
+    TablesError {
+        /// The redirected error
+        #[from]
+        value: TablesError,
+    },
+}
+
+/// Result type for reading and writing [``TableCollection``] data.
+pub type TablesIOResult<T> = std::result::Result<T, TablesIOError>;
+
+const fn make_crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            if c & 1 == 1 {
+                c = 0xEDB8_8320 ^ (c >> 1);
+            } else {
+                c >>= 1;
+            }
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+static CRC32_TABLE: [u32; 256] = make_crc32_table();
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut c = 0xFFFF_FFFFu32;
+    for b in data {
+        c = CRC32_TABLE[((c ^ *b as u32) & 0xFF) as usize] ^ (c >> 8);
+    }
+    c ^ 0xFFFF_FFFF
+}
+
+fn encode_state(state: &Option<Vec<u8>>, buffer: &mut Vec<u8>) {
+    match state {
+        None => buffer.push(0),
+        Some(s) => {
+            buffer.push(1);
+            buffer.extend_from_slice(&(s.len() as u64).to_le_bytes());
+            buffer.extend_from_slice(s);
+        }
+    }
+}
+
+fn encode_nodes(nodes: &[Node], buffer: &mut Vec<u8>) {
+    for n in nodes {
+        buffer.extend_from_slice(&n.time.to_le_bytes());
+        buffer.extend_from_slice(&n.deme.to_le_bytes());
+        buffer.extend_from_slice(&n.flags.bits().to_le_bytes());
+    }
+}
+
+fn encode_edges(edges: &[Edge], buffer: &mut Vec<u8>) {
+    for e in edges {
+        buffer.extend_from_slice(&e.left.to_le_bytes());
+        buffer.extend_from_slice(&e.right.to_le_bytes());
+        buffer.extend_from_slice(&e.parent.to_le_bytes());
+        buffer.extend_from_slice(&e.child.to_le_bytes());
+    }
+}
+
+fn encode_sites(sites: &[Site], buffer: &mut Vec<u8>) {
+    for s in sites {
+        buffer.extend_from_slice(&s.position.to_le_bytes());
+        encode_state(&s.ancestral_state, buffer);
+    }
+}
+
+fn encode_mutations(mutations: &[MutationRecord], buffer: &mut Vec<u8>) {
+    for m in mutations {
+        buffer.extend_from_slice(&m.node.to_le_bytes());
+        buffer.extend_from_slice(&(m.key as u64).to_le_bytes());
+        buffer.extend_from_slice(&(m.site as u64).to_le_bytes());
+        encode_state(&m.derived_state, buffer);
+        buffer.push(m.neutral as u8);
+    }
+}
+
+fn write_section<W: Write>(
+    num_rows: usize,
+    buffer: &mut Vec<u8>,
+    encode: impl FnOnce(&mut Vec<u8>),
+    writer: &mut W,
+) -> TablesIOResult<()> {
+    buffer.clear();
+    encode(buffer);
+    writer.write_all(&(num_rows as u64).to_le_bytes())?;
+    writer.write_all(&(buffer.len() as u64).to_le_bytes())?;
+    writer.write_all(buffer)?;
+    writer.write_all(&crc32(buffer).to_le_bytes())?;
+    Ok(())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> TablesIOResult<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> TablesIOResult<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_section<R: Read>(reader: &mut R, table: &'static str) -> TablesIOResult<(u64, Vec<u8>)> {
+    let num_rows = read_u64(reader)?;
+    let num_bytes = read_u64(reader)?;
+    let mut buffer = vec![];
+    // Use take so that a corrupt length cannot
+    // trigger a huge allocation up front.
+    reader.take(num_bytes).read_to_end(&mut buffer)?;
+    if buffer.len() as u64 != num_bytes {
+        return Err(TablesIOError::CorruptTable { table });
+    }
+    if read_u32(reader)? != crc32(&buffer) {
+        return Err(TablesIOError::ChecksumMismatch { table });
+    }
+    Ok((num_rows, buffer))
+}
+
+/// Decodes the rows of a single section.
+struct SectionDecoder<'a> {
+    data: &'a [u8],
+    table: &'static str,
+}
+
+impl<'a> SectionDecoder<'a> {
+    fn bytes(&mut self, n: usize) -> TablesIOResult<&'a [u8]> {
+        if self.data.len() < n {
+            return Err(TablesIOError::CorruptTable { table: self.table });
+        }
+        let (rv, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(rv)
+    }
+
+    fn array4(&mut self) -> TablesIOResult<[u8; 4]> {
+        let mut rv = [0u8; 4];
+        rv.copy_from_slice(self.bytes(4)?);
+        Ok(rv)
+    }
+
+    fn array8(&mut self) -> TablesIOResult<[u8; 8]> {
+        let mut rv = [0u8; 8];
+        rv.copy_from_slice(self.bytes(8)?);
+        Ok(rv)
+    }
+
+    fn id(&mut self) -> TablesIOResult<IdType> {
+        Ok(IdType::from_le_bytes(self.array4()?))
+    }
+
+    fn position(&mut self) -> TablesIOResult<Position> {
+        Ok(Position::from_le_bytes(self.array8()?))
+    }
+
+    fn time(&mut self) -> TablesIOResult<Time> {
+        Ok(Time::from_le_bytes(self.array8()?))
+    }
+
+    fn index(&mut self) -> TablesIOResult<usize> {
+        let x = u64::from_le_bytes(self.array8()?);
+        if x > usize::MAX as u64 {
+            return Err(TablesIOError::CorruptTable { table: self.table });
+        }
+        Ok(x as usize)
+    }
+
+    fn flag(&mut self) -> TablesIOResult<bool> {
+        match self.bytes(1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(TablesIOError::CorruptTable { table: self.table }),
+        }
+    }
+
+    fn state(&mut self) -> TablesIOResult<Option<Vec<u8>>> {
+        if self.flag()? {
+            let n = self.index()?;
+            Ok(Some(self.bytes(n)?.to_vec()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn finish(&self) -> TablesIOResult<()> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(TablesIOError::CorruptTable { table: self.table })
+        }
+    }
+}
+
+fn decode_section<R: Read>(
+    reader: &mut R,
+    table: &'static str,
+    mut decode_row: impl FnMut(&mut SectionDecoder) -> TablesIOResult<()>,
+) -> TablesIOResult<()> {
+    let (num_rows, buffer) = read_section(reader, table)?;
+    let mut decoder = SectionDecoder {
+        data: &buffer,
+        table,
+    };
+    for _ in 0..num_rows {
+        decode_row(&mut decoder)?;
+    }
+    decoder.finish()
+}
+
+impl TableCollection {
+    /// Write the tables to `writer` in the native binary format.
+    ///
+    /// See [``TableCollection::dump``] to write to a file.
+    ///
+    /// # Errors
+    ///
+    /// Will return [``TablesIOError``] if writing fails.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut tables = forrustts::TableCollection::new(100).unwrap();
+    /// tables.add_node(0, 0).unwrap();
+    /// let mut buffer: Vec<u8> = vec![];
+    /// tables.dump_to(&mut buffer).unwrap();
+    /// let loaded = forrustts::TableCollection::load_from(&mut buffer.as_slice()).unwrap();
+    /// assert_eq!(loaded.genome_length(), 100);
+    /// assert_eq!(loaded.num_nodes(), 1);
+    /// ```
+    pub fn dump_to<W: Write>(&self, writer: &mut W) -> TablesIOResult<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&self.genome_length().to_le_bytes())?;
+
+        let mut buffer = vec![];
+        write_section(
+            self.nodes_.len(),
+            &mut buffer,
+            |b| encode_nodes(&self.nodes_, b),
+            writer,
+        )?;
+        write_section(
+            self.edges_.len(),
+            &mut buffer,
+            |b| encode_edges(&self.edges_, b),
+            writer,
+        )?;
+        write_section(
+            self.sites_.len(),
+            &mut buffer,
+            |b| encode_sites(&self.sites_, b),
+            writer,
+        )?;
+        write_section(
+            self.mutations_.len(),
+            &mut buffer,
+            |b| encode_mutations(&self.mutations_, b),
+            writer,
+        )?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Read tables written by [``TableCollection::dump_to``]
+    /// from `reader`.
+    ///
+    /// See [``TableCollection::load``] to read from a file.
+    ///
+    /// # Errors
+    ///
+    /// Will return [``TablesIOError``] if reading fails,
+    /// if the input is not in the native binary format,
+    /// or if the input is corrupt.
+    pub fn load_from<R: Read>(reader: &mut R) -> TablesIOResult<TableCollection> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(TablesIOError::InvalidHeader);
+        }
+        let version = read_u32(reader)?;
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(TablesIOError::UnsupportedVersion { found: version });
+        }
+        let genome_length = read_u64(reader)? as Position;
+        let mut tables = TableCollection::new(genome_length)?;
+
+        decode_section(reader, "node", |d| {
+            let time = d.time()?;
+            let deme = d.id()?;
+            let flags = if version >= 2 {
+                NodeFlags::from_bits_truncate(u32::from_le_bytes(d.array4()?))
+            } else {
+                NodeFlags::empty()
+            };
+            tables.add_node_with_flags(time, deme, flags)?;
+            Ok(())
+        })?;
+        decode_section(reader, "edge", |d| {
+            let left = d.position()?;
+            let right = d.position()?;
+            let parent = d.id()?;
+            let child = d.id()?;
+            tables.add_edge(left, right, parent, child)?;
+            Ok(())
+        })?;
+        decode_section(reader, "site", |d| {
+            let position = d.position()?;
+            let ancestral_state = d.state()?;
+            tables.add_site(position, ancestral_state)?;
+            Ok(())
+        })?;
+        decode_section(reader, "mutation", |d| {
+            let node = d.id()?;
+            let key = d.index()?;
+            let site = d.index()?;
+            let derived_state = d.state()?;
+            let neutral = d.flag()?;
+            tables.add_mutation(node, key, site, derived_state, neutral)?;
+            Ok(())
+        })?;
+
+        Ok(tables)
+    }
+
+    /// Write the tables to a file in the native binary format.
+    ///
+    /// Unlike exporting to `tskit`, the integer
+    /// representations of [``Time``] and [``Position``]
+    /// are preserved exactly.
+    ///
+    /// # Parameters
+    ///
+    /// * `path`, the name of the output file. An existing file will be overwritten.
+    ///
+    /// # Errors
+    ///
+    /// Will return [``TablesIOError``] if writing fails.
+    pub fn dump<P: AsRef<std::path::Path>>(&self, path: P) -> TablesIOResult<()> {
+        let file = std::fs::File::create(path)?;
+        let mut writer = std::io::BufWriter::new(file);
+        self.dump_to(&mut writer)
+    }
+
+    /// Read tables from a file written by [``TableCollection::dump``].
+    ///
+    /// # Errors
+    ///
+    /// See [``TableCollection::load_from``].
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> TablesIOResult<TableCollection> {
+        let file = std::fs::File::open(path)?;
+        let mut reader = std::io::BufReader::new(file);
+        TableCollection::load_from(&mut reader)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn make_tables() -> TableCollection {
+        let mut tables = TableCollection::new(1000).unwrap();
+        tables.add_node(0, 0).unwrap();
+        tables.add_node(-3, 1).unwrap();
+        tables
+            .add_node_with_flags(1, 2, NodeFlags::IS_SAMPLE | NodeFlags::USER_BITS)
+            .unwrap();
+        tables.add_edge(0, 500, 0, 2).unwrap();
+        tables.add_edge(500, 1000, 1, 2).unwrap();
+        tables.add_site(7, Some(vec![0, 1, 2])).unwrap();
+        tables.add_site(999, None).unwrap();
+        tables
+            .add_mutation(2, 11, 0, Some(b"A".to_vec()), false)
+            .unwrap();
+        tables.add_mutation(1, 12, 1, None, true).unwrap();
+        tables
+    }
+
+    #[test]
+    fn test_crc32() {
+        // Standard check value for CRC-32/ISO-HDLC
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_round_trip_buffer() {
+        let tables = make_tables();
+        let mut buffer: Vec<u8> = vec![];
+        tables.dump_to(&mut buffer).unwrap();
+        assert_eq!(&buffer[0..8], &MAGIC);
+        let loaded = TableCollection::load_from(&mut buffer.as_slice()).unwrap();
+        assert_eq!(tables, loaded);
+    }
+
+    #[test]
+    fn test_round_trip_empty_tables() {
+        let tables = TableCollection::new(1).unwrap();
+        let mut buffer: Vec<u8> = vec![];
+        tables.dump_to(&mut buffer).unwrap();
+        let loaded = TableCollection::load_from(&mut buffer.as_slice()).unwrap();
+        assert_eq!(tables, loaded);
+    }
+
+    #[test]
+    fn test_round_trip_file() {
+        let tables = make_tables();
+        let path = std::env::temp_dir().join(format!(
+            "forrustts_test_round_trip_file_{}.tables",
+            std::process::id()
+        ));
+        tables.dump(&path).unwrap();
+        let loaded = TableCollection::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(tables, loaded);
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        let tables = make_tables();
+        let mut buffer: Vec<u8> = vec![];
+        tables.dump_to(&mut buffer).unwrap();
+        buffer[0] = b'X';
+        match TableCollection::load_from(&mut buffer.as_slice()) {
+            Err(TablesIOError::InvalidHeader) => (),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_bad_version() {
+        let tables = make_tables();
+        let mut buffer: Vec<u8> = vec![];
+        tables.dump_to(&mut buffer).unwrap();
+        buffer[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        match TableCollection::load_from(&mut buffer.as_slice()) {
+            Err(TablesIOError::UnsupportedVersion { found }) => {
+                assert_eq!(found, FORMAT_VERSION + 1)
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_read_version_1() {
+        // Version 1 has no node flags
+        let mut buffer: Vec<u8> = vec![];
+        buffer.extend_from_slice(&MAGIC);
+        buffer.extend_from_slice(&1u32.to_le_bytes());
+        buffer.extend_from_slice(&100i64.to_le_bytes());
+        let mut section = vec![];
+        write_section(
+            1,
+            &mut section,
+            |b| {
+                b.extend_from_slice(&(3 as Time).to_le_bytes());
+                b.extend_from_slice(&(1 as IdType).to_le_bytes());
+            },
+            &mut buffer,
+        )
+        .unwrap();
+        for _ in 0..3 {
+            write_section(0, &mut section, |_| (), &mut buffer).unwrap();
+        }
+        let loaded = TableCollection::load_from(&mut buffer.as_slice()).unwrap();
+        assert_eq!(loaded.num_nodes(), 1);
+        assert_eq!(loaded.node(0).time, 3);
+        assert_eq!(loaded.node(0).deme, 1);
+        assert!(loaded.node(0).flags.is_empty());
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let tables = make_tables();
+        let mut buffer: Vec<u8> = vec![];
+        tables.dump_to(&mut buffer).unwrap();
+        // Flip a bit in the first node's time.
+        // The header is 20 bytes, followed by 16 bytes
+        // of section metadata.
+        buffer[36] ^= 1;
+        match TableCollection::load_from(&mut buffer.as_slice()) {
+            Err(TablesIOError::ChecksumMismatch { table }) => assert_eq!(table, "node"),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_truncated_input() {
+        let tables = make_tables();
+        let mut buffer: Vec<u8> = vec![];
+        tables.dump_to(&mut buffer).unwrap();
+        buffer.truncate(buffer.len() - 10);
+        assert!(TableCollection::load_from(&mut buffer.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+//! Tables and helpers shared by tests.
+
+use crate::tables::{NodeFlags, TableCollection};
+use crate::tsdef::Position;
+
+/// Compare floating point values with a relative tolerance.
+pub(crate) fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-9 * (1. + a.abs().max(b.abs()))
+}
+
+// Two trees:
+//
+// [0, 50)       [50, 100)
+//     0             0
+//    / \           / \
+//   1   \         1   \
+//  / \   \       / \   \
+// 2   3   4     2   4   3
+//
+// Node times are 0, 5, and 10.
+pub(crate) fn two_trees() -> TableCollection {
+    let mut tables = TableCollection::new(100).unwrap();
+    tables.add_node(0, 0).unwrap();
+    tables.add_node(5, 0).unwrap();
+    for _ in 0..3 {
+        tables
+            .add_node_with_flags(10, 0, NodeFlags::IS_SAMPLE)
+            .unwrap();
+    }
+    tables.add_edge(0, 100, 0, 1).unwrap();
+    tables.add_edge(0, 100, 1, 2).unwrap();
+    tables.add_edge(0, 50, 1, 3).unwrap();
+    tables.add_edge(50, 100, 0, 3).unwrap();
+    tables.add_edge(0, 50, 0, 4).unwrap();
+    tables.add_edge(50, 100, 1, 4).unwrap();
+    tables
+}
+
+//       0
+//     /   \
+//    1     2
+//   / \   / \
+//  3   4 5   6
+//
+// Node times are 0, 5, and 10.
+// If ``swap_at`` is given, 4 and 5 swap places
+// from that position to the end of the genome.
+pub(crate) fn balanced_tree(swap_at: Option<Position>) -> TableCollection {
+    let mut tables = TableCollection::new(100).unwrap();
+    tables.add_node(0, 0).unwrap();
+    tables.add_node(5, 0).unwrap();
+    tables.add_node(5, 0).unwrap();
+    for _ in 0..4 {
+        tables
+            .add_node_with_flags(10, 0, NodeFlags::IS_SAMPLE)
+            .unwrap();
+    }
+    let breakpoint = swap_at.unwrap_or(100);
+    for (p, c) in [(0, 1), (0, 2), (1, 3), (1, 4), (2, 5), (2, 6)] {
+        tables.add_edge(0, breakpoint, p, c).unwrap();
+    }
+    if breakpoint < 100 {
+        for (p, c) in [(0, 1), (0, 2), (1, 3), (2, 4), (1, 5), (2, 6)] {
+            tables.add_edge(breakpoint, 100, p, c).unwrap();
+        }
+    }
+    tables
+}
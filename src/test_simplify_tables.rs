@@ -41,7 +41,7 @@ mod test {
         // None here means "never simplify".
         simplification_interval: Option<Time>,
         flags: SimulationFlags,
-    ) -> Result<(TableCollection, Vec<i32>), ForrusttsError> {
+    ) -> Result<TableCollection, ForrusttsError> {
         neutral_wf(
             PopulationParams {
                 size: 250,
@@ -70,7 +70,7 @@ mod test {
         let num_generations = 5000;
         let genome_length = 1000000;
 
-        let (mut tables, mut is_sample) = simulate_data(
+        let mut tables = simulate_data(
             num_generations,
             genome_length,
             0.0,
@@ -80,14 +80,15 @@ mod test {
         )
         .unwrap();
 
-        let mut tsk_tables = crate::tskit::convert_to_tskit_minimal(
+        let mut tsk_tables = crate::tskit::export_to_tskit(
             &tables,
-            &is_sample,
-            crate::tskit::simple_time_reverser(num_generations),
-            // Do not index tables here!
-            // Things are unsorted!
-            false,
-        );
+            &crate::tskit::TskitExportOptions::new()
+                .convert_time(crate::tskit::simple_time_reverser(num_generations))
+                // Do not index tables here!
+                // Things are unsorted!
+                .build_indexes(false),
+        )
+        .unwrap();
 
         // Now, sort and simplify the tables we got from the sim:
         tables.sort_tables(crate::TableSortingFlags::empty());
@@ -107,17 +108,13 @@ mod test {
         )
         .unwrap();
 
-        is_sample = vec![0; tables.num_nodes()];
-        for i in is_sample.iter_mut().take(500) {
-            *i = 1;
-        }
-
-        let mut simplified_rust_tables = crate::tskit::convert_to_tskit_minimal(
+        let mut simplified_rust_tables = crate::tskit::export_to_tskit(
             &tables,
-            &is_sample,
-            crate::tskit::simple_time_reverser(num_generations),
-            true,
-        );
+            &crate::tskit::TskitExportOptions::new()
+                .convert_time(crate::tskit::simple_time_reverser(num_generations))
+                .build_indexes(true),
+        )
+        .unwrap();
 
         unsafe {
             let rv = tskr::tsk_table_collection_sort(tsk_tables.as_mut_ptr(), std::ptr::null(), 0);
@@ -166,7 +163,7 @@ mod test {
         let genome_length = 1000000;
 
         let flags = SimulationFlags::USE_STATE;
-        let (tables_sorted, is_sample_sorted) = simulate_data(
+        let tables_sorted = simulate_data(
             num_generations,
             genome_length,
             0.0,
@@ -177,7 +174,7 @@ mod test {
         .unwrap();
 
         let flags = SimulationFlags::BUFFER_EDGES;
-        let (tables_buffered, is_sample_buffered) = simulate_data(
+        let tables_buffered = simulate_data(
             num_generations,
             genome_length,
             0.0,
@@ -193,19 +190,21 @@ mod test {
         let sum_times_buffered: Time = tables_buffered.nodes_.iter().map(|x| x.time).sum();
         assert_eq!(sum_times_sorted, sum_times_buffered);
 
-        let mut tables_sorted_tskit = crate::tskit::convert_to_tskit_minimal(
+        let mut tables_sorted_tskit = crate::tskit::export_to_tskit(
             &tables_sorted,
-            &is_sample_sorted,
-            crate::tskit::simple_time_reverser(num_generations),
-            true,
-        );
+            &crate::tskit::TskitExportOptions::new()
+                .convert_time(crate::tskit::simple_time_reverser(num_generations))
+                .build_indexes(true),
+        )
+        .unwrap();
 
-        let mut tables_buffered_tskit = crate::tskit::convert_to_tskit_minimal(
+        let mut tables_buffered_tskit = crate::tskit::export_to_tskit(
             &tables_buffered,
-            &is_sample_buffered,
-            crate::tskit::simple_time_reverser(num_generations),
-            true,
-        );
+            &crate::tskit::TskitExportOptions::new()
+                .convert_time(crate::tskit::simple_time_reverser(num_generations))
+                .build_indexes(true),
+        )
+        .unwrap();
 
         let mut sorted_ts = tables_to_treeseq(&mut tables_sorted_tskit);
         let mut buffered_ts = tables_to_treeseq(&mut tables_buffered_tskit);
@@ -236,7 +235,7 @@ mod test {
         let genome_length = 1000000;
 
         let flags = SimulationFlags::USE_STATE;
-        let (tables_sorted, is_sample_sorted) = simulate_data(
+        let tables_sorted = simulate_data(
             num_generations,
             genome_length,
             0.5,
@@ -247,7 +246,7 @@ mod test {
         .unwrap();
 
         let flags = SimulationFlags::BUFFER_EDGES;
-        let (tables_buffered, is_sample_buffered) = simulate_data(
+        let tables_buffered = simulate_data(
             num_generations,
             genome_length,
             0.5,
@@ -265,19 +264,21 @@ mod test {
         let sum_times_buffered: Time = tables_buffered.nodes_.iter().map(|x| x.time).sum();
         assert_eq!(sum_times_sorted, sum_times_buffered);
 
-        let mut tables_sorted_tskit = crate::tskit::convert_to_tskit_minimal(
+        let mut tables_sorted_tskit = crate::tskit::export_to_tskit(
             &tables_sorted,
-            &is_sample_sorted,
-            crate::tskit::simple_time_reverser(num_generations),
-            true,
-        );
+            &crate::tskit::TskitExportOptions::new()
+                .convert_time(crate::tskit::simple_time_reverser(num_generations))
+                .build_indexes(true),
+        )
+        .unwrap();
 
-        let mut tables_buffered_tskit = crate::tskit::convert_to_tskit_minimal(
+        let mut tables_buffered_tskit = crate::tskit::export_to_tskit(
             &tables_buffered,
-            &is_sample_buffered,
-            crate::tskit::simple_time_reverser(num_generations),
-            true,
-        );
+            &crate::tskit::TskitExportOptions::new()
+                .convert_time(crate::tskit::simple_time_reverser(num_generations))
+                .build_indexes(true),
+        )
+        .unwrap();
 
         let mut sorted_ts = tables_to_treeseq(&mut tables_sorted_tskit);
         let mut buffered_ts = tables_to_treeseq(&mut tables_buffered_tskit);
@@ -0,0 +1,673 @@
+//! Iteration over the marginal trees of a [``TableCollection``].
+
+use crate::tables::*;
+use crate::tsdef::{IdType, Position, NULL_ID};
+use std::borrow::Cow;
+
+/// A marginal tree.
+///
+/// The tree is a "quintuply-linked" structure, as in ``tskit``.
+/// For node `u`, the arrays give its parent, its leftmost and
+/// rightmost children, and its left and right siblings.
+/// Missing values are [``NULL_ID``](crate::NULL_ID).
+///
+/// The roots of the tree are the nodes with no parent
+/// that have at least one sample below them (or are samples).
+///
+/// See [``TreeIterator``].
+pub struct Tree {
+    left: Position,
+    right: Position,
+    index: usize,
+    num_nodes: usize,
+    // These arrays have num_nodes + 1 elements.
+    // The last element is a "virtual root"
+    // whose children are the roots of the tree.
+    parent: Vec<IdType>,
+    left_child: Vec<IdType>,
+    right_child: Vec<IdType>,
+    left_sib: Vec<IdType>,
+    right_sib: Vec<IdType>,
+    num_samples: Vec<u32>,
+    samples: Vec<IdType>,
+}
+
+impl Tree {
+    fn new(num_nodes: usize, samples: Vec<IdType>) -> Self {
+        let mut tree = Tree {
+            left: 0,
+            right: 0,
+            index: 0,
+            num_nodes,
+            parent: vec![NULL_ID; num_nodes + 1],
+            left_child: vec![NULL_ID; num_nodes + 1],
+            right_child: vec![NULL_ID; num_nodes + 1],
+            left_sib: vec![NULL_ID; num_nodes + 1],
+            right_sib: vec![NULL_ID; num_nodes + 1],
+            num_samples: vec![0; num_nodes + 1],
+            samples,
+        };
+        // Each sample starts out as a root.
+        for i in 0..tree.samples.len() {
+            let s = tree.samples[i];
+            tree.num_samples[s as usize] = 1;
+            tree.link_root(s);
+        }
+        tree
+    }
+
+    fn virtual_root(&self) -> IdType {
+        self.num_nodes as IdType
+    }
+
+    fn insert_child(&mut self, p: IdType, c: IdType) {
+        let (pu, cu) = (p as usize, c as usize);
+        let last = self.right_child[pu];
+        self.parent[cu] = p;
+        if last == NULL_ID {
+            self.left_child[pu] = c;
+        } else {
+            self.right_sib[last as usize] = c;
+        }
+        self.left_sib[cu] = last;
+        self.right_sib[cu] = NULL_ID;
+        self.right_child[pu] = c;
+    }
+
+    fn remove_child(&mut self, p: IdType, c: IdType) {
+        let (pu, cu) = (p as usize, c as usize);
+        let lsib = self.left_sib[cu];
+        let rsib = self.right_sib[cu];
+        if lsib == NULL_ID {
+            self.left_child[pu] = rsib;
+        } else {
+            self.right_sib[lsib as usize] = rsib;
+        }
+        if rsib == NULL_ID {
+            self.right_child[pu] = lsib;
+        } else {
+            self.left_sib[rsib as usize] = lsib;
+        }
+        self.parent[cu] = NULL_ID;
+        self.left_sib[cu] = NULL_ID;
+        self.right_sib[cu] = NULL_ID;
+    }
+
+    fn link_root(&mut self, u: IdType) {
+        let vr = self.virtual_root();
+        self.insert_child(vr, u);
+        // Roots have no parent.
+        self.parent[u as usize] = NULL_ID;
+    }
+
+    fn unlink_root(&mut self, u: IdType) {
+        let vr = self.virtual_root();
+        // remove_child requires the parent to be set.
+        self.parent[u as usize] = vr;
+        self.remove_child(vr, u);
+    }
+
+    fn is_root(&self, u: IdType) -> bool {
+        self.parent[u as usize] == NULL_ID && self.num_samples[u as usize] > 0
+    }
+
+    /// Return the top of the path from `u` to its root,
+    /// adding `delta` to the sample counts along the way.
+    fn update_counts_above(&mut self, mut u: IdType, delta: i64) -> IdType {
+        loop {
+            let count = self.num_samples[u as usize] as i64 + delta;
+            self.num_samples[u as usize] = count as u32;
+            let p = self.parent[u as usize];
+            if p == NULL_ID {
+                return u;
+            }
+            u = p;
+        }
+    }
+
+    fn insert_edge(&mut self, e: &Edge) {
+        let (p, c) = (e.parent, e.child);
+        let c_count = self.num_samples[c as usize];
+        if self.is_root(c) {
+            self.unlink_root(c);
+        }
+        // The top of the path containing p
+        let mut top = p;
+        while self.parent[top as usize] != NULL_ID {
+            top = self.parent[top as usize];
+        }
+        let top_was_root = self.is_root(top);
+        self.insert_child(p, c);
+        if c_count > 0 {
+            self.update_counts_above(p, c_count as i64);
+            if !top_was_root {
+                self.link_root(top);
+            }
+        }
+    }
+
+    fn remove_edge(&mut self, e: &Edge) {
+        let (p, c) = (e.parent, e.child);
+        let c_count = self.num_samples[c as usize];
+        self.remove_child(p, c);
+        if c_count > 0 {
+            let top = self.update_counts_above(p, -(c_count as i64));
+            if self.num_samples[top as usize] == 0 {
+                self.unlink_root(top);
+            }
+            self.link_root(c);
+        }
+    }
+
+    /// The genomic interval `[left, right)` covered by this tree.
+    pub fn interval(&self) -> (Position, Position) {
+        (self.left, self.right)
+    }
+
+    /// The index of this tree, starting from `0`
+    /// for the leftmost tree.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The parent of each node.
+    pub fn parents(&self) -> &[IdType] {
+        &self.parent[..self.num_nodes]
+    }
+
+    /// The leftmost child of each node.
+    pub fn left_children(&self) -> &[IdType] {
+        &self.left_child[..self.num_nodes]
+    }
+
+    /// The rightmost child of each node.
+    pub fn right_children(&self) -> &[IdType] {
+        &self.right_child[..self.num_nodes]
+    }
+
+    /// The left sibling of each node.
+    pub fn left_sibs(&self) -> &[IdType] {
+        &self.left_sib[..self.num_nodes]
+    }
+
+    /// The right sibling of each node.
+    ///
+    /// The siblings of a root are other roots.
+    pub fn right_sibs(&self) -> &[IdType] {
+        &self.right_sib[..self.num_nodes]
+    }
+
+    /// The number of samples below each node,
+    /// including the node itself if it is a sample.
+    pub fn sample_counts(&self) -> &[u32] {
+        &self.num_samples[..self.num_nodes]
+    }
+
+    /// The parent of node `u`.
+    pub fn parent(&self, u: IdType) -> IdType {
+        self.parents()[u as usize]
+    }
+
+    /// The number of samples below node `u`.
+    pub fn num_samples(&self, u: IdType) -> u32 {
+        self.sample_counts()[u as usize]
+    }
+
+    /// The samples used to build the trees.
+    pub fn samples(&self) -> &[IdType] {
+        &self.samples
+    }
+
+    /// Iterate over the children of node `u`,
+    /// from left to right.
+    pub fn children(&self, u: IdType) -> impl Iterator<Item = IdType> + '_ {
+        let first = self.left_child[u as usize];
+        std::iter::successors(Some(first).filter(|&x| x != NULL_ID), move |&c| {
+            Some(self.right_sib[c as usize]).filter(|&x| x != NULL_ID)
+        })
+    }
+
+    /// Iterate over the roots of the tree, from left to right.
+    pub fn roots(&self) -> impl Iterator<Item = IdType> + '_ {
+        self.children(self.virtual_root())
+    }
+
+    /// The number of roots.
+    pub fn num_roots(&self) -> usize {
+        self.roots().count()
+    }
+
+    fn depth(&self, mut u: IdType) -> usize {
+        let mut depth = 0;
+        while self.parent(u) != NULL_ID {
+            u = self.parent(u);
+            depth += 1;
+        }
+        depth
+    }
+
+    /// The most recent common ancestor of nodes `u` and `v`.
+    ///
+    /// A node is its own ancestor, so the MRCA of `u` and
+    /// one of its descendants is `u`.
+    ///
+    /// # Returns
+    ///
+    /// [``NULL_ID``](crate::NULL_ID) if `u` and `v` are not in
+    /// the same tree.
+    pub fn mrca(&self, mut u: IdType, mut v: IdType) -> IdType {
+        let mut du = self.depth(u);
+        let mut dv = self.depth(v);
+        while du > dv {
+            u = self.parent(u);
+            du -= 1;
+        }
+        while dv > du {
+            v = self.parent(v);
+            dv -= 1;
+        }
+        while u != v {
+            u = self.parent(u);
+            v = self.parent(v);
+        }
+        u
+    }
+}
+
+/// Iterate over the marginal trees of a [``TableCollection``],
+/// from left to right.
+///
+/// The trees cover the whole genome, including
+/// regions with no edges.
+/// The edge table does not have to be sorted.
+/// If [``TableCollection::has_indexes``] is `true`,
+/// the stored edge indexes are used.
+/// Otherwise, they are built for this iterator.
+///
+/// Because each tree is updated in place,
+/// this type does not implement [``Iterator``].
+/// Use [``TreeIterator::next_tree``] instead.
+///
+/// # Example
+///
+/// ```
+/// use forrustts::{NodeFlags, TableCollection, TreeIterator};
+///
+/// let mut tables = TableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap();
+/// tables.add_node(1, 0).unwrap();
+/// for _ in 0..2 {
+///     tables
+///         .add_node_with_flags(2, 0, NodeFlags::IS_SAMPLE)
+///         .unwrap();
+/// }
+/// tables.add_edge(0, 50, 0, 2).unwrap();
+/// tables.add_edge(0, 50, 0, 3).unwrap();
+/// tables.add_edge(50, 100, 1, 2).unwrap();
+/// tables.add_edge(50, 100, 1, 3).unwrap();
+///
+/// let mut trees = TreeIterator::new(&tables).unwrap();
+/// let mut intervals = vec![];
+/// while let Some(tree) = trees.next_tree() {
+///     intervals.push(tree.interval());
+///     assert_eq!(tree.num_roots(), 1);
+///     let root = tree.roots().next().unwrap();
+///     assert_eq!(tree.num_samples(root), 2);
+///     assert_eq!(tree.children(root).collect::<Vec<_>>(), vec![2, 3]);
+/// }
+/// assert_eq!(intervals, vec![(0, 50), (50, 100)]);
+/// ```
+pub struct TreeIterator<'a> {
+    tables: &'a TableCollection,
+    insertion: Cow<'a, [IdType]>,
+    removal: Cow<'a, [IdType]>,
+    insertion_index: usize,
+    removal_index: usize,
+    tree: Tree,
+    started: bool,
+}
+
+impl<'a> TreeIterator<'a> {
+    /// Iterate over trees whose samples are the nodes with
+    /// [``NodeFlags::IS_SAMPLE``] set.
+    ///
+    /// # Errors
+    ///
+    /// See [``TreeIterator::new_with_samples``].
+    pub fn new(tables: &'a TableCollection) -> TablesResult<Self> {
+        let samples = tables.sample_nodes();
+        Self::new_with_samples(tables, &samples)
+    }
+
+    /// Iterate over trees with the given samples.
+    ///
+    /// # Errors
+    ///
+    /// Will return [``TablesError::NodeOutOfBounds``] if any sample
+    /// or edge refers to a node that is not in the node table,
+    /// and [``TablesError::InvalidNodeValue``] if a sample is
+    /// repeated.
+    pub fn new_with_samples(tables: &'a TableCollection, samples: &[IdType]) -> TablesResult<Self> {
+        let num_nodes = tables.num_nodes();
+        let in_bounds = |u: IdType| u >= 0 && (u as usize) < num_nodes;
+        for e in tables.edges() {
+            if !in_bounds(e.parent) || !in_bounds(e.child) {
+                return Err(TablesError::NodeOutOfBounds);
+            }
+        }
+        let mut is_sample = vec![false; num_nodes];
+        for &s in samples {
+            if !in_bounds(s) {
+                return Err(TablesError::NodeOutOfBounds);
+            }
+            if is_sample[s as usize] {
+                return Err(TablesError::InvalidNodeValue { found: s });
+            }
+            is_sample[s as usize] = true;
+        }
+        let (insertion, removal) =
+            match (tables.edge_insertion_order(), tables.edge_removal_order()) {
+                (Some(i), Some(r)) => (Cow::Borrowed(i), Cow::Borrowed(r)),
+                _ => {
+                    let indexes = make_edge_indexes(tables.nodes(), tables.edges());
+                    (Cow::Owned(indexes.insertion), Cow::Owned(indexes.removal))
+                }
+            };
+        Ok(TreeIterator {
+            tables,
+            insertion,
+            removal,
+            insertion_index: 0,
+            removal_index: 0,
+            tree: Tree::new(num_nodes, samples.to_vec()),
+            started: false,
+        })
+    }
+
+    /// Advance to the next tree.
+    ///
+    /// Returns [``None``] once all trees have been visited.
+    pub fn next_tree(&mut self) -> Option<&Tree> {
+        let genome_length = self.tables.genome_length();
+        let edges = self.tables.edges();
+        let num_edges = edges.len();
+
+        if self.started {
+            if self.tree.right >= genome_length {
+                return None;
+            }
+            self.tree.left = self.tree.right;
+            self.tree.index += 1;
+        }
+        self.started = true;
+        let left = self.tree.left;
+
+        while self.removal_index < num_edges
+            && edges[self.removal[self.removal_index] as usize].right == left
+        {
+            let e = &edges[self.removal[self.removal_index] as usize];
+            self.tree.remove_edge(e);
+            self.removal_index += 1;
+        }
+        while self.insertion_index < num_edges
+            && edges[self.insertion[self.insertion_index] as usize].left == left
+        {
+            let e = &edges[self.insertion[self.insertion_index] as usize];
+            self.tree.insert_edge(e);
+            self.insertion_index += 1;
+        }
+
+        let mut right = genome_length;
+        if self.insertion_index < num_edges {
+            right = std::cmp::min(
+                right,
+                edges[self.insertion[self.insertion_index] as usize].left,
+            );
+        }
+        if self.removal_index < num_edges {
+            right = std::cmp::min(
+                right,
+                edges[self.removal[self.removal_index] as usize].right,
+            );
+        }
+        self.tree.right = right;
+        Some(&self.tree)
+    }
+
+    /// Move to the tree containing a genomic position.
+    ///
+    /// Positions to the left of the current tree
+    /// restart iteration from the first tree.
+    /// Later calls to [``TreeIterator::next_tree``]
+    /// continue from the returned tree.
+    ///
+    /// # Errors
+    ///
+    /// Will return [``TablesError::InvalidPosition``] if
+    /// `position` is not in `[0, genome_length)`.
+    pub fn tree_at(&mut self, position: Position) -> TablesResult<&Tree> {
+        if position < 0 || position >= self.tables.genome_length() {
+            return Err(TablesError::InvalidPosition { found: position });
+        }
+        if self.started && position < self.tree.left {
+            let samples = std::mem::take(&mut self.tree.samples);
+            self.tree = Tree::new(self.tables.num_nodes(), samples);
+            self.insertion_index = 0;
+            self.removal_index = 0;
+            self.started = false;
+        }
+        while !self.started || self.tree.right <= position {
+            // The trees cover the genome, so this
+            // stops before running out of trees.
+            self.next_tree();
+        }
+        Ok(&self.tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_tables() -> TableCollection {
+        let mut tables = TableCollection::new(100).unwrap();
+        tables.add_node(0, 0).unwrap(); // 0
+        tables.add_node(1, 0).unwrap(); // 1
+        for _ in 0..3 {
+            tables
+                .add_node_with_flags(2, 0, NodeFlags::IS_SAMPLE)
+                .unwrap(); // 2, 3, 4
+        }
+        tables.add_edge(0, 100, 0, 1).unwrap();
+        tables.add_edge(0, 60, 1, 2).unwrap();
+        tables.add_edge(0, 100, 1, 3).unwrap();
+        tables.add_edge(60, 100, 0, 2).unwrap();
+        tables.add_edge(10, 100, 0, 4).unwrap();
+        tables
+    }
+
+    /// Check a tree against the edges that overlap it.
+    fn check_tree(tables: &TableCollection, tree: &Tree) {
+        let (left, right) = tree.interval();
+        let mut parents = vec![NULL_ID; tables.num_nodes()];
+        for e in tables.edges() {
+            if e.left <= left && right <= e.right {
+                parents[e.child as usize] = e.parent;
+            } else {
+                assert!(e.right <= left || e.left >= right);
+            }
+        }
+        assert_eq!(tree.parents(), parents.as_slice());
+
+        let mut counts = vec![0; tables.num_nodes()];
+        for &s in tree.samples() {
+            let mut u = s;
+            while u != NULL_ID {
+                counts[u as usize] += 1;
+                u = parents[u as usize];
+            }
+        }
+        assert_eq!(tree.sample_counts(), counts.as_slice());
+
+        let mut roots: Vec<IdType> = (0..tables.num_nodes() as IdType)
+            .filter(|&u| parents[u as usize] == NULL_ID && counts[u as usize] > 0)
+            .collect();
+        let mut tree_roots: Vec<IdType> = tree.roots().collect();
+        roots.sort_unstable();
+        tree_roots.sort_unstable();
+        assert_eq!(tree_roots, roots);
+
+        for u in 0..tables.num_nodes() as IdType {
+            for c in tree.children(u) {
+                assert_eq!(tree.parent(c), u);
+            }
+            let n = tree.children(u).count();
+            let expected = parents.iter().filter(|&&p| p == u).count();
+            assert_eq!(n, expected);
+        }
+    }
+
+    fn check_all_trees(tables: &TableCollection) -> usize {
+        let mut trees = TreeIterator::new(tables).unwrap();
+        let mut last_right = 0;
+        let mut num_trees = 0;
+        while let Some(tree) = trees.next_tree() {
+            assert_eq!(tree.index(), num_trees);
+            let (left, right) = tree.interval();
+            assert_eq!(left, last_right);
+            assert!(right > left);
+            check_tree(tables, tree);
+            last_right = right;
+            num_trees += 1;
+        }
+        assert_eq!(last_right, tables.genome_length());
+        assert!(trees.next_tree().is_none());
+        num_trees
+    }
+
+    #[test]
+    fn test_trees() {
+        let tables = make_tables();
+        assert_eq!(check_all_trees(&tables), 3);
+
+        let mut trees = TreeIterator::new(&tables).unwrap();
+        let tree = trees.next_tree().unwrap();
+        assert_eq!(tree.interval(), (0, 10));
+        // Sample 4 has no ancestry in this tree.
+        assert_eq!(tree.num_roots(), 2);
+        assert_eq!(tree.left_children()[1], 2);
+        assert_eq!(tree.right_children()[1], 3);
+        assert_eq!(tree.right_sibs()[2], 3);
+        assert_eq!(tree.left_sibs()[3], 2);
+        assert_eq!(tree.num_samples(0), 2);
+        assert_eq!(tree.mrca(2, 3), 1);
+        assert_eq!(tree.mrca(2, 4), NULL_ID);
+        let tree = trees.next_tree().unwrap();
+        assert_eq!(tree.interval(), (10, 60));
+        assert_eq!(tree.roots().collect::<Vec<_>>(), vec![0]);
+        assert_eq!(tree.num_samples(0), 3);
+        assert_eq!(tree.mrca(2, 4), 0);
+        assert_eq!(tree.mrca(1, 3), 1);
+        assert_eq!(tree.mrca(4, 4), 4);
+        let tree = trees.next_tree().unwrap();
+        assert_eq!(tree.interval(), (60, 100));
+        assert_eq!(tree.parent(2), 0);
+        assert_eq!(tree.num_samples(1), 1);
+        assert_eq!(tree.mrca(2, 3), 0);
+    }
+
+    #[test]
+    fn test_tree_at() {
+        let tables = make_tables();
+        let mut trees = TreeIterator::new(&tables).unwrap();
+        for (position, interval) in [
+            (65, (60, 100)),
+            (99, (60, 100)),
+            (10, (10, 60)),
+            (0, (0, 10)),
+            (59, (10, 60)),
+        ] {
+            let tree = trees.tree_at(position).unwrap();
+            assert_eq!(tree.interval(), interval);
+            check_tree(&tables, tree);
+        }
+        let tree = trees.next_tree().unwrap();
+        assert_eq!(tree.interval(), (60, 100));
+        assert_eq!(tree.index(), 2);
+        check_tree(&tables, tree);
+        for position in [-1, 100] {
+            assert!(matches!(
+                trees.tree_at(position),
+                Err(TablesError::InvalidPosition { found }) if found == position
+            ));
+        }
+    }
+
+    #[test]
+    fn test_empty_regions() {
+        let mut tables = TableCollection::new(100).unwrap();
+        tables.add_node(0, 0).unwrap();
+        tables
+            .add_node_with_flags(1, 0, NodeFlags::IS_SAMPLE)
+            .unwrap();
+        tables.add_edge(20, 30, 0, 1).unwrap();
+        assert_eq!(check_all_trees(&tables), 3);
+
+        let tables = TableCollection::new(10).unwrap();
+        let mut trees = TreeIterator::new(&tables).unwrap();
+        let tree = trees.next_tree().unwrap();
+        assert_eq!(tree.interval(), (0, 10));
+        assert_eq!(tree.num_roots(), 0);
+        assert!(trees.next_tree().is_none());
+    }
+
+    #[test]
+    fn test_simulated_trees() {
+        use crate::wright_fisher::*;
+        let tables = neutral_wf(
+            PopulationParams::new(50, 10000, 5e-3, 0.0),
+            SimulationParams::new(Some(10), 1234, 100, SimulationFlags::empty()),
+        )
+        .unwrap();
+        assert!(check_all_trees(&tables) > 1);
+    }
+
+    #[test]
+    fn test_stored_indexes() {
+        let mut tables = make_tables();
+        tables.build_indexes().unwrap();
+        let trees = TreeIterator::new(&tables).unwrap();
+        assert!(matches!(trees.insertion, Cow::Borrowed(_)));
+        assert!(matches!(trees.removal, Cow::Borrowed(_)));
+        assert_eq!(check_all_trees(&tables), 3);
+    }
+
+    #[test]
+    fn test_explicit_samples() {
+        let tables = make_tables();
+        let mut trees = TreeIterator::new_with_samples(&tables, &[2]).unwrap();
+        let tree = trees.next_tree().unwrap();
+        assert_eq!(tree.roots().collect::<Vec<_>>(), vec![0]);
+        assert_eq!(tree.num_samples(0), 1);
+        check_tree(&tables, tree);
+    }
+
+    #[test]
+    fn test_errors() {
+        let tables = make_tables();
+        assert!(matches!(
+            TreeIterator::new_with_samples(&tables, &[5]),
+            Err(TablesError::NodeOutOfBounds)
+        ));
+        assert!(matches!(
+            TreeIterator::new_with_samples(&tables, &[2, 2]),
+            Err(TablesError::InvalidNodeValue { found: 2 })
+        ));
+        let mut tables = make_tables();
+        tables.add_edge(0, 100, 0, 10).unwrap();
+        assert!(matches!(
+            TreeIterator::new(&tables),
+            Err(TablesError::NodeOutOfBounds)
+        ));
+    }
+}
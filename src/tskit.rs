@@ -1,17 +1,53 @@
-//! Data interchange to ``tskit`` format using [``tskit_rust``].
+//! Data interchange to and from ``tskit`` format.
 //!
-//! # Note
+//! Conversion to and from a ``tskit_rust::TableCollection`` requires
+//! the `tskit_rust` feature, which is enabled by default.
+//! Export is done by [``export_to_tskit``], whose behavior
+//! is set by [``TskitExportOptions``].
+//! [``export_to_tskit_from``] does the same, but consumes
+//! the input tables in order to free memory during the export.
 //!
-//! This module may be prone to API-breaking changes!
-//! Currently, the function implementations are the minimum
-//! needed for testing/data exchange.
+//! With the `kastore` feature, [``write_trees``] and
+//! [``write_trees_file``] write the ``tskit`` ``.trees``
+//! file format directly, without the ``tskit`` C library.
 //!
-//! As things develop, we may either add new functions or
-//! refactor the existing.
+//! # Mutation metadata
+//!
+//! When exporting, the [``MutationRecord::key``](crate::MutationRecord::key)
+//! and [``MutationRecord::neutral``](crate::MutationRecord::neutral)
+//! fields of each mutation are written as ``tskit`` mutation metadata.
+//! The metadata are the key as a little-endian `u64` followed by
+//! one byte that is `1` for neutral mutations and `0` otherwise.
+//! The mutation table's metadata schema describes this layout
+//! using the ``tskit`` "struct" codec.
+//!
+//! # Deprecated functions
+//!
+//! [``convert_to_tskit_minimal``] and
+//! [``convert_to_tskit_and_drain_minimal``] are kept for
+//! compatibility and will be removed in a future release.
+//! New code should use [``export_to_tskit``] and
+//! [``export_to_tskit_from``], which support all of the
+//! options of the older functions.
 
 use crate::tsdef::Time;
+#[cfg(feature = "tskit_rust")]
+use crate::tsdef::{IdType, Position};
+#[cfg(feature = "tskit_rust")]
 use crate::TableCollection;
-use tskit_rust::{tsk_flags_t, tsk_id_t, TSK_NODE_IS_SAMPLE, TSK_NULL};
+#[cfg(feature = "tskit_rust")]
+use crate::{ForrusttsError, NodeFlags, SamplesInfo, TableSortingFlags};
+
+#[cfg(feature = "tskit_rust")]
+mod export;
+#[cfg(feature = "kastore")]
+mod trees_file;
+
+#[cfg(feature = "tskit_rust")]
+pub use export::{export_to_tskit, export_to_tskit_from, TskitExportFlags, TskitExportOptions};
+
+#[cfg(feature = "kastore")]
+pub use trees_file::{write_trees, write_trees_file};
 
 /// Return a closure to help reverse time.
 ///
@@ -21,6 +57,39 @@ pub fn simple_time_reverser(x: Time) -> Box<dyn Fn(Time) -> f64> {
     Box::new(move |t: Time| -1. * (t - x) as f64)
 }
 
+/// Return a closure to convert backwards time to forwards time.
+///
+/// This is the inverse of [``simple_time_reverser``].
+/// For all input values, ``t`` the closure will
+/// return ``x - t``, rounded to the nearest [``Time``].
+///
+/// # Example
+///
+/// ```
+/// use forrustts::tskit::{simple_time_forwarder, simple_time_reverser};
+/// let reverser = simple_time_reverser(10);
+/// let forwarder = simple_time_forwarder(10);
+/// assert_eq!(reverser(3), 7.0);
+/// assert_eq!(forwarder(7.0), 3);
+/// assert_eq!(forwarder(reverser(3)), 3);
+/// ```
+pub fn simple_time_forwarder(x: Time) -> Box<dyn Fn(f64) -> Time> {
+    Box::new(move |t: f64| (x as f64 - t).round() as Time)
+}
+
+/// The ``tskit`` metadata schema for mutations.
+/// See the module documentation.
+#[cfg(any(feature = "tskit_rust", feature = "kastore"))]
+const MUTATION_METADATA_SCHEMA: &str = r#"{"codec":"struct","type":"object","properties":{"key":{"type":"integer","binaryFormat":"<Q","index":0},"neutral":{"type":"boolean","binaryFormat":"?","index":1}},"required":["key","neutral"],"additionalProperties":false}"#;
+
+#[cfg(any(feature = "tskit_rust", feature = "kastore"))]
+fn encode_mutation_metadata(m: &crate::MutationRecord) -> Vec<u8> {
+    let mut metadata = (m.key as u64).to_le_bytes().to_vec();
+    metadata.push(m.neutral as u8);
+    metadata
+}
+
+#[cfg(feature = "tskit_rust")]
 /// Convert a [``TableCollection``](crate::TableCollection)
 /// to ``tskit`` format.
 ///
@@ -36,77 +105,30 @@ pub fn simple_time_reverser(x: Time) -> Box<dyn Fn(Time) -> f64> {
 ///
 /// # Notes
 ///
-/// If the input ``tables`` are not sorted, pass ``false`` for
-/// `build_indexes`.
-///
-/// This function will not be part of the long-term API.
-/// Rather, it is the minimum currently needed to get stuff done.
-///
-/// # Returns
-///
-/// A [``tskit_rust::TableCollection``].
+/// This function is equivalent to [``export_to_tskit``]
+/// with the corresponding [``TskitExportOptions``].
 ///
-/// # Example
+/// # Errors
 ///
-/// ```
-/// let mut tables = forrustts::TableCollection::new(100).unwrap();
-/// tables.add_node(0, 0).unwrap(); // Add a parent node at time 0
-/// tables.add_node(1, 0).unwrap(); // Add a child node at time 1
-/// tables.add_edge(0, 100, 0, 1).unwrap(); // Add an edge
-/// let is_sample = vec![0, 1]; // Mark the child node as a sample.
-/// let tsk_tables = forrustts::tskit::convert_to_tskit_minimal(
-///     &tables,
-///     &is_sample,
-///     forrustts::tskit::simple_time_reverser(1),
-///     true,
-/// );
-/// assert_eq!(tsk_tables.nodes().num_rows(), 2);
-/// assert_eq!(tsk_tables.edges().num_rows(), 1);
-/// assert_eq!(tsk_tables.populations().num_rows(), 1);
-/// ```
+/// See [``export_to_tskit``].
+#[deprecated(since = "0.2.0", note = "use export_to_tskit instead")]
 pub fn convert_to_tskit_minimal(
     tables: &TableCollection,
     is_sample: &[i32],
     convert_time: impl Fn(Time) -> f64,
     build_indexes: bool,
-) -> tskit_rust::TableCollection {
-    let mut tsk_tables = tskit_rust::TableCollection::new(tables.genome_length() as f64).unwrap();
-
-    for e in tables.edges() {
-        tsk_tables
-            .add_edge(e.left as f64, e.right as f64, e.parent, e.child)
-            .unwrap();
-    }
-
-    let mut max_pop: tsk_id_t = -1;
-    for (i, n) in tables.enumerate_nodes() {
-        let flags: tsk_flags_t = if is_sample[i] > 0 {
-            TSK_NODE_IS_SAMPLE
-        } else {
-            0
-        };
-        tsk_tables
-            .add_node(flags, convert_time(n.time), n.deme, TSK_NULL)
-            .unwrap();
-        max_pop = std::cmp::max(n.deme, max_pop);
-    }
-
-    for _ in 0..(max_pop + 1) {
-        tsk_tables.add_population().unwrap();
-    }
-
-    if build_indexes {
-        tsk_tables.build_index(0).unwrap();
-    }
-
-    tsk_tables
-}
-
-fn swap_with_empty<T>(v: &mut Vec<T>) {
-    let mut temp = Vec::<T>::new();
-    std::mem::swap(v, &mut temp);
+) -> Result<tskit_rust::TableCollection, ForrusttsError> {
+    let options = TskitExportOptions::new()
+        .sample_nodes(export::sample_nodes_from_is_sample(tables, is_sample)?)
+        .build_indexes(build_indexes);
+    export::export_impl(
+        export::ExportSource::Borrowed(tables),
+        &convert_time,
+        &options,
+    )
 }
 
+#[cfg(feature = "tskit_rust")]
 /// Convert a [``TableCollection``](crate::TableCollection)
 /// to ``tskit`` format.
 ///
@@ -125,88 +147,139 @@ fn swap_with_empty<T>(v: &mut Vec<T>) {
 ///
 /// # Notes
 ///
-/// If the input ``tables`` are not sorted, pass ``false`` for
-/// `build_indexes`.
+/// This function is equivalent to [``export_to_tskit_from``]
+/// with the corresponding [``TskitExportOptions``].
+///
+/// # Errors
+///
+/// See [``export_to_tskit``].
+/// If `is_sample` is invalid, `tables` are unchanged.
+#[deprecated(since = "0.2.0", note = "use export_to_tskit_from instead")]
+pub fn convert_to_tskit_and_drain_minimal(
+    is_sample: &[i32],
+    convert_time: impl Fn(Time) -> f64,
+    build_indexes: bool,
+    tables: &mut TableCollection,
+) -> Result<tskit_rust::TableCollection, ForrusttsError> {
+    let options = TskitExportOptions::new()
+        .sample_nodes(export::sample_nodes_from_is_sample(tables, is_sample)?)
+        .build_indexes(build_indexes);
+    let tables = std::mem::replace(tables, TableCollection::new(tables.genome_length())?);
+    export::export_impl(export::ExportSource::Owned(tables), &convert_time, &options)
+}
+
+#[cfg(feature = "tskit_rust")]
+/// Convert ``tskit`` tables into a [``TableCollection``](crate::TableCollection).
+///
+/// This allows a forward simulation to start from a
+/// history generated elsewhere, *e.g.* by a coalescent simulation.
+///
+/// # Parameters
 ///
-/// This function will not be part of the long-term API.
-/// Rather, it is the minimum currently needed to get stuff done.
+/// * `tsk_tables`: A [``tskit_rust::TableCollection``].
+/// * `convert_time`: A callback to convert time from backwards to forwards. For example, see [``simple_time_forwarder``](crate::tskit::simple_time_forwarder).
+/// * `convert_position`: A callback to convert a genomic position to a [``Position``](crate::Position). This callback is also applied to the sequence length.
 ///
 /// # Returns
 ///
-/// A [``tskit_rust::TableCollection``].
+/// A tuple containing the [``TableCollection``](crate::TableCollection)
+/// and a [``SamplesInfo``](crate::SamplesInfo).
+/// The node and edge tables are copied.
+/// Node populations become demes.
+/// Node flags become [``NodeFlags``](crate::NodeFlags),
+/// dropping any bits not defined by that type.
+/// The edge table is sorted, so that the tables are
+/// ready for [``simplify_tables``](crate::simplify_tables).
+/// All nodes flagged as samples are recorded in
+/// [``SamplesInfo::samples``](crate::SamplesInfo::samples)
+/// and in [``SamplesInfo::edge_buffer_founder_nodes``](crate::SamplesInfo::edge_buffer_founder_nodes).
+///
+/// # Notes
+///
+/// Sites and mutations are not imported.
+///
+/// # Errors
+///
+/// Will return [``ForrusttsError``](crate::ForrusttsError) if
+/// any converted row is invalid or if the converted edge table
+/// fails validation. For example, nodes with no population
+/// are invalid, as are edges whose converted left and right
+/// positions are the same, and edges whose converted parent
+/// and child times are the same.
 ///
 /// # Example
 ///
 /// ```
-/// let mut tables = forrustts::TableCollection::new(100).unwrap();
-/// tables.add_node(0, 0).unwrap(); // Add a parent node at time 0
-/// tables.add_node(1, 0).unwrap(); // Add a child node at time 1
-/// tables.add_edge(0, 100, 0, 1).unwrap(); // Add an edge
-/// let is_sample = vec![0, 1]; // Mark the child node as a sample.
-/// let tsk_tables = forrustts::tskit::convert_to_tskit_and_drain_minimal(
-///     &is_sample,
-///     forrustts::tskit::simple_time_reverser(1),
-///     true,
-///     &mut tables,
-/// );
-/// assert_eq!(tsk_tables.nodes().num_rows(), 2);
-/// assert_eq!(tsk_tables.edges().num_rows(), 1);
-/// assert_eq!(tsk_tables.populations().num_rows(), 1);
-/// // The input tables have no data:
-/// assert_eq!(tables.num_nodes(), 0);
-/// assert_eq!(tables.num_edges(), 0);
-/// // The memory has been returned to the system,
-/// // and the internal containers have no allocated
-/// // capacity:
-/// assert_eq!(tables.nodes().capacity(), 0);
-/// assert_eq!(tables.edges().capacity(), 0);
+/// let mut tsk_tables = tskit_rust::TableCollection::new(100.).unwrap();
+/// tsk_tables.add_node(0, 1.0, 0, tskit_rust::TSK_NULL).unwrap();
+/// tsk_tables
+///     .add_node(tskit_rust::TSK_NODE_IS_SAMPLE, 0.0, 0, tskit_rust::TSK_NULL)
+///     .unwrap();
+/// tsk_tables.add_edge(0., 100., 0, 1).unwrap();
+/// let (tables, samples) = forrustts::tskit::convert_from_tskit(
+///     &tsk_tables,
+///     forrustts::tskit::simple_time_forwarder(1),
+///     |x| x as forrustts::Position,
+/// )
+/// .unwrap();
+/// assert_eq!(tables.genome_length(), 100);
+/// assert_eq!(tables.num_nodes(), 2);
+/// assert_eq!(tables.node(0).time, 0);
+/// assert_eq!(tables.node(1).time, 1);
+/// assert_eq!(tables.num_edges(), 1);
+/// assert_eq!(samples.samples, vec![1]);
+/// assert!(tables.node(1).flags.is_sample());
 /// ```
-pub fn convert_to_tskit_and_drain_minimal(
-    is_sample: &[i32],
-    convert_time: impl Fn(Time) -> f64,
-    build_indexes: bool,
-    tables: &mut TableCollection,
-) -> tskit_rust::TableCollection {
-    let mut tsk_tables = tskit_rust::TableCollection::new(tables.genome_length() as f64).unwrap();
-
-    let mut max_pop: tsk_id_t = -1;
-    for (i, n) in tables.enumerate_nodes() {
-        let flags: tsk_flags_t = if is_sample[i] > 0 {
-            TSK_NODE_IS_SAMPLE
-        } else {
-            0
-        };
-        tsk_tables
-            .add_node(flags, convert_time(n.time), n.deme, TSK_NULL)
-            .unwrap();
-        max_pop = std::cmp::max(n.deme, max_pop);
-    }
-    swap_with_empty(&mut tables.nodes_);
-
-    // Edges take the most memory,
-    // so we clear out the other tables first.
-    for e in tables.edges() {
-        tsk_tables
-            .add_edge(e.left as f64, e.right as f64, e.parent, e.child)
-            .unwrap();
-    }
-    swap_with_empty(&mut tables.edges_);
+pub fn convert_from_tskit(
+    tsk_tables: &tskit_rust::TableCollection,
+    convert_time: impl Fn(f64) -> Time,
+    convert_position: impl Fn(f64) -> Position,
+) -> Result<(TableCollection, SamplesInfo), ForrusttsError> {
+    let mut tables = TableCollection::new(convert_position(tsk_tables.sequence_length()))?;
+    let mut samples = SamplesInfo::new();
 
-    for _ in 0..(max_pop + 1) {
-        tsk_tables.add_population().unwrap();
+    let nodes = tsk_tables.nodes();
+    for i in 0..nodes.num_rows() as IdType {
+        let flags = NodeFlags::from_bits_truncate(nodes.flags(i)?);
+        let id = tables.add_node_with_flags(
+            convert_time(nodes.time(i)?),
+            nodes.population(i)?,
+            flags,
+        )?;
+        if flags.is_sample() {
+            samples.samples.push(id);
+        }
     }
+    samples.edge_buffer_founder_nodes = samples.samples.clone();
 
-    if build_indexes {
-        tsk_tables.build_index(0).unwrap();
+    let edges = tsk_tables.edges();
+    for i in 0..edges.num_rows() as IdType {
+        let (parent, child) = (edges.parent(i)?, edges.child(i)?);
+        for node in &[parent, child] {
+            if *node < 0 || *node as usize >= tables.num_nodes() {
+                return Err(crate::TablesError::NodeOutOfBounds.into());
+            }
+        }
+        tables.add_edge(
+            convert_position(edges.left(i)?),
+            convert_position(edges.right(i)?),
+            parent,
+            child,
+        )?;
     }
+    tables.sort_tables(TableSortingFlags::empty());
+    tables.validate(crate::TableValidationFlags::empty())?;
 
-    tsk_tables
+    Ok((tables, samples))
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "tskit_rust"))]
+#[allow(deprecated)]
 mod tests {
 
     use super::*;
+    use tskit_rust::bindings as tskr;
+    use tskit_rust::{tsk_id_t, TSK_NODE_IS_SAMPLE, TSK_NULL};
 
     #[test]
     fn test_convert_to_tskit() {
@@ -216,7 +289,7 @@ mod tests {
         tables.add_edge(0, 100, 0, 1).unwrap(); // Add an edge
         let is_sample = vec![0, 1]; // Mark the child node as a sample.
         let tsk_tables =
-            convert_to_tskit_minimal(&tables, &is_sample, simple_time_reverser(1), true);
+            convert_to_tskit_minimal(&tables, &is_sample, simple_time_reverser(1), true).unwrap();
         assert_eq!(tsk_tables.nodes().num_rows(), 2);
         assert_eq!(tsk_tables.edges().num_rows(), 1);
         assert_eq!(tsk_tables.populations().num_rows(), 1);
@@ -236,11 +309,249 @@ mod tests {
             simple_time_reverser(1),
             true,
             &mut tables,
-        );
+        )
+        .unwrap();
         assert_eq!(tsk_tables.nodes().num_rows(), 2);
         assert_eq!(tsk_tables.edges().num_rows(), 1);
         assert_eq!(tsk_tables.populations().num_rows(), 1);
         assert_eq!(tables.edges_.capacity(), 0);
         assert_eq!(tables.nodes_.capacity(), 0);
     }
+
+    #[test]
+    fn test_convert_from_tskit_round_trip() {
+        use crate::wright_fisher::*;
+        let nsteps = 100;
+        let tables = neutral_wf(
+            PopulationParams::new(100, 10000, 5e-3, 0.0),
+            SimulationParams::new(Some(10), 54321, nsteps, SimulationFlags::empty()),
+        )
+        .unwrap();
+        let options = TskitExportOptions::new().convert_time(simple_time_reverser(nsteps));
+        let tsk_tables = export_to_tskit(&tables, &options).unwrap();
+
+        let (imported, samples) =
+            convert_from_tskit(&tsk_tables, simple_time_forwarder(nsteps), |x| {
+                x as Position
+            })
+            .unwrap();
+        let diff = imported.diff(&tables);
+        assert!(diff.is_empty(), "{:?}", diff);
+        let expected_samples = tables.sample_nodes();
+        assert_eq!(samples.samples, expected_samples);
+        assert_eq!(samples.edge_buffer_founder_nodes, expected_samples);
+    }
+
+    #[test]
+    fn test_simplify_imported_tables() {
+        // A tree with three samples, imported in tskit's edge order.
+        let mut tsk_tables = tskit_rust::TableCollection::new(10.).unwrap();
+        for _ in 0..3 {
+            tsk_tables
+                .add_node(TSK_NODE_IS_SAMPLE, 0.0, 0, TSK_NULL)
+                .unwrap();
+        }
+        tsk_tables.add_node(0, 1.0, 0, TSK_NULL).unwrap();
+        tsk_tables.add_node(0, 2.0, 0, TSK_NULL).unwrap();
+        tsk_tables.add_edge(0., 10., 3, 0).unwrap();
+        tsk_tables.add_edge(0., 10., 3, 1).unwrap();
+        tsk_tables.add_edge(0., 10., 4, 2).unwrap();
+        tsk_tables.add_edge(0., 10., 4, 3).unwrap();
+
+        let (mut tables, samples) =
+            convert_from_tskit(&tsk_tables, simple_time_forwarder(2), |x| x as Position).unwrap();
+        assert_eq!(tables.edge(0).parent, 3);
+        let mut output = crate::SimplificationOutput::new();
+        crate::simplify_tables_without_state(
+            &samples,
+            crate::SimplificationFlags::VALIDATE_ALL,
+            &mut tables,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(tables.num_nodes(), 5);
+        assert_eq!(tables.num_edges(), 4);
+    }
+
+    #[test]
+    fn test_convert_from_tskit_errors() {
+        let mut tsk_tables = tskit_rust::TableCollection::new(10.).unwrap();
+        tsk_tables
+            .add_node(TSK_NODE_IS_SAMPLE, 0.0, TSK_NULL, TSK_NULL)
+            .unwrap();
+        assert!(
+            convert_from_tskit(&tsk_tables, simple_time_forwarder(0), |x| x as Position).is_err()
+        );
+
+        // Times that are equal after conversion
+        let mut tsk_tables = tskit_rust::TableCollection::new(10.).unwrap();
+        tsk_tables
+            .add_node(TSK_NODE_IS_SAMPLE, 0.0, 0, TSK_NULL)
+            .unwrap();
+        tsk_tables.add_node(0, 0.25, 0, TSK_NULL).unwrap();
+        tsk_tables.add_edge(0., 10., 1, 0).unwrap();
+        match convert_from_tskit(&tsk_tables, simple_time_forwarder(1), |x| x as Position) {
+            Err(ForrusttsError::TablesError { value }) => {
+                assert_eq!(value, crate::TablesError::NodeTimesUnordered)
+            }
+            _ => panic!("expected NodeTimesUnordered"),
+        }
+    }
+
+    fn make_tables_with_mutations() -> (TableCollection, Vec<i32>) {
+        let mut tables = TableCollection::new(100).unwrap();
+        tables.add_node(0, 0).unwrap();
+        tables.add_node(1, 0).unwrap();
+        tables.add_node(2, 0).unwrap();
+        tables.add_node(2, 0).unwrap();
+        tables.add_edge(0, 100, 0, 1).unwrap();
+        tables.add_edge(0, 100, 1, 2).unwrap();
+        tables.add_edge(0, 100, 1, 3).unwrap();
+        tables.sort_tables(crate::TableSortingFlags::empty());
+        tables.add_site(50, Some(b"A".to_vec())).unwrap();
+        tables.add_site(10, None).unwrap();
+        tables
+            .add_mutation(2, 6, 0, Some(b"T".to_vec()), false)
+            .unwrap();
+        tables
+            .add_mutation(1, 5, 0, Some(b"G".to_vec()), true)
+            .unwrap();
+        tables
+            .add_mutation(3, 7, 1, Some(b"C".to_vec()), true)
+            .unwrap();
+        (tables, vec![0, 0, 1, 1])
+    }
+
+    fn mutation_metadata(tsk_tables: &tskit_rust::TableCollection, row: usize) -> (u64, bool) {
+        let mutations = unsafe { &(*tsk_tables.as_ptr()).mutations };
+        let md = unsafe {
+            let start = *mutations.metadata_offset.add(row) as usize;
+            let stop = *mutations.metadata_offset.add(row + 1) as usize;
+            std::slice::from_raw_parts(mutations.metadata.add(start) as *const u8, stop - start)
+        };
+        assert_eq!(md.len(), 9);
+        let mut key = [0_u8; 8];
+        key.copy_from_slice(&md[0..8]);
+        (u64::from_le_bytes(key), md[8] == 1)
+    }
+
+    fn validate_exported_mutations(tsk_tables: &tskit_rust::TableCollection) {
+        let sites = tsk_tables.sites();
+        assert_eq!(sites.num_rows(), 2);
+        assert_eq!(sites.position(0).unwrap(), 10.);
+        assert_eq!(sites.ancestral_state(0).unwrap(), None);
+        assert_eq!(sites.position(1).unwrap(), 50.);
+        assert_eq!(sites.ancestral_state(1).unwrap(), Some(b"A".to_vec()));
+
+        let mutations = tsk_tables.mutations();
+        assert_eq!(mutations.num_rows(), 3);
+        let expected = [
+            (0, 3, TSK_NULL, 0., b"C", 7, true),
+            (1, 1, TSK_NULL, 1., b"G", 5, true),
+            (1, 2, 1, 0., b"T", 6, false),
+        ];
+        for (i, e) in expected.iter().enumerate() {
+            let row = i as tsk_id_t;
+            assert_eq!(mutations.site(row).unwrap(), e.0);
+            assert_eq!(mutations.node(row).unwrap(), e.1);
+            assert_eq!(mutations.parent(row).unwrap(), e.2);
+            assert_eq!(mutations.time(row).unwrap(), e.3);
+            assert_eq!(mutations.derived_state(row).unwrap(), Some(e.4.to_vec()));
+            assert_eq!(mutation_metadata(tsk_tables, i), (e.5, e.6));
+        }
+
+        let rv = unsafe {
+            tskr::tsk_table_collection_check_integrity(tsk_tables.as_ptr(), tskr::TSK_CHECK_TREES)
+        };
+        assert!(rv >= 0);
+    }
+
+    #[test]
+    fn test_convert_sites_and_mutations() {
+        let (tables, is_sample) = make_tables_with_mutations();
+        let tsk_tables =
+            convert_to_tskit_minimal(&tables, &is_sample, simple_time_reverser(2), true).unwrap();
+        validate_exported_mutations(&tsk_tables);
+        assert_eq!(tables.sites().len(), 2);
+        assert_eq!(tables.mutations().len(), 3);
+    }
+
+    #[test]
+    fn test_convert_sites_and_mutations_and_drain() {
+        let (mut tables, is_sample) = make_tables_with_mutations();
+        let tsk_tables = convert_to_tskit_and_drain_minimal(
+            &is_sample,
+            simple_time_reverser(2),
+            true,
+            &mut tables,
+        )
+        .unwrap();
+        validate_exported_mutations(&tsk_tables);
+        assert_eq!(tables.sites_.capacity(), 0);
+        assert_eq!(tables.mutations_.capacity(), 0);
+    }
+
+    #[test]
+    fn test_convert_mutations_without_indexes() {
+        let (tables, is_sample) = make_tables_with_mutations();
+        let tsk_tables =
+            convert_to_tskit_minimal(&tables, &is_sample, simple_time_reverser(2), false).unwrap();
+        // Without indexes, mutation parents are not computed.
+        let mutations = tsk_tables.mutations();
+        assert_eq!(mutations.num_rows(), 3);
+        for row in 0..3 {
+            assert_eq!(mutations.parent(row).unwrap(), TSK_NULL);
+        }
+        assert_eq!(mutations.node(1).unwrap(), 1);
+        assert_eq!(mutations.node(2).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_convert_with_bad_is_sample() {
+        let (mut tables, _) = make_tables_with_mutations();
+        let is_sample = vec![0, 1];
+        for rv in [
+            convert_to_tskit_minimal(&tables, &is_sample, simple_time_reverser(2), true),
+            convert_to_tskit_and_drain_minimal(
+                &is_sample,
+                simple_time_reverser(2),
+                true,
+                &mut tables,
+            ),
+        ]
+        .iter()
+        {
+            match rv {
+                Err(ForrusttsError::TskitError { code, message: _ }) => {
+                    assert_eq!(*code, tskr::TSK_ERR_BAD_PARAM_VALUE)
+                }
+                _ => panic!("expected TskitError"),
+            }
+        }
+        // The check happens before draining
+        assert_eq!(tables.num_nodes(), 4);
+        assert_eq!(tables.num_edges(), 3);
+    }
+
+    #[test]
+    fn test_convert_unsorted_tables_with_indexes() {
+        let mut tables = TableCollection::new(100).unwrap();
+        tables.add_node(0, 0).unwrap();
+        tables.add_node(1, 0).unwrap();
+        tables.add_node(2, 0).unwrap();
+        // Parent times are not sorted
+        tables.add_edge(0, 100, 0, 1).unwrap();
+        tables.add_edge(0, 100, 1, 2).unwrap();
+        let is_sample = vec![0, 0, 1];
+        match convert_to_tskit_minimal(&tables, &is_sample, simple_time_reverser(2), true) {
+            Err(ForrusttsError::TskitError { code, message }) => {
+                assert!(code < 0);
+                assert_eq!(message, tskit_rust::error::get_tskit_error_message(code));
+            }
+            _ => panic!("expected TskitError"),
+        }
+        assert!(
+            convert_to_tskit_minimal(&tables, &is_sample, simple_time_reverser(2), false).is_ok()
+        );
+    }
 }
@@ -0,0 +1,877 @@
+//! Export of a [``TableCollection``] to ``tskit`` format.
+
+use super::{encode_mutation_metadata, MUTATION_METADATA_SCHEMA};
+use crate::tsdef::{IdType, Time};
+use crate::{ForrusttsError, TableCollection};
+use bitflags::bitflags;
+use std::os::raw::c_char;
+use tskit_rust::bindings as tskr;
+use tskit_rust::{tsk_flags_t, tsk_id_t, tsk_size_t, TSK_NODE_IS_SAMPLE, TSK_NULL};
+
+/// The ``tskit`` metadata schema for populations.
+const POPULATION_METADATA_SCHEMA: &str = r#"{"codec":"json","type":"object","properties":{"name":{"type":"string"}},"required":["name"],"additionalProperties":true}"#;
+
+bitflags! {
+    /// Modifies the behavior of [``export_to_tskit``]
+    /// and [``export_to_tskit_from``].
+    ///
+    /// The default (empty) is to export all tables.
+    ///
+    /// ```
+    /// let f = forrustts::tskit::TskitExportFlags::default();
+    /// assert!(f.is_empty());
+    /// ```
+    #[derive(Default)]
+    pub struct TskitExportFlags: u32 {
+        /// Do not export the edge table.
+        const SKIP_EDGE_TABLE = 1 << 0;
+        /// Do not export the site table.
+        /// Because mutations refer to sites,
+        /// this also skips the mutation table.
+        const SKIP_SITE_TABLE = 1 << 1;
+        /// Do not export the mutation table.
+        const SKIP_MUTATION_TABLE = 1 << 2;
+    }
+}
+
+/// Options for [``export_to_tskit``] and [``export_to_tskit_from``].
+///
+/// This type is a builder: each method consumes and returns
+/// the options.
+///
+/// # Defaults
+///
+/// * Nodes with [``NodeFlags::IS_SAMPLE``](crate::NodeFlags::IS_SAMPLE) set are samples.
+/// * Time is converted using [``simple_time_reverser``](crate::tskit::simple_time_reverser),
+///   with the largest node time as the present.
+/// * Edge table indexes are built.
+/// * Populations have no metadata.
+/// * No provenance record is added.
+/// * All tables are exported.
+///
+/// # Example
+///
+/// ```
+/// use forrustts::tskit::{TskitExportFlags, TskitExportOptions};
+///
+/// let options = TskitExportOptions::new()
+///     .sample_nodes(vec![1, 2])
+///     .convert_time(forrustts::tskit::simple_time_reverser(1))
+///     .build_indexes(true)
+///     .population_names(vec!["deme0".to_string()])
+///     .include_provenance(true)
+///     .flags(TskitExportFlags::SKIP_MUTATION_TABLE);
+/// ```
+pub struct TskitExportOptions {
+    sample_nodes: Option<Vec<IdType>>,
+    convert_time: Option<Box<dyn Fn(Time) -> f64>>,
+    build_indexes: bool,
+    population_names: Option<Vec<String>>,
+    include_provenance: bool,
+    flags: TskitExportFlags,
+}
+
+impl Default for TskitExportOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TskitExportOptions {
+    /// Create options with default values.
+    pub fn new() -> Self {
+        TskitExportOptions {
+            sample_nodes: None,
+            convert_time: None,
+            build_indexes: true,
+            population_names: None,
+            include_provenance: false,
+            flags: TskitExportFlags::empty(),
+        }
+    }
+
+    /// Set which nodes are samples.
+    ///
+    /// Exactly the nodes in `samples` are exported as samples,
+    /// and the [``NodeFlags::IS_SAMPLE``](crate::NodeFlags::IS_SAMPLE)
+    /// flag of each node is ignored.
+    /// All other node flags are exported unchanged.
+    pub fn sample_nodes(mut self, samples: Vec<IdType>) -> Self {
+        self.sample_nodes = Some(samples);
+        self
+    }
+
+    /// Set the callback used to convert time, *e.g.* from forwards
+    /// to backwards.
+    pub fn convert_time(mut self, convert_time: impl Fn(Time) -> f64 + 'static) -> Self {
+        self.convert_time = Some(Box::new(convert_time));
+        self
+    }
+
+    /// Set whether to build the edge table indexes.
+    ///
+    /// If the tables are not sorted, this must be `false`.
+    /// Mutation parents are only computed if the indexes
+    /// are built.
+    pub fn build_indexes(mut self, build_indexes: bool) -> Self {
+        self.build_indexes = build_indexes;
+        self
+    }
+
+    /// Name the populations.
+    ///
+    /// The name of population `i` is `names[i]`.
+    /// Names are stored as ``tskit`` population metadata,
+    /// using the ``json`` codec.
+    /// There must be at least one name per deme.
+    /// Extra names add populations that contain no nodes.
+    pub fn population_names(mut self, names: Vec<String>) -> Self {
+        self.population_names = Some(names);
+        self
+    }
+
+    /// Set whether to add a record to the ``tskit`` provenance table.
+    pub fn include_provenance(mut self, include_provenance: bool) -> Self {
+        self.include_provenance = include_provenance;
+        self
+    }
+
+    /// Set which tables to export.
+    /// See [``TskitExportFlags``].
+    pub fn flags(mut self, flags: TskitExportFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+}
+
+impl From<tskit_rust::TskitRustError> for ForrusttsError {
+    fn from(e: tskit_rust::TskitRustError) -> Self {
+        match e {
+            tskit_rust::TskitRustError::ErrorCode { code } => tskit_error(code),
+            _ => ForrusttsError::TskitError {
+                code: tskr::TSK_ERR_GENERIC,
+                message: e.to_string(),
+            },
+        }
+    }
+}
+
+fn tskit_error(code: i32) -> ForrusttsError {
+    ForrusttsError::TskitError {
+        code,
+        message: tskit_rust::error::get_tskit_error_message(code),
+    }
+}
+
+fn bad_param_error(message: String) -> ForrusttsError {
+    ForrusttsError::TskitError {
+        code: tskr::TSK_ERR_BAD_PARAM_VALUE,
+        message,
+    }
+}
+
+/// Convert the return value of a ``tskit`` C function.
+fn handle_tsk_return_value(rv: i32) -> Result<i32, ForrusttsError> {
+    if rv < 0 {
+        Err(tskit_error(rv))
+    } else {
+        Ok(rv)
+    }
+}
+
+/// Convert a `0/1` array of sample flags into the
+/// sample nodes.
+pub(super) fn sample_nodes_from_is_sample(
+    tables: &TableCollection,
+    is_sample: &[i32],
+) -> Result<Vec<IdType>, ForrusttsError> {
+    if is_sample.len() != tables.num_nodes() {
+        return Err(bad_param_error(format!(
+            "is_sample has length {} but there are {} nodes",
+            is_sample.len(),
+            tables.num_nodes()
+        )));
+    }
+    Ok(is_sample
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| **s > 0)
+        .map(|(i, _)| i as IdType)
+        .collect())
+}
+
+/// Return whether each node is a sample,
+/// or `None` if the node flags are used.
+fn sample_mask(
+    tables: &TableCollection,
+    options: &TskitExportOptions,
+) -> Result<Option<Vec<bool>>, ForrusttsError> {
+    let samples = match &options.sample_nodes {
+        Some(samples) => samples,
+        None => return Ok(None),
+    };
+    let mut is_sample = vec![false; tables.num_nodes()];
+    for &s in samples {
+        if s < 0 || s as usize >= is_sample.len() {
+            return Err(bad_param_error(format!(
+                "sample node {} is out of range",
+                s
+            )));
+        }
+        is_sample[s as usize] = true;
+    }
+    Ok(Some(is_sample))
+}
+
+fn count_populations(
+    tables: &TableCollection,
+    options: &TskitExportOptions,
+) -> Result<usize, ForrusttsError> {
+    let max_pop: tsk_id_t = tables
+        .nodes()
+        .iter()
+        .fold(-1, |m, n| std::cmp::max(m, n.deme));
+    let num_demes = (max_pop + 1) as usize;
+    match &options.population_names {
+        Some(names) => {
+            if names.len() < num_demes {
+                return Err(bad_param_error(format!(
+                    "{} population names given but there are {} demes",
+                    names.len(),
+                    num_demes
+                )));
+            }
+            Ok(names.len())
+        }
+        None => Ok(num_demes),
+    }
+}
+
+fn bytes_as_ptr(bytes: &[u8]) -> (*const c_char, tsk_size_t) {
+    (bytes.as_ptr() as *const c_char, bytes.len() as tsk_size_t)
+}
+
+fn state_as_ptr(state: &Option<Vec<u8>>) -> (*const c_char, tsk_size_t) {
+    match state {
+        Some(x) => bytes_as_ptr(x),
+        None => (std::ptr::null(), 0),
+    }
+}
+
+fn add_nodes(
+    tables: &TableCollection,
+    is_sample: Option<&[bool]>,
+    convert_time: &dyn Fn(Time) -> f64,
+    tsk_tables: &mut tskit_rust::TableCollection,
+) -> Result<(), ForrusttsError> {
+    for (i, n) in tables.enumerate_nodes() {
+        let flags: tsk_flags_t = match is_sample {
+            Some(s) => {
+                let sample = if s[i] { TSK_NODE_IS_SAMPLE } else { 0 };
+                (n.flags.bits() & !TSK_NODE_IS_SAMPLE) | sample
+            }
+            None => n.flags.bits(),
+        };
+        tsk_tables.add_node(flags, convert_time(n.time), n.deme, TSK_NULL)?;
+    }
+    Ok(())
+}
+
+fn add_edges(
+    tables: &TableCollection,
+    tsk_tables: &mut tskit_rust::TableCollection,
+) -> Result<(), ForrusttsError> {
+    for e in tables.edges() {
+        tsk_tables.add_edge(e.left as f64, e.right as f64, e.parent, e.child)?;
+    }
+    Ok(())
+}
+
+fn add_sites(
+    tables: &TableCollection,
+    tsk_tables: &mut tskit_rust::TableCollection,
+) -> Result<(), ForrusttsError> {
+    let tsk = unsafe { &mut *tsk_tables.as_mut_ptr() };
+    for s in tables.sites() {
+        let (state, state_length) = state_as_ptr(&s.ancestral_state);
+        let rv = unsafe {
+            tskr::tsk_site_table_add_row(
+                &mut tsk.sites,
+                s.position as f64,
+                state,
+                state_length,
+                std::ptr::null(),
+                0,
+            )
+        };
+        handle_tsk_return_value(rv)?;
+    }
+    Ok(())
+}
+
+/// Add the mutation table.
+///
+/// Must be called after the node table has been
+/// added to `tsk_tables`, as mutation times are
+/// the converted node times.
+fn add_mutations(
+    tables: &TableCollection,
+    tsk_tables: &mut tskit_rust::TableCollection,
+) -> Result<(), ForrusttsError> {
+    let tsk = unsafe { &mut *tsk_tables.as_mut_ptr() };
+    let (schema, schema_length) = bytes_as_ptr(MUTATION_METADATA_SCHEMA.as_bytes());
+    let rv = unsafe {
+        tskr::tsk_mutation_table_set_metadata_schema(&mut tsk.mutations, schema, schema_length)
+    };
+    handle_tsk_return_value(rv)?;
+    for m in tables.mutations() {
+        if m.node < 0 || m.node as tsk_size_t >= tsk.nodes.num_rows {
+            return Err(tskit_error(tskr::TSK_ERR_NODE_OUT_OF_BOUNDS));
+        }
+        let (state, state_length) = state_as_ptr(&m.derived_state);
+        let metadata = encode_mutation_metadata(m);
+        let (metadata_ptr, metadata_length) = bytes_as_ptr(&metadata);
+        let time = unsafe { *tsk.nodes.time.add(m.node as usize) };
+        let rv = unsafe {
+            tskr::tsk_mutation_table_add_row(
+                &mut tsk.mutations,
+                m.site as tsk_id_t,
+                m.node,
+                TSK_NULL,
+                time,
+                state,
+                state_length,
+                metadata_ptr,
+                metadata_length,
+            )
+        };
+        handle_tsk_return_value(rv)?;
+    }
+    Ok(())
+}
+
+/// Quote a string for use in JSON.
+fn json_string(s: &str) -> String {
+    let mut rv = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => rv.push_str("\\\""),
+            '\\' => rv.push_str("\\\\"),
+            '\n' => rv.push_str("\\n"),
+            '\r' => rv.push_str("\\r"),
+            '\t' => rv.push_str("\\t"),
+            c if (c as u32) < 0x20 => rv.push_str(&format!("\\u{:04x}", c as u32)),
+            c => rv.push(c),
+        }
+    }
+    rv.push('"');
+    rv
+}
+
+fn add_populations(
+    num_populations: usize,
+    names: Option<&[String]>,
+    tsk_tables: &mut tskit_rust::TableCollection,
+) -> Result<(), ForrusttsError> {
+    let names = match names {
+        Some(names) => names,
+        None => {
+            for _ in 0..num_populations {
+                tsk_tables.add_population()?;
+            }
+            return Ok(());
+        }
+    };
+
+    let tsk = unsafe { &mut *tsk_tables.as_mut_ptr() };
+    let (schema, schema_length) = bytes_as_ptr(POPULATION_METADATA_SCHEMA.as_bytes());
+    let rv = unsafe {
+        tskr::tsk_population_table_set_metadata_schema(&mut tsk.populations, schema, schema_length)
+    };
+    handle_tsk_return_value(rv)?;
+    for name in names {
+        let metadata = format!("{{\"name\":{}}}", json_string(name));
+        let (metadata_ptr, metadata_length) = bytes_as_ptr(metadata.as_bytes());
+        let rv = unsafe {
+            tskr::tsk_population_table_add_row(&mut tsk.populations, metadata_ptr, metadata_length)
+        };
+        handle_tsk_return_value(rv)?;
+    }
+    Ok(())
+}
+
+/// The current UTC time in ISO 8601 format.
+fn timestamp() -> String {
+    let seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let (days, seconds_of_day) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+
+    // Convert days since the epoch to a civil date.
+    // See http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        (seconds_of_day % 3600) / 60,
+        seconds_of_day % 60
+    )
+}
+
+fn add_provenance(
+    options: &TskitExportOptions,
+    tsk_tables: &mut tskit_rust::TableCollection,
+) -> Result<(), ForrusttsError> {
+    let record = format!(
+        "{{\"schema_version\":\"1.0.0\",\"software\":{{\"name\":\"forrustts\",\"version\":{}}},\"parameters\":{{\"command\":\"export_to_tskit\",\"build_indexes\":{},\"flags\":{}}},\"environment\":{{\"os\":{{\"system\":{}}}}}}}",
+        json_string(crate::version()),
+        options.build_indexes,
+        options.flags.bits(),
+        json_string(std::env::consts::OS)
+    );
+    let timestamp = timestamp();
+    let tsk = unsafe { &mut *tsk_tables.as_mut_ptr() };
+    let (timestamp_ptr, timestamp_length) = bytes_as_ptr(timestamp.as_bytes());
+    let (record_ptr, record_length) = bytes_as_ptr(record.as_bytes());
+    let rv = unsafe {
+        tskr::tsk_provenance_table_add_row(
+            &mut tsk.provenances,
+            timestamp_ptr,
+            timestamp_length,
+            record_ptr,
+            record_length,
+        )
+    };
+    handle_tsk_return_value(rv)?;
+    Ok(())
+}
+
+/// Sort the site and mutation tables, and, if requested,
+/// build the indexes and compute mutation parents.
+fn finalize_tables(
+    tsk_tables: &mut tskit_rust::TableCollection,
+    build_indexes: bool,
+) -> Result<(), ForrusttsError> {
+    if tsk_tables.sites().num_rows() > 0 {
+        // Sort sites and mutations but leave the edges alone.
+        let mut bookmark = tskit_rust::types::Bookmark::new();
+        bookmark.offsets.edges = tsk_tables.edges().num_rows();
+        tsk_tables.sort(&bookmark, 0)?;
+    }
+
+    if build_indexes {
+        tsk_tables.build_index(0)?;
+        if tsk_tables.mutations().num_rows() > 0 {
+            let rv = unsafe {
+                tskr::tsk_table_collection_compute_mutation_parents(tsk_tables.as_mut_ptr(), 0)
+            };
+            handle_tsk_return_value(rv)?;
+        }
+    }
+    Ok(())
+}
+
+fn export_sites(options: &TskitExportOptions) -> bool {
+    !options.flags.contains(TskitExportFlags::SKIP_SITE_TABLE)
+}
+
+fn export_mutations(options: &TskitExportOptions) -> bool {
+    export_sites(options)
+        && !options
+            .flags
+            .contains(TskitExportFlags::SKIP_MUTATION_TABLE)
+}
+
+fn max_node_time(tables: &TableCollection) -> Time {
+    tables.nodes().iter().map(|n| n.time).max().unwrap_or(0)
+}
+
+/// The time conversion callback of `options`, or the
+/// default if there is none.
+fn time_converter<'a>(
+    tables: &TableCollection,
+    options: &'a TskitExportOptions,
+) -> Box<dyn Fn(Time) -> f64 + 'a> {
+    match &options.convert_time {
+        Some(f) => Box::new(f),
+        None => super::simple_time_reverser(max_node_time(tables)),
+    }
+}
+
+/// The tables to export.
+///
+/// Owned tables have their data freed as soon
+/// as it has been exported.
+pub(super) enum ExportSource<'a> {
+    Borrowed(&'a TableCollection),
+    Owned(TableCollection),
+}
+
+impl ExportSource<'_> {
+    fn tables(&self) -> &TableCollection {
+        match self {
+            ExportSource::Borrowed(tables) => tables,
+            ExportSource::Owned(tables) => tables,
+        }
+    }
+
+    /// Apply `f` to owned tables.
+    fn drain(&mut self, f: impl FnOnce(&mut TableCollection)) {
+        if let ExportSource::Owned(tables) = self {
+            f(tables)
+        }
+    }
+}
+
+fn swap_with_empty<T>(v: &mut Vec<T>) {
+    let mut temp = Vec::<T>::new();
+    std::mem::swap(v, &mut temp);
+}
+
+pub(super) fn export_impl(
+    mut source: ExportSource,
+    convert_time: &dyn Fn(Time) -> f64,
+    options: &TskitExportOptions,
+) -> Result<tskit_rust::TableCollection, ForrusttsError> {
+    let is_sample = sample_mask(source.tables(), options)?;
+    let num_populations = count_populations(source.tables(), options)?;
+    let mut tsk_tables = tskit_rust::TableCollection::new(source.tables().genome_length() as f64)?;
+
+    add_nodes(
+        source.tables(),
+        is_sample.as_deref(),
+        convert_time,
+        &mut tsk_tables,
+    )?;
+    if export_sites(options) {
+        add_sites(source.tables(), &mut tsk_tables)?;
+    }
+    if export_mutations(options) {
+        add_mutations(source.tables(), &mut tsk_tables)?;
+    }
+
+    // Edges take the most memory,
+    // so we clear out the other tables first.
+    source.drain(|tables| {
+        swap_with_empty(&mut tables.nodes_);
+        swap_with_empty(&mut tables.sites_);
+        swap_with_empty(&mut tables.mutations_);
+    });
+    if !options.flags.contains(TskitExportFlags::SKIP_EDGE_TABLE) {
+        add_edges(source.tables(), &mut tsk_tables)?;
+    }
+    source.drain(|tables| {
+        swap_with_empty(&mut tables.edges_);
+        tables.drop_indexes();
+    });
+
+    add_populations(
+        num_populations,
+        options.population_names.as_deref(),
+        &mut tsk_tables,
+    )?;
+    if options.include_provenance {
+        add_provenance(options, &mut tsk_tables)?;
+    }
+    finalize_tables(&mut tsk_tables, options.build_indexes)?;
+
+    Ok(tsk_tables)
+}
+
+/// Export a [``TableCollection``](crate::TableCollection)
+/// to ``tskit`` format.
+///
+/// # Parameters
+///
+/// * `tables`: A [``TableCollection``](crate::TableCollection)
+/// * `options`: The [``TskitExportOptions``].
+///
+/// # Notes
+///
+/// Unless [``TskitExportOptions::sample_nodes``] is used,
+/// the [``NodeFlags``](crate::NodeFlags) of each node become its
+/// ``tskit`` node flags, so nodes with
+/// [``NodeFlags::IS_SAMPLE``](crate::NodeFlags::IS_SAMPLE) set are samples.
+///
+/// Sites and mutations are exported, and the ``tskit`` site and
+/// mutation tables are sorted.
+/// The time of a mutation is the converted time of its node.
+/// See the [module documentation](crate::tskit) for the
+/// format of mutation metadata.
+///
+/// To free the memory used by `tables` during the export,
+/// see [``export_to_tskit_from``].
+///
+/// # Errors
+///
+/// Will return [``ForrusttsError::TskitError``](crate::ForrusttsError::TskitError)
+/// if there are fewer population names than demes,
+/// if a sample node is out of range,
+/// or if any ``tskit`` function returns an error.
+///
+/// # Example
+///
+/// ```
+/// use forrustts::tskit::{export_to_tskit, TskitExportOptions};
+///
+/// let mut tables = forrustts::TableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap(); // Add a parent node at time 0
+/// // Add a child node at time 1 and mark it as a sample.
+/// tables
+///     .add_node_with_flags(1, 0, forrustts::NodeFlags::IS_SAMPLE)
+///     .unwrap();
+/// tables.add_edge(0, 100, 0, 1).unwrap(); // Add an edge
+/// let options = TskitExportOptions::new()
+///     .population_names(vec!["pop0".to_string()]);
+/// let tsk_tables = export_to_tskit(&tables, &options).unwrap();
+/// assert_eq!(tsk_tables.nodes().num_rows(), 2);
+/// assert_eq!(tsk_tables.nodes().time(1).unwrap(), 0.0);
+/// assert_eq!(tsk_tables.edges().num_rows(), 1);
+/// assert_eq!(tsk_tables.populations().num_rows(), 1);
+/// ```
+pub fn export_to_tskit(
+    tables: &TableCollection,
+    options: &TskitExportOptions,
+) -> Result<tskit_rust::TableCollection, ForrusttsError> {
+    let convert_time = time_converter(tables, options);
+    export_impl(ExportSource::Borrowed(tables), &convert_time, options)
+}
+
+/// Export a [``TableCollection``](crate::TableCollection)
+/// to ``tskit`` format, consuming the tables.
+///
+/// The memory of each table is freed once it has
+/// been exported, including tables that are not exported.
+/// The edge table, which is usually the largest, is
+/// exported last.
+///
+/// # Parameters
+///
+/// * `tables`: A [``TableCollection``](crate::TableCollection)
+/// * `options`: The [``TskitExportOptions``].
+///
+/// # Notes
+///
+/// See [``export_to_tskit``].
+///
+/// # Errors
+///
+/// See [``export_to_tskit``].
+///
+/// # Example
+///
+/// ```
+/// use forrustts::tskit::{export_to_tskit_from, TskitExportOptions};
+///
+/// let mut tables = forrustts::TableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap(); // Add a parent node at time 0
+/// tables
+///     .add_node_with_flags(1, 0, forrustts::NodeFlags::IS_SAMPLE)
+///     .unwrap();
+/// tables.add_edge(0, 100, 0, 1).unwrap(); // Add an edge
+/// let options = TskitExportOptions::new();
+/// let tsk_tables = export_to_tskit_from(tables, &options).unwrap();
+/// assert_eq!(tsk_tables.nodes().num_rows(), 2);
+/// assert_eq!(tsk_tables.edges().num_rows(), 1);
+/// ```
+pub fn export_to_tskit_from(
+    tables: TableCollection,
+    options: &TskitExportOptions,
+) -> Result<tskit_rust::TableCollection, ForrusttsError> {
+    let convert_time = time_converter(&tables, options);
+    export_impl(ExportSource::Owned(tables), &convert_time, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_tables() -> TableCollection {
+        let mut tables = TableCollection::new(100).unwrap();
+        tables.add_node(0, 0).unwrap();
+        tables.add_node(1, 1).unwrap();
+        tables.add_node(2, 0).unwrap();
+        tables.add_node(2, 1).unwrap();
+        tables.add_edge(0, 100, 0, 1).unwrap();
+        tables.add_edge(0, 100, 1, 2).unwrap();
+        tables.add_edge(0, 100, 1, 3).unwrap();
+        tables.sort_tables(crate::TableSortingFlags::empty());
+        tables.add_site(50, Some(b"A".to_vec())).unwrap();
+        tables.add_mutation(2, 0, 0, None, true).unwrap();
+        tables
+    }
+
+    fn population_metadata(tsk_tables: &tskit_rust::TableCollection, row: usize) -> String {
+        let populations = unsafe { &(*tsk_tables.as_ptr()).populations };
+        let md = unsafe {
+            let start = *populations.metadata_offset.add(row) as usize;
+            let stop = *populations.metadata_offset.add(row + 1) as usize;
+            std::slice::from_raw_parts(populations.metadata.add(start) as *const u8, stop - start)
+        };
+        String::from_utf8(md.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_default_options() {
+        let tables = make_tables();
+        let tsk_tables = export_to_tskit(&tables, &TskitExportOptions::default()).unwrap();
+        assert_eq!(tsk_tables.nodes().num_rows(), 4);
+        for i in 0..4 {
+            assert_eq!(tsk_tables.nodes().flags(i).unwrap(), 0);
+        }
+        // The youngest nodes are at time 0
+        assert_eq!(tsk_tables.nodes().time(0).unwrap(), 2.0);
+        assert_eq!(tsk_tables.nodes().time(2).unwrap(), 0.0);
+        assert_eq!(tsk_tables.edges().num_rows(), 3);
+        assert_eq!(tsk_tables.sites().num_rows(), 1);
+        assert_eq!(tsk_tables.mutations().num_rows(), 1);
+        assert_eq!(tsk_tables.populations().num_rows(), 2);
+        assert_eq!(population_metadata(&tsk_tables, 0), "");
+        let tsk = unsafe { &*tsk_tables.as_ptr() };
+        assert_eq!(tsk.provenances.num_rows, 0);
+        assert!(!tsk.indexes.edge_insertion_order.is_null());
+    }
+
+    #[test]
+    fn test_options() {
+        let mut tables = make_tables();
+        for node in 2..4 {
+            tables
+                .set_node_flags(node, crate::NodeFlags::IS_SAMPLE)
+                .unwrap();
+        }
+        let options = TskitExportOptions::new()
+            .convert_time(|t| (10 - t) as f64)
+            .build_indexes(false)
+            .population_names(vec![
+                "A".to_string(),
+                "B \"quoted\"".to_string(),
+                "C".to_string(),
+            ])
+            .include_provenance(true);
+        let tsk_tables = export_to_tskit(&tables, &options).unwrap();
+        assert_eq!(tsk_tables.nodes().flags(1).unwrap(), 0);
+        assert_eq!(tsk_tables.nodes().flags(2).unwrap(), TSK_NODE_IS_SAMPLE);
+        assert_eq!(tsk_tables.nodes().time(2).unwrap(), 8.0);
+        assert_eq!(tsk_tables.populations().num_rows(), 3);
+        assert_eq!(population_metadata(&tsk_tables, 0), r#"{"name":"A"}"#);
+        assert_eq!(
+            population_metadata(&tsk_tables, 1),
+            r#"{"name":"B \"quoted\""}"#
+        );
+        let tsk = unsafe { &*tsk_tables.as_ptr() };
+        assert!(tsk.indexes.edge_insertion_order.is_null());
+        assert_eq!(tsk.provenances.num_rows, 1);
+        let record = unsafe {
+            std::slice::from_raw_parts(
+                tsk.provenances.record as *const u8,
+                tsk.provenances.record_length as usize,
+            )
+        };
+        let record = String::from_utf8(record.to_vec()).unwrap();
+        assert!(record.contains(r#""name":"forrustts""#));
+        let timestamp_length = tsk.provenances.timestamp_length as usize;
+        assert_eq!(timestamp_length, "2021-01-01T00:00:00Z".len());
+    }
+
+    #[test]
+    fn test_node_flags() {
+        let mut tables = make_tables();
+        let user_flag = crate::NodeFlags::from_bits(1 << 16).unwrap();
+        let flags = crate::NodeFlags::IS_SAMPLE | crate::NodeFlags::IS_ALIVE | user_flag;
+        tables.set_node_flags(2, flags).unwrap();
+        tables
+            .set_node_flags(3, crate::NodeFlags::IS_PRESERVED)
+            .unwrap();
+        let tsk_tables = export_to_tskit(&tables, &TskitExportOptions::new()).unwrap();
+        assert_eq!(tsk_tables.nodes().flags(2).unwrap(), flags.bits());
+        assert_eq!(
+            tsk_tables.nodes().flags(3).unwrap(),
+            crate::NodeFlags::IS_PRESERVED.bits()
+        );
+
+        // Sample nodes given explicitly replace IS_SAMPLE
+        let options = TskitExportOptions::new().sample_nodes(vec![3]);
+        let tsk_tables = export_to_tskit(&tables, &options).unwrap();
+        assert_eq!(
+            tsk_tables.nodes().flags(2).unwrap(),
+            (flags & !crate::NodeFlags::IS_SAMPLE).bits()
+        );
+        assert_eq!(
+            tsk_tables.nodes().flags(3).unwrap(),
+            (crate::NodeFlags::IS_PRESERVED | crate::NodeFlags::IS_SAMPLE).bits()
+        );
+    }
+
+    #[test]
+    fn test_skip_tables() {
+        let tables = make_tables();
+        let options = TskitExportOptions::new().flags(TskitExportFlags::SKIP_MUTATION_TABLE);
+        let tsk_tables = export_to_tskit(&tables, &options).unwrap();
+        assert_eq!(tsk_tables.sites().num_rows(), 1);
+        assert_eq!(tsk_tables.mutations().num_rows(), 0);
+
+        let options = TskitExportOptions::new()
+            .flags(TskitExportFlags::SKIP_SITE_TABLE | TskitExportFlags::SKIP_EDGE_TABLE);
+        let tsk_tables = export_to_tskit(&tables, &options).unwrap();
+        assert_eq!(tsk_tables.nodes().num_rows(), 4);
+        assert_eq!(tsk_tables.edges().num_rows(), 0);
+        assert_eq!(tsk_tables.sites().num_rows(), 0);
+        assert_eq!(tsk_tables.mutations().num_rows(), 0);
+    }
+
+    #[test]
+    fn test_too_few_population_names() {
+        let tables = make_tables();
+        let options = TskitExportOptions::new().population_names(vec!["A".to_string()]);
+        match export_to_tskit(&tables, &options) {
+            Err(ForrusttsError::TskitError { code, message: _ }) => {
+                assert_eq!(code, tskr::TSK_ERR_BAD_PARAM_VALUE)
+            }
+            _ => panic!("expected TskitError"),
+        }
+    }
+
+    #[test]
+    fn test_bad_sample_nodes() {
+        let tables = make_tables();
+        for samples in [vec![4], vec![-1]].iter() {
+            let options = TskitExportOptions::new().sample_nodes(samples.clone());
+            match export_to_tskit(&tables, &options) {
+                Err(ForrusttsError::TskitError { code, message: _ }) => {
+                    assert_eq!(code, tskr::TSK_ERR_BAD_PARAM_VALUE)
+                }
+                _ => panic!("expected TskitError"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_export_from() {
+        let tables = make_tables();
+        let options = TskitExportOptions::new().sample_nodes(vec![2, 3]);
+        let expected = export_to_tskit(&tables, &options).unwrap();
+        let tsk_tables = export_to_tskit_from(tables, &options).unwrap();
+        assert!(tsk_tables.equals(&expected, 0));
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("a"), "\"a\"");
+        assert_eq!(json_string("a\\b\n"), "\"a\\\\b\\n\"");
+        assert_eq!(json_string("\u{1}"), "\"\\u0001\"");
+    }
+}
@@ -0,0 +1,697 @@
+//! Write the ``tskit`` ``.trees`` file format without the ``tskit`` C library.
+//!
+//! A ``.trees`` file is a [kastore](https://github.com/tskit-dev/kastore)
+//! file. A kastore is a 64-byte header, followed by one 64-byte
+//! descriptor per item, followed by the item keys, followed
+//! by the item arrays. Items are sorted by key and each
+//! array starts on an 8-byte boundary.
+
+use super::{encode_mutation_metadata, MUTATION_METADATA_SCHEMA};
+use crate::tables_io::{TablesIOError, TablesIOResult};
+use crate::tsdef::{IdType, Time, NULL_ID};
+use crate::{TableCollection, TablesError};
+use std::io::Write;
+
+const KAS_MAGIC: [u8; 8] = *b"\x89KAS\r\n\x1a\n";
+const KAS_VERSION_MAJOR: u16 = 1;
+const KAS_VERSION_MINOR: u16 = 0;
+const KAS_HEADER_SIZE: u64 = 64;
+const KAS_ITEM_DESCRIPTOR_SIZE: u64 = 64;
+const KAS_ARRAY_ALIGN: u64 = 8;
+
+const KAS_INT8: u8 = 0;
+const KAS_UINT8: u8 = 1;
+const KAS_INT32: u8 = 4;
+const KAS_UINT32: u8 = 5;
+const KAS_FLOAT64: u8 = 9;
+
+const TSK_FILE_FORMAT_NAME: &[u8] = b"tskit.trees";
+const TSK_FILE_FORMAT_VERSION_MAJOR: u32 = 12;
+const TSK_FILE_FORMAT_VERSION_MINOR: u32 = 3;
+
+struct Item {
+    key: &'static str,
+    item_type: u8,
+    num_elements: u64,
+    data: Vec<u8>,
+}
+
+impl Item {
+    fn bytes(key: &'static str, item_type: u8, data: &[u8]) -> Self {
+        Self {
+            key,
+            item_type,
+            num_elements: data.len() as u64,
+            data: data.to_vec(),
+        }
+    }
+
+    fn int32(key: &'static str, values: impl Iterator<Item = i32>) -> Self {
+        let mut data = vec![];
+        values.for_each(|x| data.extend_from_slice(&x.to_le_bytes()));
+        Self {
+            key,
+            item_type: KAS_INT32,
+            num_elements: (data.len() / 4) as u64,
+            data,
+        }
+    }
+
+    fn uint32(key: &'static str, values: impl Iterator<Item = u32>) -> Self {
+        let mut data = vec![];
+        values.for_each(|x| data.extend_from_slice(&x.to_le_bytes()));
+        Self {
+            key,
+            item_type: KAS_UINT32,
+            num_elements: (data.len() / 4) as u64,
+            data,
+        }
+    }
+
+    fn float64(key: &'static str, values: impl Iterator<Item = f64>) -> Self {
+        let mut data = vec![];
+        values.for_each(|x| data.extend_from_slice(&x.to_le_bytes()));
+        Self {
+            key,
+            item_type: KAS_FLOAT64,
+            num_elements: (data.len() / 8) as u64,
+            data,
+        }
+    }
+
+    /// An empty ragged column, as needed for unused metadata.
+    fn empty_ragged(key: &'static str, offset_key: &'static str, num_rows: usize) -> [Self; 2] {
+        [
+            Item::bytes(key, KAS_UINT8, &[]),
+            Item::uint32(offset_key, vec![0; num_rows + 1].into_iter()),
+        ]
+    }
+
+    fn ragged<'a>(
+        table: &'static str,
+        key: &'static str,
+        offset_key: &'static str,
+        rows: impl Iterator<Item = &'a Option<Vec<u8>>>,
+    ) -> TablesIOResult<[Self; 2]> {
+        let mut data = vec![];
+        let mut offsets = vec![0_u32];
+        for row in rows {
+            if let Some(x) = row {
+                data.extend_from_slice(x);
+            }
+            if data.len() > u32::MAX as usize {
+                return Err(TablesIOError::TableTooLarge { table });
+            }
+            offsets.push(data.len() as u32);
+        }
+        Ok([
+            Item::bytes(key, KAS_UINT8, &data),
+            Item::uint32(offset_key, offsets.into_iter()),
+        ])
+    }
+}
+
+fn write_kastore<W: Write>(mut items: Vec<Item>, writer: &mut W) -> TablesIOResult<()> {
+    items.sort_by(|a, b| a.key.as_bytes().cmp(b.key.as_bytes()));
+
+    let mut offset = KAS_HEADER_SIZE + KAS_ITEM_DESCRIPTOR_SIZE * items.len() as u64;
+    let mut key_starts = vec![];
+    for item in &items {
+        key_starts.push(offset);
+        offset += item.key.len() as u64;
+    }
+    let mut array_starts = vec![];
+    for item in &items {
+        offset += (KAS_ARRAY_ALIGN - offset % KAS_ARRAY_ALIGN) % KAS_ARRAY_ALIGN;
+        array_starts.push(offset);
+        offset += item.data.len() as u64;
+    }
+    let file_size = offset;
+
+    let mut header = [0_u8; KAS_HEADER_SIZE as usize];
+    header[0..8].copy_from_slice(&KAS_MAGIC);
+    header[8..10].copy_from_slice(&KAS_VERSION_MAJOR.to_le_bytes());
+    header[10..12].copy_from_slice(&KAS_VERSION_MINOR.to_le_bytes());
+    header[12..16].copy_from_slice(&(items.len() as u32).to_le_bytes());
+    header[16..24].copy_from_slice(&file_size.to_le_bytes());
+    writer.write_all(&header)?;
+
+    for (i, item) in items.iter().enumerate() {
+        let mut descriptor = [0_u8; KAS_ITEM_DESCRIPTOR_SIZE as usize];
+        descriptor[0] = item.item_type;
+        descriptor[8..16].copy_from_slice(&key_starts[i].to_le_bytes());
+        descriptor[16..24].copy_from_slice(&(item.key.len() as u64).to_le_bytes());
+        descriptor[24..32].copy_from_slice(&array_starts[i].to_le_bytes());
+        descriptor[32..40].copy_from_slice(&item.num_elements.to_le_bytes());
+        writer.write_all(&descriptor)?;
+    }
+
+    let mut position = KAS_HEADER_SIZE + KAS_ITEM_DESCRIPTOR_SIZE * items.len() as u64;
+    for item in &items {
+        writer.write_all(item.key.as_bytes())?;
+        position += item.key.len() as u64;
+    }
+    for (i, item) in items.iter().enumerate() {
+        let padding = (array_starts[i] - position) as usize;
+        writer.write_all(&[0_u8; KAS_ARRAY_ALIGN as usize][..padding])?;
+        writer.write_all(&item.data)?;
+        position = array_starts[i] + item.data.len() as u64;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Generate a random (version 4) UUID string.
+fn make_uuid() -> Vec<u8> {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let mut bytes = vec![];
+    for _ in 0..2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(nanos);
+        bytes.extend_from_slice(&hasher.finish().to_le_bytes());
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let mut uuid = String::new();
+    for (i, b) in bytes.iter().enumerate() {
+        if i == 4 || i == 6 || i == 8 || i == 10 {
+            uuid.push('-');
+        }
+        uuid.push_str(&format!("{:02x}", b));
+    }
+    uuid.into_bytes()
+}
+
+fn make_indexes(tables: &TableCollection, node_times: &[f64]) -> [Item; 2] {
+    let edges = tables.edges();
+    let mut insertion: Vec<usize> = (0..edges.len()).collect();
+    insertion.sort_by(|&i, &j| {
+        let (a, b) = (&edges[i], &edges[j]);
+        a.left
+            .cmp(&b.left)
+            .then(
+                node_times[a.parent as usize]
+                    .partial_cmp(&node_times[b.parent as usize])
+                    .unwrap_or(std::cmp::Ordering::Equal),
+            )
+            .then(a.parent.cmp(&b.parent))
+            .then(a.child.cmp(&b.child))
+    });
+    let mut removal: Vec<usize> = (0..edges.len()).collect();
+    removal.sort_by(|&i, &j| {
+        let (a, b) = (&edges[i], &edges[j]);
+        a.right
+            .cmp(&b.right)
+            .then(
+                node_times[b.parent as usize]
+                    .partial_cmp(&node_times[a.parent as usize])
+                    .unwrap_or(std::cmp::Ordering::Equal),
+            )
+            .then(b.parent.cmp(&a.parent))
+            .then(b.child.cmp(&a.child))
+    });
+    [
+        Item::int32(
+            "indexes/edge_insertion_order",
+            insertion.into_iter().map(|i| i as IdType),
+        ),
+        Item::int32(
+            "indexes/edge_removal_order",
+            removal.into_iter().map(|i| i as IdType),
+        ),
+    ]
+}
+
+/// Write a [``TableCollection``](crate::TableCollection)
+/// in the ``tskit`` ``.trees`` format.
+///
+/// This function does not require the ``tskit`` C library.
+///
+/// # Parameters
+///
+/// * `tables`: A [``TableCollection``](crate::TableCollection)
+/// * `convert_time`: A callback to convert time, *e.g.* from forwards to backwards. For example, see [``simple_time_reverser``](crate::tskit::simple_time_reverser).
+/// * `build_indexes`: If `true`, write the edge table indexes.
+/// * `writer`: The output stream.
+///
+/// # Notes
+///
+/// If the input ``tables`` are not sorted, pass ``false`` for
+/// `build_indexes`.
+///
+/// Sites are written in order of increasing position and
+/// mutations are written in order of site and then
+/// decreasing (converted) time.
+/// The time of a mutation is the converted time of its node.
+/// Mutation parents are not computed.
+/// See the [module documentation](crate::tskit) for the
+/// format of mutation metadata.
+///
+/// The [``NodeFlags``](crate::NodeFlags) of each node are written
+/// as its ``tskit`` node flags.
+///
+/// One population is written for each deme, from
+/// `0` up to the largest deme in the node table.
+///
+/// # Errors
+///
+/// Will return [``TablesIOError``] if a table is too large for
+/// the format, or if writing fails.
+/// Will return [``TablesIOError::TablesError``] if a mutation refers
+/// to a site or node that does not exist.
+pub fn write_trees<W: Write>(
+    tables: &TableCollection,
+    convert_time: impl Fn(Time) -> f64,
+    build_indexes: bool,
+    writer: &mut W,
+) -> TablesIOResult<()> {
+    let node_times: Vec<f64> = tables
+        .nodes()
+        .iter()
+        .map(|n| convert_time(n.time))
+        .collect();
+
+    for m in tables.mutations() {
+        if m.site >= tables.sites().len() {
+            return Err(TablesError::InvalidSite { found: m.site }.into());
+        }
+        if m.node < 0 || m.node as usize >= node_times.len() {
+            return Err(TablesError::NodeOutOfBounds.into());
+        }
+    }
+
+    let mut site_order: Vec<usize> = (0..tables.sites().len()).collect();
+    site_order.sort_by_key(|&i| tables.sites()[i].position);
+    let mut site_map = vec![0; site_order.len()];
+    for (new, &old) in site_order.iter().enumerate() {
+        site_map[old] = new;
+    }
+    let mut mutation_order: Vec<usize> = (0..tables.mutations().len()).collect();
+    mutation_order.sort_by(|&i, &j| {
+        let (a, b) = (&tables.mutations()[i], &tables.mutations()[j]);
+        site_map[a.site].cmp(&site_map[b.site]).then(
+            node_times[b.node as usize]
+                .partial_cmp(&node_times[a.node as usize])
+                .unwrap_or(std::cmp::Ordering::Equal),
+        )
+    });
+    let sites: Vec<_> = site_order.iter().map(|&i| &tables.sites()[i]).collect();
+    let mutations: Vec<_> = mutation_order
+        .iter()
+        .map(|&i| &tables.mutations()[i])
+        .collect();
+
+    let num_populations = match tables.nodes().iter().map(|n| n.deme).max() {
+        Some(x) if x >= 0 => x as usize + 1,
+        _ => 0,
+    };
+
+    let version = [TSK_FILE_FORMAT_VERSION_MAJOR, TSK_FILE_FORMAT_VERSION_MINOR];
+    let mut items = vec![
+        Item::bytes("format/name", KAS_INT8, TSK_FILE_FORMAT_NAME),
+        Item::uint32("format/version", version.iter().cloned()),
+        Item::float64(
+            "sequence_length",
+            std::iter::once(tables.genome_length() as f64),
+        ),
+        Item::bytes("uuid", KAS_INT8, &make_uuid()),
+        Item::bytes("metadata", KAS_INT8, &[]),
+        Item::bytes("metadata_schema", KAS_INT8, &[]),
+    ];
+
+    items.push(Item::float64("nodes/time", node_times.iter().cloned()));
+    items.push(Item::uint32(
+        "nodes/flags",
+        tables.nodes().iter().map(|n| n.flags.bits()),
+    ));
+    items.push(Item::int32(
+        "nodes/population",
+        tables.nodes().iter().map(|n| n.deme),
+    ));
+    items.push(Item::int32(
+        "nodes/individual",
+        tables.nodes().iter().map(|_| NULL_ID),
+    ));
+    items.extend(Item::empty_ragged(
+        "nodes/metadata",
+        "nodes/metadata_offset",
+        tables.num_nodes(),
+    ));
+    items.push(Item::bytes("nodes/metadata_schema", KAS_UINT8, &[]));
+
+    items.push(Item::float64(
+        "edges/left",
+        tables.edges().iter().map(|e| e.left as f64),
+    ));
+    items.push(Item::float64(
+        "edges/right",
+        tables.edges().iter().map(|e| e.right as f64),
+    ));
+    items.push(Item::int32(
+        "edges/parent",
+        tables.edges().iter().map(|e| e.parent),
+    ));
+    items.push(Item::int32(
+        "edges/child",
+        tables.edges().iter().map(|e| e.child),
+    ));
+    items.push(Item::bytes("edges/metadata_schema", KAS_UINT8, &[]));
+
+    items.push(Item::float64(
+        "sites/position",
+        sites.iter().map(|s| s.position as f64),
+    ));
+    items.extend(Item::ragged(
+        "site",
+        "sites/ancestral_state",
+        "sites/ancestral_state_offset",
+        sites.iter().map(|s| &s.ancestral_state),
+    )?);
+    items.extend(Item::empty_ragged(
+        "sites/metadata",
+        "sites/metadata_offset",
+        sites.len(),
+    ));
+    items.push(Item::bytes("sites/metadata_schema", KAS_UINT8, &[]));
+
+    items.push(Item::int32(
+        "mutations/site",
+        mutations.iter().map(|m| site_map[m.site] as IdType),
+    ));
+    items.push(Item::int32(
+        "mutations/node",
+        mutations.iter().map(|m| m.node),
+    ));
+    items.push(Item::int32(
+        "mutations/parent",
+        mutations.iter().map(|_| NULL_ID),
+    ));
+    items.push(Item::float64(
+        "mutations/time",
+        mutations.iter().map(|m| node_times[m.node as usize]),
+    ));
+    items.extend(Item::ragged(
+        "mutation",
+        "mutations/derived_state",
+        "mutations/derived_state_offset",
+        mutations.iter().map(|m| &m.derived_state),
+    )?);
+    let mutation_metadata: Vec<_> = mutations
+        .iter()
+        .map(|m| Some(encode_mutation_metadata(m)))
+        .collect();
+    items.extend(Item::ragged(
+        "mutation",
+        "mutations/metadata",
+        "mutations/metadata_offset",
+        mutation_metadata.iter(),
+    )?);
+    items.push(Item::bytes(
+        "mutations/metadata_schema",
+        KAS_UINT8,
+        MUTATION_METADATA_SCHEMA.as_bytes(),
+    ));
+
+    for key in &["migrations/left", "migrations/right", "migrations/time"] {
+        items.push(Item::float64(key, std::iter::empty()));
+    }
+    for key in &["migrations/node", "migrations/source", "migrations/dest"] {
+        items.push(Item::int32(key, std::iter::empty()));
+    }
+    items.extend(Item::empty_ragged(
+        "migrations/metadata",
+        "migrations/metadata_offset",
+        0,
+    ));
+    items.push(Item::bytes("migrations/metadata_schema", KAS_UINT8, &[]));
+
+    items.push(Item::uint32("individuals/flags", std::iter::empty()));
+    items.push(Item::float64("individuals/location", std::iter::empty()));
+    items.push(Item::uint32(
+        "individuals/location_offset",
+        std::iter::once(0),
+    ));
+    items.extend(Item::empty_ragged(
+        "individuals/metadata",
+        "individuals/metadata_offset",
+        0,
+    ));
+    items.push(Item::bytes("individuals/metadata_schema", KAS_UINT8, &[]));
+
+    items.extend(Item::empty_ragged(
+        "populations/metadata",
+        "populations/metadata_offset",
+        num_populations,
+    ));
+    items.push(Item::bytes("populations/metadata_schema", KAS_UINT8, &[]));
+
+    items.extend(Item::empty_ragged(
+        "provenances/timestamp",
+        "provenances/timestamp_offset",
+        0,
+    ));
+    items.extend(Item::empty_ragged(
+        "provenances/record",
+        "provenances/record_offset",
+        0,
+    ));
+
+    if build_indexes {
+        items.extend(make_indexes(tables, &node_times));
+    }
+
+    write_kastore(items, writer)
+}
+
+/// Write a [``TableCollection``](crate::TableCollection)
+/// to a ``tskit`` ``.trees`` file.
+///
+/// See [``write_trees``] for details.
+///
+/// # Parameters
+///
+/// * `path`, the name of the output file. An existing file will be overwritten.
+///
+/// # Errors
+///
+/// See [``write_trees``].
+///
+/// # Example
+///
+/// ```
+/// let mut tables = forrustts::TableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap(); // Add a parent node at time 0
+/// // Add a child node at time 1 and mark it as a sample.
+/// tables
+///     .add_node_with_flags(1, 0, forrustts::NodeFlags::IS_SAMPLE)
+///     .unwrap();
+/// tables.add_edge(0, 100, 0, 1).unwrap(); // Add an edge
+/// let path = std::env::temp_dir().join("forrustts_write_trees_file_example.trees");
+/// forrustts::tskit::write_trees_file(
+///     &tables,
+///     forrustts::tskit::simple_time_reverser(1),
+///     true,
+///     &path,
+/// )
+/// .unwrap();
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub fn write_trees_file<P: AsRef<std::path::Path>>(
+    tables: &TableCollection,
+    convert_time: impl Fn(Time) -> f64,
+    build_indexes: bool,
+    path: P,
+) -> TablesIOResult<()> {
+    let file = std::fs::File::create(path)?;
+    let mut writer = std::io::BufWriter::new(file);
+    write_trees(tables, convert_time, build_indexes, &mut writer)
+}
+
+#[cfg(all(test, feature = "kastore"))]
+mod tests {
+    use super::*;
+    use crate::tskit::simple_time_reverser;
+    use crate::NodeFlags;
+
+    pub(super) fn make_tables() -> TableCollection {
+        let mut tables = TableCollection::new(100).unwrap();
+        tables.add_node(0, 0).unwrap();
+        tables.add_node(1, 1).unwrap();
+        tables
+            .add_node_with_flags(2, 0, NodeFlags::IS_SAMPLE | NodeFlags::IS_ALIVE)
+            .unwrap();
+        tables
+            .add_node_with_flags(2, 1, NodeFlags::IS_SAMPLE)
+            .unwrap();
+        tables.add_edge(0, 100, 0, 1).unwrap();
+        tables.add_edge(0, 50, 1, 2).unwrap();
+        tables.add_edge(50, 100, 0, 2).unwrap();
+        tables.add_edge(0, 100, 1, 3).unwrap();
+        tables.sort_tables(crate::TableSortingFlags::empty());
+        tables.add_site(75, Some(b"G".to_vec())).unwrap();
+        tables.add_site(10, None).unwrap();
+        tables
+            .add_mutation(2, 0, 1, Some(b"T".to_vec()), true)
+            .unwrap();
+        tables
+            .add_mutation(1, 1, 1, Some(b"A".to_vec()), false)
+            .unwrap();
+        tables.add_mutation(3, 2, 0, None, true).unwrap();
+        tables
+    }
+
+    #[test]
+    fn test_kastore_layout() {
+        let tables = make_tables();
+        let mut buffer = vec![];
+        write_trees(&tables, simple_time_reverser(2), true, &mut buffer).unwrap();
+        assert_eq!(&buffer[0..8], &KAS_MAGIC);
+        let file_size = u64::from_le_bytes([
+            buffer[16], buffer[17], buffer[18], buffer[19], buffer[20], buffer[21], buffer[22],
+            buffer[23],
+        ]);
+        assert_eq!(file_size, buffer.len() as u64);
+    }
+
+    #[test]
+    fn test_invalid_mutations() {
+        let mut tables = make_tables();
+        tables.add_mutation(2, 0, 3, None, true).unwrap();
+        let mut buffer = vec![];
+        match write_trees(&tables, simple_time_reverser(2), true, &mut buffer) {
+            Err(TablesIOError::TablesError { value }) => {
+                assert_eq!(value, TablesError::InvalidSite { found: 3 })
+            }
+            _ => panic!("expected InvalidSite"),
+        }
+
+        let mut tables = make_tables();
+        tables.add_mutation(4, 0, 0, None, true).unwrap();
+        match write_trees(&tables, simple_time_reverser(2), true, &mut buffer) {
+            Err(TablesIOError::TablesError { value }) => {
+                assert_eq!(value, TablesError::NodeOutOfBounds)
+            }
+            _ => panic!("expected NodeOutOfBounds"),
+        }
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_uuid() {
+        let a = make_uuid();
+        let b = make_uuid();
+        assert_eq!(a.len(), 36);
+        assert_ne!(a, b);
+        assert_eq!(a[14], b'4');
+    }
+}
+
+#[cfg(all(test, feature = "tskit_rust"))]
+mod test_tskit {
+    use super::tests::make_tables;
+    use super::*;
+    use crate::tskit::{export_to_tskit, simple_time_reverser, TskitExportOptions};
+    use crate::NodeFlags;
+    use tskit_rust::bindings as tskr;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("forrustts_{}_{}.trees", name, std::process::id()))
+    }
+
+    fn write_and_read(
+        tables: &TableCollection,
+        max_time: Time,
+        name: &str,
+    ) -> tskit_rust::TableCollection {
+        let path = temp_path(name);
+        write_trees_file(tables, simple_time_reverser(max_time), true, &path).unwrap();
+        let rv = tskit_rust::TableCollection::new_from_file(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        rv
+    }
+
+    fn index_of(tables: &tskit_rust::TableCollection) -> (Vec<IdType>, Vec<IdType>) {
+        let indexes = unsafe { (*tables.as_ptr()).indexes };
+        let n = indexes.num_edges as usize;
+        assert!(!indexes.edge_insertion_order.is_null());
+        let insertion = unsafe { std::slice::from_raw_parts(indexes.edge_insertion_order, n) };
+        let removal = unsafe { std::slice::from_raw_parts(indexes.edge_removal_order, n) };
+        (insertion.to_vec(), removal.to_vec())
+    }
+
+    #[test]
+    fn test_read_back_with_tskit() {
+        let tables = make_tables();
+        let tsk_tables = write_and_read(&tables, 2, "read_back");
+        assert_eq!(
+            tsk_tables.nodes().flags(2).unwrap(),
+            (NodeFlags::IS_SAMPLE | NodeFlags::IS_ALIVE).bits()
+        );
+
+        // Mutation parents are not computed when indexes are not built.
+        let options = TskitExportOptions::new()
+            .convert_time(simple_time_reverser(2))
+            .build_indexes(false);
+        let mut expected = export_to_tskit(&tables, &options).unwrap();
+        expected.build_index(0).unwrap();
+        assert_eq!(expected.mutations().num_rows(), 3);
+
+        assert_eq!(tsk_tables.sequence_length(), 100.);
+        assert!(tsk_tables.equals(&expected, 0));
+        assert_eq!(index_of(&tsk_tables), index_of(&expected));
+
+        let rv = unsafe {
+            tskr::tsk_table_collection_check_integrity(tsk_tables.as_ptr(), tskr::TSK_CHECK_TREES)
+        };
+        assert!(rv >= 0);
+    }
+
+    #[test]
+    fn test_simulated_tables() {
+        use crate::wright_fisher::*;
+        let nsteps = 200;
+        let tables = neutral_wf(
+            PopulationParams::new(100, 100000, 5e-3, 0.0),
+            SimulationParams::new(Some(10), 101, nsteps, SimulationFlags::empty()),
+        )
+        .unwrap();
+        let tsk_tables = write_and_read(&tables, nsteps, "simulated");
+        let options = TskitExportOptions::new().convert_time(simple_time_reverser(nsteps));
+        let expected = export_to_tskit(&tables, &options).unwrap();
+        assert!(tsk_tables.equals(&expected, 0));
+        assert_eq!(index_of(&tsk_tables), index_of(&expected));
+    }
+
+    #[test]
+    fn test_no_indexes() {
+        let tables = make_tables();
+        let path = temp_path("no_indexes");
+        write_trees_file(&tables, simple_time_reverser(2), false, &path).unwrap();
+        let tsk_tables =
+            tskit_rust::TableCollection::new_from_file(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(unsafe {
+            (*tsk_tables.as_ptr())
+                .indexes
+                .edge_insertion_order
+                .is_null()
+        });
+        assert_eq!(tsk_tables.edges().num_rows(), 4);
+    }
+
+    #[test]
+    fn test_empty_tables() {
+        let tables = TableCollection::new(10).unwrap();
+        let tsk_tables = write_and_read(&tables, 0, "empty");
+        assert_eq!(tsk_tables.nodes().num_rows(), 0);
+        assert_eq!(tsk_tables.populations().num_rows(), 0);
+        assert_eq!(tsk_tables.sequence_length(), 10.);
+    }
+}
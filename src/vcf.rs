@@ -0,0 +1,430 @@
+//! Output of variants in
+//! [VCF](https://samtools.github.io/hts-specs/VCFv4.2.pdf) format.
+//!
+//! Sample nodes are grouped into individuals, and each
+//! individual is a sample column in the output.
+//! Genotypes are phased.
+//!
+//! By default, consecutive pairs of sample nodes (see
+//! [``TableCollection::sample_nodes``]) are diploid individuals.
+//! This matches the nodes of each individual alive at the end
+//! of a simulation with [``wright_fisher``](crate::wright_fisher).
+//!
+//! Alleles are obtained with [``VariantIterator``].
+//! The allelic states must be valid UTF-8 and must not contain
+//! whitespace or commas.
+
+use crate::stats::{StatsError, VariantIterator};
+use crate::tables::TableCollection;
+use crate::tsdef::{IdType, Position};
+use std::io::Write;
+use thiserror::Error;
+
+/// Error type for VCF output.
+#[derive(Error, Debug)]
+pub enum VcfError {
+    /// A redirection of a [``std::io::Error``].
+    #[error("{value:?}")]
+    IOError {
+        /// The redirected error
+        #[from]
+        value: std::io::Error,
+    },
+    /// A redirection of a [``StatsError``].
+    #[error("{value:?}")]
+    StatsError {
+        /// The redirected error
+        #[from]
+        value: StatsError,
+    },
+    /// Raised when there is an odd number of sample
+    /// nodes to group into diploid individuals.
+    #[error("Cannot group {found:?} sample nodes into diploids")]
+    OddNumberOfSamples {
+        /// The number of sample nodes
+        found: usize,
+    },
+    /// Raised when an individual has no nodes, or
+    /// when the number of names does not match the
+    /// number of individuals.
+    #[error("Invalid individuals")]
+    InvalidIndividuals,
+    /// Raised when a position is less than one
+    /// after adding the offset.
+    #[error("Invalid VCF position: {found:?}")]
+    InvalidPosition {
+        /// The position, including the offset
+        found: Position,
+    },
+    /// Raised when an allelic state cannot be written.
+    #[error("Invalid allele at site {site:?}")]
+    InvalidAllele {
+        /// The index of the site
+        site: usize,
+    },
+}
+
+/// Result type for VCF output.
+pub type VcfResult<T> = Result<T, VcfError>;
+
+/// Options for [``write_vcf``].
+///
+/// This type is a builder: each method consumes and returns
+/// the options.
+///
+/// # Defaults
+///
+/// * The contig name is `1`.
+/// * The position offset is `0`.
+///   Because VCF positions start at one, a site at position `0`
+///   is an error unless an offset is added.
+/// * Individuals are consecutive pairs of sample nodes.
+/// * Individuals are named `ind_0`, `ind_1`, *etc.*.
+/// * Missing states are `0` (ancestral) and `1` (derived).
+///
+/// # Example
+///
+/// ```
+/// use forrustts::vcf::VcfOptions;
+///
+/// let options = VcfOptions::new()
+///     .contig("chr2L")
+///     .position_offset(1)
+///     .individuals(vec![vec![0, 1], vec![2, 3]])
+///     .individual_names(vec!["a".to_string(), "b".to_string()])
+///     .default_states(b"A", b"T");
+/// ```
+pub struct VcfOptions {
+    contig: String,
+    position_offset: Position,
+    individuals: Option<Vec<Vec<IdType>>>,
+    individual_names: Option<Vec<String>>,
+    default_states: (Vec<u8>, Vec<u8>),
+}
+
+impl Default for VcfOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VcfOptions {
+    /// Create options with default values.
+    pub fn new() -> Self {
+        VcfOptions {
+            contig: "1".to_string(),
+            position_offset: 0,
+            individuals: None,
+            individual_names: None,
+            default_states: (b"0".to_vec(), b"1".to_vec()),
+        }
+    }
+
+    /// Set the contig (``CHROM``) name.
+    pub fn contig(mut self, contig: &str) -> Self {
+        self.contig = contig.to_string();
+        self
+    }
+
+    /// Set the value added to each site's position.
+    pub fn position_offset(mut self, offset: Position) -> Self {
+        self.position_offset = offset;
+        self
+    }
+
+    /// Set the nodes of each individual.
+    ///
+    /// Individual `i` has the nodes `individuals[i]`,
+    /// and its ploidy is the number of nodes.
+    pub fn individuals(mut self, individuals: Vec<Vec<IdType>>) -> Self {
+        self.individuals = Some(individuals);
+        self
+    }
+
+    /// Name the individuals.
+    ///
+    /// There must be one name per individual.
+    pub fn individual_names(mut self, names: Vec<String>) -> Self {
+        self.individual_names = Some(names);
+        self
+    }
+
+    /// Set the states written when a site's ancestral state or
+    /// a mutation's derived state is [``None``].
+    pub fn default_states(mut self, ancestral: &[u8], derived: &[u8]) -> Self {
+        self.default_states = (ancestral.to_vec(), derived.to_vec());
+        self
+    }
+}
+
+/// Group sample nodes into diploid individuals.
+///
+/// Individual `i` has nodes `samples[2 * i]` and `samples[2 * i + 1]`.
+///
+/// # Errors
+///
+/// [``VcfError::OddNumberOfSamples``] if `samples` has an odd length.
+///
+/// # Example
+///
+/// ```
+/// let individuals = forrustts::vcf::diploid_individuals(&[0, 1, 4, 5]).unwrap();
+/// assert_eq!(individuals, vec![vec![0, 1], vec![4, 5]]);
+/// ```
+pub fn diploid_individuals(samples: &[IdType]) -> VcfResult<Vec<Vec<IdType>>> {
+    if !samples.len().is_multiple_of(2) {
+        return Err(VcfError::OddNumberOfSamples {
+            found: samples.len(),
+        });
+    }
+    Ok(samples.chunks(2).map(|c| c.to_vec()).collect())
+}
+
+fn allele_string(allele: &[u8], site: usize) -> VcfResult<&str> {
+    match std::str::from_utf8(allele) {
+        Ok(s) if !s.is_empty() && !s.contains(|c: char| c.is_whitespace() || c == ',') => Ok(s),
+        _ => Err(VcfError::InvalidAllele { site }),
+    }
+}
+
+/// Write the variants of a [``TableCollection``] in VCF 4.2 format.
+///
+/// # Parameters
+///
+/// * `tables`: the tables
+/// * `options`: see [``VcfOptions``]
+/// * `writer`: the output
+///
+/// # Errors
+///
+/// [``VcfError``] if the individuals, positions, or alleles are
+/// invalid, or if writing fails.
+///
+/// # Example
+///
+/// ```
+/// use forrustts::vcf::{write_vcf, VcfOptions};
+/// use forrustts::{NodeFlags, TableCollection};
+///
+/// let mut tables = TableCollection::new(100).unwrap();
+/// tables.add_node(0, 0).unwrap();
+/// for _ in 0..4 {
+///     tables
+///         .add_node_with_flags(10, 0, NodeFlags::IS_SAMPLE)
+///         .unwrap();
+/// }
+/// for c in 1..5 {
+///     tables.add_edge(0, 100, 0, c).unwrap();
+/// }
+/// tables.add_site(50, Some(b"A".to_vec())).unwrap();
+/// tables.add_mutation(2, 0, 0, Some(b"G".to_vec()), true).unwrap();
+///
+/// let mut output = vec![];
+/// write_vcf(&tables, &VcfOptions::new(), &mut output).unwrap();
+/// let output = String::from_utf8(output).unwrap();
+/// let last = output.lines().last().unwrap();
+/// assert_eq!(last, "1\t50\t0\tA\tG\t.\tPASS\t.\tGT\t0|1\t0|0");
+/// ```
+pub fn write_vcf<W: Write>(
+    tables: &TableCollection,
+    options: &VcfOptions,
+    writer: &mut W,
+) -> VcfResult<()> {
+    let individuals = match &options.individuals {
+        Some(i) => i.clone(),
+        None => diploid_individuals(&tables.sample_nodes())?,
+    };
+    if individuals.iter().any(|i| i.is_empty()) {
+        return Err(VcfError::InvalidIndividuals);
+    }
+    let names = match &options.individual_names {
+        Some(names) => {
+            if names.len() != individuals.len() {
+                return Err(VcfError::InvalidIndividuals);
+            }
+            names.clone()
+        }
+        None => (0..individuals.len())
+            .map(|i| format!("ind_{}", i))
+            .collect(),
+    };
+    let samples: Vec<IdType> = individuals.iter().flatten().cloned().collect();
+    let variants = VariantIterator::new(tables, &samples)?
+        .with_default_states(&options.default_states.0, &options.default_states.1);
+
+    writeln!(writer, "##fileformat=VCFv4.2")?;
+    writeln!(writer, "##source=forrustts {}", crate::version())?;
+    writeln!(
+        writer,
+        "##FILTER=<ID=PASS,Description=\"All filters passed\">"
+    )?;
+    writeln!(
+        writer,
+        "##contig=<ID={},length={}>",
+        options.contig,
+        tables.genome_length() + options.position_offset
+    )?;
+    writeln!(
+        writer,
+        "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">"
+    )?;
+    write!(
+        writer,
+        "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT"
+    )?;
+    for name in &names {
+        write!(writer, "\t{}", name)?;
+    }
+    writeln!(writer)?;
+
+    for variant in variants {
+        let site = variant.site();
+        let position = variant.position() + options.position_offset;
+        if position < 1 {
+            return Err(VcfError::InvalidPosition { found: position });
+        }
+        let alleles = variant
+            .alleles()
+            .iter()
+            .map(|a| allele_string(a, site))
+            .collect::<VcfResult<Vec<&str>>>()?;
+        let alt = if alleles.len() > 1 {
+            alleles[1..].join(",")
+        } else {
+            ".".to_string()
+        };
+        write!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t.\tPASS\t.\tGT",
+            options.contig, position, site, alleles[0], alt
+        )?;
+        let mut genotypes = variant.genotypes().iter();
+        for individual in &individuals {
+            write!(writer, "\t")?;
+            for i in 0..individual.len() {
+                if i > 0 {
+                    write!(writer, "|")?;
+                }
+                write!(writer, "{}", genotypes.next().unwrap())?;
+            }
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::balanced_tree;
+    use crate::NodeFlags;
+
+    // The tree of [`balanced_tree`], with samples 3-6 forming
+    // two diploid individuals.
+    // Site 0 has two derived states, site 1 has a missing
+    // ancestral state, and site 2 has no mutations.
+    fn make_tables() -> TableCollection {
+        let mut tables = balanced_tree(None);
+        tables.add_site(70, Some(b"A".to_vec())).unwrap();
+        tables
+            .add_mutation(1, 0, 0, Some(b"C".to_vec()), true)
+            .unwrap();
+        tables
+            .add_mutation(6, 0, 0, Some(b"G".to_vec()), true)
+            .unwrap();
+        tables.add_site(20, None).unwrap();
+        tables.add_mutation(5, 0, 1, None, true).unwrap();
+        tables.add_site(90, Some(b"T".to_vec())).unwrap();
+        tables
+    }
+
+    fn write(tables: &TableCollection, options: &VcfOptions) -> VcfResult<String> {
+        let mut output = vec![];
+        write_vcf(tables, options, &mut output)?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_default_options() {
+        let tables = make_tables();
+        let output = write(&tables, &VcfOptions::new()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "##fileformat=VCFv4.2");
+        assert!(lines.contains(&"##contig=<ID=1,length=100>"));
+        let header = lines.iter().position(|l| l.starts_with("#CHROM")).unwrap();
+        assert_eq!(
+            lines[header],
+            "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tind_0\tind_1"
+        );
+        assert_eq!(
+            &lines[header + 1..],
+            &[
+                "1\t20\t1\t0\t1\t.\tPASS\t.\tGT\t0|0\t1|0",
+                "1\t70\t0\tA\tC,G\t.\tPASS\t.\tGT\t1|1\t0|2",
+                "1\t90\t2\tT\t.\t.\tPASS\t.\tGT\t0|0\t0|0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_options() {
+        let tables = make_tables();
+        let options = VcfOptions::new()
+            .contig("chrX")
+            .position_offset(1000)
+            .individuals(vec![vec![6, 3, 4], vec![5]])
+            .individual_names(vec!["triploid".to_string(), "haploid".to_string()])
+            .default_states(b"N", b"X");
+        let output = write(&tables, &options).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines.contains(&"##contig=<ID=chrX,length=1100>"));
+        let header = lines.iter().position(|l| l.starts_with("#CHROM")).unwrap();
+        assert!(lines[header].ends_with("FORMAT\ttriploid\thaploid"));
+        assert_eq!(
+            lines[header + 1],
+            "chrX\t1020\t1\tN\tX\t.\tPASS\t.\tGT\t0|0|0\t1"
+        );
+        assert_eq!(
+            lines[header + 2],
+            "chrX\t1070\t0\tA\tC,G\t.\tPASS\t.\tGT\t2|1|1\t0"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut tables = make_tables();
+        let options = VcfOptions::new().individuals(vec![vec![2, 3], vec![]]);
+        assert!(matches!(
+            write(&tables, &options),
+            Err(VcfError::InvalidIndividuals)
+        ));
+        let options = VcfOptions::new().individual_names(vec!["a".to_string()]);
+        assert!(matches!(
+            write(&tables, &options),
+            Err(VcfError::InvalidIndividuals)
+        ));
+        let options = VcfOptions::new().individuals(vec![vec![2, 2]]);
+        assert!(matches!(
+            write(&tables, &options),
+            Err(VcfError::StatsError { .. })
+        ));
+        let options = VcfOptions::new().position_offset(-20);
+        assert!(matches!(
+            write(&tables, &options),
+            Err(VcfError::InvalidPosition { found: 0 })
+        ));
+        let options = VcfOptions::new().default_states(b"0", b"a b");
+        assert!(matches!(
+            write(&tables, &options),
+            Err(VcfError::InvalidAllele { site: 1 })
+        ));
+
+        tables
+            .add_node_with_flags(10, 0, NodeFlags::IS_SAMPLE)
+            .unwrap();
+        assert!(matches!(
+            write(&tables, &VcfOptions::new()),
+            Err(VcfError::OddNumberOfSamples { found: 5 })
+        ));
+    }
+}
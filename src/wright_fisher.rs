@@ -11,6 +11,7 @@ use crate::tables::TableCollection;
 use crate::tsdef::*;
 use crate::EdgeBuffer;
 use crate::ForrusttsError;
+use crate::NodeFlags;
 use crate::SamplesInfo;
 use crate::Segment;
 use crate::SimplificationBuffers;
@@ -371,6 +372,7 @@ fn validate_simplification_interval(x: Time) -> Time {
 
 /// Parameters of a population to be evolved by
 /// [``neutral_wf``].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PopulationParams {
     /// Diploid population size
     pub size: u32,
@@ -403,6 +405,7 @@ bitflags! {
     /// Bitwise flag tweaking the behavior of the
     /// simplification algorithm.
     #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct SimulationFlags: u32
     {
         /// If set, and [``BUFFER_EDGES``] is not set,
@@ -420,6 +423,7 @@ bitflags! {
 
 /// Parameters of a simulation to be executed
 /// by [``neutral_wf``].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SimulationParams {
     /// How often to apply the simplification algorithm.
     /// If ``None``, then simplification never happens.
@@ -473,11 +477,10 @@ impl SimulationParams {
 ///
 /// # Return values
 ///
-/// The return value is a tuple containing a [``TableCollection``]
-/// and a vector.  The length of the vector is equal to the
-/// length of the [``crate::NodeTable``] in the return value. The vector
-/// contains 1 if the node at that index is alive at the end of
-/// the simlation and 0 otherwise.
+/// The return value is a [``TableCollection``].
+/// The nodes alive at the end of the simulation have
+/// [``crate::NodeFlags::IS_SAMPLE``] and [``crate::NodeFlags::IS_ALIVE``]
+/// set.  All other nodes have empty flags.
 ///
 /// # Error
 ///
@@ -491,7 +494,7 @@ impl SimulationParams {
 pub fn neutral_wf(
     pop_params: PopulationParams,
     params: SimulationParams,
-) -> Result<(TableCollection, Vec<i32>), ForrusttsError> {
+) -> Result<TableCollection, ForrusttsError> {
     // FIXME: gotta validate input params!
 
     let mut actual_simplification_interval: Time = -1;
@@ -574,14 +577,14 @@ pub fn neutral_wf(
         );
     }
 
-    let mut is_alive: Vec<i32> = vec![0; pop.tables.num_nodes()];
-
     for p in pop.parents {
-        is_alive[p.node0 as usize] = 1;
-        is_alive[p.node1 as usize] = 1;
+        for node in [p.node0, p.node1].iter() {
+            pop.tables
+                .set_node_flags(*node, NodeFlags::IS_SAMPLE | NodeFlags::IS_ALIVE)?;
+        }
     }
 
-    Ok((pop.tables, is_alive))
+    Ok(pop.tables)
 }
 
 #[cfg(test)]
@@ -620,7 +623,7 @@ mod test {
         assert!(b == vec![2, 3, 5]);
     }
 
-    fn run_sim(use_state: bool) -> (TableCollection, Vec<i32>) {
+    fn run_sim(use_state: bool) -> TableCollection {
         let flags = if use_state {
             SimulationFlags::USE_STATE
         } else {
@@ -640,21 +643,62 @@ mod test {
 
     #[test]
     fn compare_state_to_no_state() {
-        let (tables, _) = run_sim(false);
-        let (tables_state, _) = run_sim(true);
+        let tables = run_sim(false);
+        let tables_state = run_sim(true);
 
-        assert_eq!(tables.num_nodes(), tables_state.num_nodes());
-        assert_eq!(tables.num_edges(), tables_state.num_edges());
+        let diff = tables.diff(&tables_state);
+        assert!(diff.is_empty(), "{:?}", diff);
+        assert!(tables == tables_state);
+    }
 
-        for (i, j) in tables.nodes_.iter().zip(tables_state.nodes_) {
-            assert_eq!(i.time, j.time);
-            assert_eq!(i.deme, j.deme);
-        }
-        for (i, j) in tables.edges_.iter().zip(tables_state.edges_) {
-            assert_eq!(i.left, j.left);
-            assert_eq!(i.right, j.right);
-            assert_eq!(i.parent, j.parent);
-            assert_eq!(i.child, j.child);
+    #[test]
+    fn test_alive_nodes_are_samples() {
+        let tables = neutral_wf(
+            PopulationParams::new(100, 1000, 5e-3, 0.0),
+            SimulationParams::new(Some(10), 42, 50, SimulationFlags::empty()),
+        )
+        .unwrap();
+        let samples = tables.sample_nodes();
+        assert_eq!(samples.len(), 200);
+        for (i, n) in tables.enumerate_nodes() {
+            if n.time == 50 {
+                assert_eq!(n.flags, NodeFlags::IS_SAMPLE | NodeFlags::IS_ALIVE);
+                assert!(samples.contains(&(i as IdType)));
+            } else {
+                assert!(n.flags.is_empty());
+            }
         }
     }
 }
+
+#[cfg(all(test, feature = "serde"))]
+mod test_serde {
+    use super::*;
+
+    #[test]
+    fn test_params_round_trip() {
+        let pop_params = PopulationParams::new(100, 1000, 1e-3, 0.5);
+        let mut params = SimulationParams::new(Some(10), 42, 100, SimulationFlags::BUFFER_EDGES);
+        params.simplification_flags = SimplificationFlags::VALIDATE_ALL;
+
+        let json = serde_json::to_string(&pop_params).unwrap();
+        let p: PopulationParams = serde_json::from_str(&json).unwrap();
+        assert_eq!(p.size, 100);
+        assert_eq!(p.genome_length, 1000);
+        assert!((p.littler - 1e-3).abs() < f64::EPSILON);
+        assert!((p.psurvival - 0.5).abs() < f64::EPSILON);
+
+        let encoded = bincode::serialize(&params).unwrap();
+        let p: SimulationParams = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(p.simplification_interval, Some(10));
+        assert_eq!(p.seed, 42);
+        assert_eq!(p.nsteps, 100);
+        assert_eq!(p.flags, SimulationFlags::BUFFER_EDGES);
+        assert_eq!(p.simplification_flags, SimplificationFlags::VALIDATE_ALL);
+
+        let json = serde_json::to_string(&params).unwrap();
+        let p: SimulationParams = serde_json::from_str(&json).unwrap();
+        assert_eq!(p.flags, SimulationFlags::BUFFER_EDGES);
+        assert_eq!(p.simplification_interval, Some(10));
+    }
+}